@@ -0,0 +1,109 @@
+//! Node.js bindings of craftping, built with napi-rs.
+//!
+//! ```js
+//! const { ping } = require('craftping');
+//!
+//! const response = await ping('my.server.com', 25565, { timeout: 5000 });
+//! console.log(`${response.onlinePlayers} of ${response.maxPlayers} player(s) online`);
+//! ```
+use std::time::Duration;
+
+use craftping::pinger::Pinger;
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+
+const DEFAULT_PORT: u16 = 25565;
+
+#[napi(object)]
+/// The options of [`ping`](ping).
+pub struct PingOptions {
+    /// The timeout of connecting and each read and write, in milliseconds. No timeout by default.
+    pub timeout: Option<u32>,
+}
+
+#[napi(object)]
+/// A player in the sample of the response.
+pub struct Player {
+    /// The name of the player.
+    pub name: String,
+    /// The uuid of the player.
+    pub id: String,
+}
+
+#[napi(object)]
+/// A ping response returned from the server.
+pub struct Response {
+    /// The version name of the server.
+    pub version: String,
+    /// The protocol number of the server.
+    pub protocol: i32,
+    /// If the server requires the user to sign chat messages with their private key.
+    pub enforces_secure_chat: Option<bool>,
+    /// The maximum number of the connected players.
+    pub max_players: u32,
+    /// The number of the players currently connected.
+    pub online_players: u32,
+    /// The sample of the connected players.
+    pub sample: Option<Vec<Player>>,
+    /// The description (aka MOTD) of the server as a plain text, without any styles.
+    pub motd: String,
+    /// The description of the server as a chat component object.
+    pub description: serde_json::Value,
+    /// The favicon of the server in PNG format.
+    pub favicon: Option<Buffer>,
+    /// The fields of the response which are not recognized by craftping.
+    pub extras: serde_json::Value,
+}
+
+impl From<craftping::Response> for Response {
+    fn from(response: craftping::Response) -> Self {
+        Self {
+            motd: response.description.plain_text(),
+            description: serde_json::to_value(&response.description).unwrap_or_default(),
+            version: response.version,
+            protocol: response.protocol,
+            enforces_secure_chat: response.enforces_secure_chat,
+            max_players: saturate(response.max_players),
+            online_players: saturate(response.online_players),
+            sample: response.sample.map(|sample| {
+                sample
+                    .into_iter()
+                    .map(|player| Player {
+                        name: player.name,
+                        id: player.id,
+                    })
+                    .collect()
+            }),
+            favicon: response.favicon.map(Buffer::from),
+            extras: response.extras.into(),
+        }
+    }
+}
+
+// the player counts are numbers in JS, which cannot hold every usize
+fn saturate(count: usize) -> u32 {
+    count.try_into().unwrap_or(u32::MAX)
+}
+
+#[napi]
+/// Connect to the server and ping it, falling back to the legacy protocol for the old servers.
+///
+/// The port defaults to 25565. Rejects with the error message if the server cannot be pinged.
+pub async fn ping(
+    host: String,
+    port: Option<u16>,
+    opts: Option<PingOptions>,
+) -> napi::Result<Response> {
+    let mut pinger = Pinger::new(host, port.unwrap_or(DEFAULT_PORT));
+    if let Some(timeout) = opts.and_then(|opts| opts.timeout) {
+        let timeout = Duration::from_millis(timeout.into());
+        pinger = pinger
+            .connect_timeout(timeout)
+            .read_timeout(timeout)
+            .write_timeout(timeout);
+    }
+    match pinger.ping_tokio().await {
+        Ok(response) => Ok(response.into()),
+        Err(error) => Err(napi::Error::from_reason(error.to_string())),
+    }
+}
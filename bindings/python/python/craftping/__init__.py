@@ -0,0 +1,21 @@
+"""Minecraft Server List Ping Implementation, backed by the craftping crate."""
+import asyncio
+import functools
+from typing import Optional
+
+from ._craftping import PingError, Player, Response, ping
+
+__all__ = ["PingError", "Player", "Response", "ping", "ping_async"]
+
+
+async def ping_async(
+    host: str, port: int = 25565, timeout: Optional[float] = None
+) -> Response:
+    """Ping the server like `ping`, without blocking the event loop.
+
+    The ping runs in the default executor of the running loop.
+    """
+    loop = asyncio.get_running_loop()
+    return await loop.run_in_executor(
+        None, functools.partial(ping, host, port, timeout)
+    )
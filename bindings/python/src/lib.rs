@@ -0,0 +1,165 @@
+//! Python bindings of craftping, built with PyO3.
+//!
+//! The native module is `craftping._craftping`, re-exported by the `craftping` package
+//! together with the asyncio-compatible `ping_async`.
+use std::time::Duration;
+
+use craftping::pinger::Pinger;
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+
+create_exception!(
+    craftping,
+    PingError,
+    PyException,
+    "Raised when the server cannot be pinged."
+);
+
+#[pyclass(module = "craftping", frozen, get_all, skip_from_py_object)]
+#[derive(Clone)]
+/// A player in the sample of the response.
+struct Player {
+    /// The name of the player.
+    name: String,
+    /// The uuid of the player.
+    id: String,
+}
+
+#[pymethods]
+impl Player {
+    fn __repr__(&self) -> String {
+        format!("Player(name={:?}, id={:?})", self.name, self.id)
+    }
+}
+
+#[pyclass(module = "craftping", frozen)]
+/// A ping response returned from the server.
+struct Response {
+    /// The version name of the server.
+    #[pyo3(get)]
+    version: String,
+    /// The protocol number of the server.
+    #[pyo3(get)]
+    protocol: i32,
+    /// If the server requires the user to sign chat messages with their private key.
+    #[pyo3(get)]
+    enforces_secure_chat: Option<bool>,
+    /// The maximum number of the connected players.
+    #[pyo3(get)]
+    max_players: usize,
+    /// The number of the players currently connected.
+    #[pyo3(get)]
+    online_players: usize,
+    /// The sample of the connected players.
+    #[pyo3(get)]
+    sample: Option<Vec<Player>>,
+    /// The description (aka MOTD) of the server as a plain text, without any styles.
+    #[pyo3(get)]
+    motd: String,
+    favicon: Option<Vec<u8>>,
+}
+
+#[pymethods]
+impl Response {
+    /// The favicon of the server in PNG format.
+    #[getter]
+    fn favicon<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyBytes>> {
+        self.favicon
+            .as_deref()
+            .map(|favicon| PyBytes::new(py, favicon))
+    }
+
+    /// Convert the response into a dict, e.g. to serialize it as JSON.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("version", &self.version)?;
+        dict.set_item("protocol", self.protocol)?;
+        dict.set_item("enforces_secure_chat", self.enforces_secure_chat)?;
+        dict.set_item("max_players", self.max_players)?;
+        dict.set_item("online_players", self.online_players)?;
+        let sample = match &self.sample {
+            Some(sample) => {
+                let players = sample
+                    .iter()
+                    .map(|player| {
+                        let entry = PyDict::new(py);
+                        entry.set_item("name", &player.name)?;
+                        entry.set_item("id", &player.id)?;
+                        Ok(entry)
+                    })
+                    .collect::<PyResult<Vec<_>>>()?;
+                Some(players)
+            }
+            None => None,
+        };
+        dict.set_item("sample", sample)?;
+        dict.set_item("motd", &self.motd)?;
+        dict.set_item("favicon", self.favicon(py))?;
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Response(version={:?}, protocol={}, online_players={}, max_players={})",
+            self.version, self.protocol, self.online_players, self.max_players
+        )
+    }
+}
+
+impl From<craftping::Response> for Response {
+    fn from(response: craftping::Response) -> Self {
+        Self {
+            motd: response.description.plain_text(),
+            version: response.version,
+            protocol: response.protocol,
+            enforces_secure_chat: response.enforces_secure_chat,
+            max_players: response.max_players,
+            online_players: response.online_players,
+            sample: response.sample.map(|sample| {
+                sample
+                    .into_iter()
+                    .map(|player| Player {
+                        name: player.name,
+                        id: player.id,
+                    })
+                    .collect()
+            }),
+            favicon: response.favicon,
+        }
+    }
+}
+
+/// Connect to the server and ping it, falling back to the legacy protocol for the old servers.
+///
+/// The timeout in seconds applies to connecting and each read and write.
+/// The GIL is released while waiting for the server.
+#[pyfunction]
+#[pyo3(signature = (host, port = 25565, timeout = None))]
+fn ping(py: Python<'_>, host: String, port: u16, timeout: Option<f64>) -> PyResult<Response> {
+    let mut pinger = Pinger::new(host, port);
+    if let Some(timeout) = timeout {
+        let timeout = Duration::try_from_secs_f64(timeout)
+            .map_err(|error| PyValueError::new_err(error.to_string()))?;
+        pinger = pinger
+            .connect_timeout(timeout)
+            .read_timeout(timeout)
+            .write_timeout(timeout);
+    }
+    match py.detach(|| pinger.ping()) {
+        Ok(response) => Ok(response.into()),
+        Err(error) => Err(PingError::new_err(error.to_string())),
+    }
+}
+
+#[pymodule]
+fn _craftping(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(ping, m)?)?;
+    m.add_class::<Response>()?;
+    m.add_class::<Player>()?;
+    m.add("PingError", m.py().get_type::<PingError>())?;
+    Ok(())
+}
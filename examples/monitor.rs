@@ -0,0 +1,12 @@
+use std::fs::File;
+
+use craftping::monitor::{Monitor, MonitorConfig};
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("usage: monitor <config.json>");
+    let config: MonitorConfig = serde_json::from_reader(File::open(path).unwrap()).unwrap();
+    let mut monitor = Monitor::new(config).unwrap();
+    monitor.run().unwrap();
+}
@@ -0,0 +1,252 @@
+use alloc::string::{String, ToString};
+use core::{fmt, net::Ipv6Addr, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// The address of a server, the hostname and the port to ping.
+///
+/// It can be parsed from the address the users enter in the client,
+/// like `play.example.com`, `example.com:25566` or `[2001:db8::1]:25565`.
+/// The port defaults to [`DEFAULT_PORT`](ServerAddress::DEFAULT_PORT) if not given,
+/// and then the server may be reached through its SRV record instead. See also [`lookup`](ServerAddress::lookup).
+///
+/// # Examples
+///
+/// ```
+/// use craftping::ServerAddress;
+///
+/// let address: ServerAddress = "[2001:db8::1]".parse().unwrap();
+/// assert_eq!(address.hostname(), "2001:db8::1");
+/// assert_eq!(address.port(), 25565);
+/// assert!(!address.has_port());
+/// assert_eq!(address.to_string(), "[2001:db8::1]:25565");
+/// ```
+pub struct ServerAddress {
+    hostname: String,
+    port: u16,
+    has_port: bool,
+}
+
+impl ServerAddress {
+    /// The port of the servers, used when the address has none.
+    pub const DEFAULT_PORT: u16 = 25565;
+
+    /// Create an address of the hostname and the port.
+    pub fn new(hostname: impl Into<String>, port: u16) -> Self {
+        Self {
+            hostname: hostname.into(),
+            port,
+            has_port: true,
+        }
+    }
+
+    // an address with the port defaulted, as parsed without one
+    #[cfg(any(feature = "sync", feature = "async-tokio"))]
+    pub(crate) fn without_port(hostname: impl Into<String>) -> Self {
+        Self {
+            hostname: hostname.into(),
+            port: Self::DEFAULT_PORT,
+            has_port: false,
+        }
+    }
+
+    /// The hostname of the server, which can be an IP address.
+    /// An IPv6 address is without the brackets.
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// The port of the server.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// `true` if the port was given, rather than defaulted to [`DEFAULT_PORT`](ServerAddress::DEFAULT_PORT).
+    pub fn has_port(&self) -> bool {
+        self.has_port
+    }
+
+    /// Parse a `minecraft://host:port` URI, as the launchers and the link handlers pass.
+    ///
+    /// The address without the scheme is accepted as well, and a trailing slash is ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use craftping::ServerAddress;
+    ///
+    /// let address = ServerAddress::from_url("minecraft://play.example.com:25566/").unwrap();
+    /// assert_eq!(address, ServerAddress::new("play.example.com", 25566));
+    /// assert!(ServerAddress::from_url("https://play.example.com").is_err());
+    /// ```
+    pub fn from_url(url: &str) -> Result<Self, ParseServerAddressError> {
+        let url = url.trim();
+        let address = match url.split_once("://") {
+            Some((scheme, address)) if scheme.eq_ignore_ascii_case("minecraft") => address,
+            Some(_) => return Err(ParseServerAddressError),
+            None => url,
+        };
+        address.strip_suffix('/').unwrap_or(address).parse()
+    }
+
+    /// Resolve the address to connect to like the vanilla client,
+    /// which looks up the `_minecraft._tcp` SRV record only if the port was not given.
+    ///
+    /// The handshake should still carry the address itself rather than the one returned.
+    /// See also [`resolve::lookup`](crate::resolve::lookup).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::{sync::ping, ServerAddress};
+    /// use std::net::TcpStream;
+    ///
+    /// let address: ServerAddress = "my.server.com".parse().unwrap();
+    /// let mut stream = TcpStream::connect(address.lookup()).unwrap();
+    /// let response = ping(&mut stream, address.hostname(), address.port()).unwrap();
+    /// ```
+    #[cfg(feature = "sync")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+    pub fn lookup(&self) -> ServerAddress {
+        if self.has_port {
+            return self.clone();
+        }
+        let (hostname, port) = crate::resolve::lookup(&self.hostname);
+        ServerAddress::new(hostname, port)
+    }
+
+    /// Resolve every target to connect to, in the order they should be tried.
+    ///
+    /// Returns the address itself if it has a port, as [`lookup`](ServerAddress::lookup) does.
+    /// See also [`resolve::lookup_all`](crate::resolve::lookup_all).
+    #[cfg(feature = "sync")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+    pub fn lookup_all(&self) -> Vec<ServerAddress> {
+        if self.has_port {
+            return vec![self.clone()];
+        }
+        crate::resolve::lookup_all(&self.hostname)
+            .into_iter()
+            .map(|(hostname, port)| ServerAddress::new(hostname, port))
+            .collect()
+    }
+}
+
+impl<S: Into<String>> From<(S, u16)> for ServerAddress {
+    fn from((hostname, port): (S, u16)) -> Self {
+        Self::new(hostname, port)
+    }
+}
+
+impl fmt::Display for ServerAddress {
+    /// Format the address as `hostname:port`, with the brackets around an IPv6 address.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.hostname.contains(':') {
+            write!(f, "[{}]:{}", self.hostname, self.port)
+        } else {
+            write!(f, "{}:{}", self.hostname, self.port)
+        }
+    }
+}
+
+impl FromStr for ServerAddress {
+    type Err = ParseServerAddressError;
+
+    /// Parse `hostname`, `hostname:port`, `[ipv6]` or `[ipv6]:port`.
+    /// An IPv6 address without the brackets is accepted as well, without the port.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (hostname, port) = match s.strip_prefix('[') {
+            Some(rest) => {
+                let (ip, rest) = rest.split_once(']').ok_or(ParseServerAddressError)?;
+                ip.parse::<Ipv6Addr>()
+                    .map_err(|_| ParseServerAddressError)?;
+                let port = match rest {
+                    "" => None,
+                    _ => Some(rest.strip_prefix(':').ok_or(ParseServerAddressError)?),
+                };
+                (ip, port)
+            }
+            None if s.parse::<Ipv6Addr>().is_ok() => (s, None),
+            None => match s.split_once(':') {
+                Some((hostname, port)) => (hostname, Some(port)),
+                None => (s, None),
+            },
+        };
+        if hostname.is_empty() || hostname.contains(|c: char| c.is_whitespace() || c == '/') {
+            return Err(ParseServerAddressError);
+        }
+        let port = match port {
+            Some(port) => Some(port.parse().map_err(|_| ParseServerAddressError)?),
+            None => None,
+        };
+        Ok(Self {
+            hostname: hostname.to_string(),
+            port: port.unwrap_or(Self::DEFAULT_PORT),
+            has_port: port.is_some(),
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::net::ToSocketAddrs for ServerAddress {
+    type Iter = std::vec::IntoIter<std::net::SocketAddr>;
+
+    /// Resolve the hostname without looking up the SRV record.
+    fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
+        (self.hostname.as_str(), self.port).to_socket_addrs()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The error returned when a [`ServerAddress`](ServerAddress) cannot be parsed.
+pub struct ParseServerAddressError;
+
+impl fmt::Display for ParseServerAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid server address")
+    }
+}
+
+impl core::error::Error for ParseServerAddressError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_server_address() {
+        let parse = |s: &str| s.parse::<ServerAddress>().ok();
+        let address = |hostname: &str, port| Some(ServerAddress::new(hostname, port));
+
+        let play = parse("play.example.com").unwrap();
+        assert_eq!((play.hostname(), play.port()), ("play.example.com", 25565));
+        assert!(!play.has_port());
+        assert_eq!(parse("example.com:25566"), address("example.com", 25566));
+        assert_eq!(parse("127.0.0.1:25565"), address("127.0.0.1", 25565));
+        assert_eq!(parse("[::1]:25566"), address("::1", 25566));
+        assert_eq!(parse("[::1]").unwrap().hostname(), "::1");
+        assert_eq!(parse("2001:db8::1").unwrap().hostname(), "2001:db8::1");
+
+        for invalid in [
+            "",
+            ":25565",
+            "example.com:",
+            "example.com:65536",
+            "[::1",
+            "[::1]25565",
+        ] {
+            assert_eq!(parse(invalid), None, "{}", invalid);
+        }
+        assert_eq!(parse("[example.com]:25565"), None);
+        assert_eq!(
+            ServerAddress::from_url("Minecraft://[::1]:25566").ok(),
+            address("::1", 25566)
+        );
+        assert_eq!(
+            ServerAddress::from_url("example.com:25566/").ok(),
+            address("example.com", 25566)
+        );
+        assert!(ServerAddress::from_url("minecraft://example.com/path").is_err());
+        assert_eq!(parse("a b:25565"), None);
+    }
+}
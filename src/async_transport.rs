@@ -0,0 +1,303 @@
+//! Provides the [`AsyncTransport`](AsyncTransport) abstraction which the asynchronous ping is written against.
+//!
+//! The [`tokio`](crate::tokio) and [`futures`](crate::futures) modules wrap their streams with
+//! [`TokioIo`](TokioIo) and [`FuturesIo`](FuturesIo) respectively, and share the rest of the flow.
+//! Another runtime can be supported by implementing [`AsyncTransport`](AsyncTransport) for its stream,
+//! and pinging with [`ping`](ping) of this module.
+//!
+//! Unlike [`Transport`](crate::transport::Transport), it does not open a connection, but reads and writes an open stream.
+use std::{
+    convert::TryInto,
+    future::Future,
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::*;
+
+/// An asynchronous stream which a ping request is sent over.
+///
+/// A stream may implement the traits of both tokio and futures, so the streams of each runtime are wrapped
+/// with [`TokioIo`](TokioIo) or [`FuturesIo`](FuturesIo) rather than implementing it directly.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::async_transport::{ping, TokioIo};
+/// use tokio::net::TcpStream;
+///
+/// # async fn run() {
+/// let stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+/// let response = ping(&mut TokioIo(stream), "my.server.com", 25565).await.unwrap();
+/// # }
+/// ```
+pub trait AsyncTransport {
+    /// Read some bytes into the buffer, and return the number of bytes read. `0` means the end of the stream.
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>>;
+
+    /// Read the exact number of bytes to fill the buffer.
+    ///
+    /// By default, it reads repeatedly with [`read`](AsyncTransport::read).
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> impl Future<Output = io::Result<()>> {
+        async move {
+            while !buf.is_empty() {
+                match self.read(buf).await? {
+                    0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    read => buf = &mut buf[read..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Write the whole buffer.
+    fn write_all(&mut self, buf: &[u8]) -> impl Future<Output = io::Result<()>>;
+
+    /// Flush the written bytes to the server.
+    fn flush(&mut self) -> impl Future<Output = io::Result<()>>;
+}
+
+/// Wraps a tokio stream to implement [`AsyncTransport`](AsyncTransport).
+#[cfg(feature = "async-tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
+#[derive(Debug)]
+pub struct TokioIo<S>(pub S);
+
+#[cfg(feature = "async-tokio")]
+impl<S> AsyncTransport for TokioIo<S>
+where
+    S: ::tokio::io::AsyncRead + ::tokio::io::AsyncWrite + Unpin,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        ::tokio::io::AsyncReadExt::read(&mut self.0, buf).await
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        ::tokio::io::AsyncReadExt::read_exact(&mut self.0, buf).await?;
+        Ok(())
+    }
+
+    async fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        ::tokio::io::AsyncWriteExt::write_all(&mut self.0, buf).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        ::tokio::io::AsyncWriteExt::flush(&mut self.0).await
+    }
+}
+
+/// Wraps a futures-io stream to implement [`AsyncTransport`](AsyncTransport).
+#[cfg(feature = "async-futures")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async-futures")))]
+#[derive(Debug)]
+pub struct FuturesIo<S>(pub S);
+
+#[cfg(feature = "async-futures")]
+impl<S> AsyncTransport for FuturesIo<S>
+where
+    S: ::futures::AsyncRead + ::futures::AsyncWrite + Unpin,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        ::futures::AsyncReadExt::read(&mut self.0, buf).await
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        ::futures::AsyncReadExt::read_exact(&mut self.0, buf).await
+    }
+
+    async fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        ::futures::AsyncWriteExt::write_all(&mut self.0, buf).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        ::futures::AsyncWriteExt::flush(&mut self.0).await
+    }
+}
+
+/// Send a ping request over the transport and return a future response.
+///
+/// See also [`Response`](Response).
+pub async fn ping<T: AsyncTransport>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+) -> Result<Response> {
+    ping_with_config(transport, hostname, port, &PingConfig::default()).await
+}
+
+/// Send a ping request over the transport with the options and return a future response.
+///
+/// See also [`PingConfig`](PingConfig).
+pub async fn ping_with_config<T: AsyncTransport>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response> {
+    ping_with_buffer(transport, hostname, port, config, &mut Vec::new()).await
+}
+
+/// Send a ping request over the transport and return a future response,
+/// reusing the memory of the raw response received by the previous ping with the same `buf`.
+///
+/// See also [`ResponseBuf`](ResponseBuf).
+pub async fn ping_into<'a, T: AsyncTransport>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+    buf: &'a mut ResponseBuf,
+) -> Result<&'a Response> {
+    let config = PingConfig::default();
+    let response = ping_with_buffer(transport, hostname, port, &config, buf.reclaim()).await?;
+    Ok(buf.store(response))
+}
+
+/// Send a ping request over the transport, and measure the round-trip time with the ping packet after the response.
+///
+/// For the legacy servers, which do not support the ping packet, the time taken by the legacy request is returned.
+pub async fn ping_with_latency<T: AsyncTransport>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+) -> Result<(Response, Duration)> {
+    let config = PingConfig::default();
+    match request_latest(transport, hostname, port, &config, &mut Vec::new()).await {
+        Ok(response) => Ok((response, measure_latency(transport).await?)),
+        Err(error) => {
+            telemetry::fallback(&error);
+            let start = Instant::now();
+            let response = request_legacy(
+                transport,
+                &build_legacy_request(hostname, port, &config),
+                &config.limits,
+                &mut Vec::new(),
+            )
+            .await?;
+            Ok((response, start.elapsed()))
+        }
+    }
+}
+
+// ping with the config, receiving the raw response into the buffer
+async fn ping_with_buffer<T: AsyncTransport>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+    buffer: &mut Vec<u8>,
+) -> Result<Response> {
+    match request_latest(transport, hostname, port, config, buffer).await {
+        Ok(response) => Ok(response),
+        Err(error) if !config.legacy_fallback => Err(error),
+        Err(error) => {
+            telemetry::fallback(&error);
+            request_legacy(
+                transport,
+                &build_legacy_request(hostname, port, config),
+                &config.limits,
+                buffer,
+            )
+            .await
+        }
+    }
+}
+
+pub(crate) async fn request_latest<T: AsyncTransport>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+    buffer: &mut Vec<u8>,
+) -> Result<Response> {
+    telemetry::observe_async("latest", async {
+        let limits = &config.limits;
+        let request = build_latest_request(hostname, port, config)?;
+        telemetry::stage("handshake", write_request(transport, &request).await)?;
+
+        let _length = telemetry::stage("length", read_varint(transport).await)?;
+        let packet_id = telemetry::stage("packet_id", read_varint(transport).await)?;
+        let response_length = telemetry::stage("status_length", read_varint(transport).await)?;
+        if packet_id != 0x00
+            || response_length < 0
+            || response_length as usize > limits.max_response_size
+        {
+            telemetry::unexpected_status(packet_id, response_length);
+            return Err(Error::UnsupportedProtocol);
+        }
+        buffer.clear();
+        buffer.resize(response_length as usize, 0);
+        telemetry::stage("status", transport.read_exact(buffer).await)?;
+
+        let mut raw = telemetry::stage("decode", decode_latest_response(buffer, limits))?;
+        raw.raw_json = std::mem::take(buffer);
+        raw.try_into()
+    })
+    .await
+}
+
+pub(crate) async fn request_legacy<T: AsyncTransport>(
+    transport: &mut T,
+    request: &[u8],
+    limits: &Limits,
+    buffer: &mut Vec<u8>,
+) -> Result<Response> {
+    telemetry::observe_async("legacy", async {
+        telemetry::stage("handshake", write_request(transport, request).await)?;
+
+        // read as long as the header tells, since some servers keep the connection open
+        let mut header = [0u8; 3];
+        telemetry::stage("status", transport.read_exact(&mut header).await)?;
+        let length = legacy_body_length(header, limits)?;
+        buffer.clear();
+        buffer.extend_from_slice(&header);
+        buffer.resize(header.len() + length, 0);
+        telemetry::stage(
+            "status",
+            transport.read_exact(&mut buffer[header.len()..]).await,
+        )?;
+
+        let response = telemetry::stage("decode", decode_legacy(buffer))?;
+        parse_legacy(&response, std::mem::take(buffer))
+    })
+    .await
+}
+
+async fn write_request<T: AsyncTransport>(transport: &mut T, request: &[u8]) -> io::Result<()> {
+    transport.write_all(request).await?;
+    transport.flush().await
+}
+
+async fn measure_latency<T: AsyncTransport>(transport: &mut T) -> Result<Duration> {
+    let payload = ping_payload();
+    let start = Instant::now();
+    transport.write_all(&build_ping_request(payload)).await?;
+    transport.flush().await?;
+
+    let length = read_varint(transport).await?;
+    let packet_id = read_varint(transport).await?;
+    let mut pong = [0u8; 8];
+    transport.read_exact(&mut pong).await?;
+    if length != 9 || packet_id != 0x01 || i64::from_be_bytes(pong) != payload {
+        return Err(Error::UnsupportedProtocol);
+    }
+    Ok(start.elapsed())
+}
+
+pub(crate) async fn read_varint<T: AsyncTransport>(transport: &mut T) -> Result<i32> {
+    let mut buffer = [0u8];
+    let mut result = 0;
+    let mut read_count = 0u32;
+    loop {
+        transport.read_exact(&mut buffer).await?;
+        result |= (buffer[0] as i32 & LAST_SEVEN_BITS)
+            .checked_shl(7 * read_count)
+            .ok_or(Error::UnsupportedProtocol)?;
+
+        read_count += 1;
+        if read_count > 5 {
+            break Err(Error::UnsupportedProtocol);
+        } else if (buffer[0] & NEXT_BYTE_EXISTS) == 0 {
+            break Ok(result);
+        }
+    }
+}
@@ -0,0 +1,476 @@
+//! The command line interface of craftping.
+//!
+//! ```text
+//! craftping ping my.server.com
+//! craftping legacy my.server.com:25565 --json
+//! craftping query my.server.com --full
+//! craftping watch my.server.com --interval 30
+//! craftping ping my.server.com --check --warning-latency 200 --critical-players 100
+//! ```
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use craftping::{
+    pinger::Pinger,
+    query::{basic_stat_with_retransmission, full_stat_with_retransmission, Retransmission},
+    resolve::DnsCache,
+    sync::ping_legacy,
+    watch::Watcher,
+    Error, Response, Result, ServerAddress,
+};
+use serde_json::json;
+
+const USAGE: &str = "\
+usage: craftping <command> <host>[:<port>] [options]
+
+commands:
+  ping      ping the server with the Server List Ping, falling back to the legacy protocol
+  legacy    ping the server with the legacy protocol of 1.6
+  query     request the stat of the server with the Query protocol
+  watch     ping the server periodically until interrupted
+
+options:
+  --json             print the results as JSON, one line each
+  --timeout <secs>   the timeout of connecting and each read, or of the first query attempt (default: 5)
+  --full             request the full stat, including the plugins and the players (query only)
+  --interval <secs>  the interval between the pings (watch only, default: 10)
+  --check            print one line and exit like a monitoring plugin (ping and legacy only)
+  --warning-latency <ms>, --critical-latency <ms>
+                     the latency above which the check warns or fails
+  --warning-players <n>, --critical-players <n>
+                     the online players at or above which the check warns or fails
+
+With --check, the exit code is 0 if OK, 1 if WARNING, 2 if CRITICAL and 3 if UNKNOWN.
+
+The port defaults to the one of the _minecraft._tcp SRV record, or 25565.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Ping,
+    Legacy,
+    Query,
+    Watch,
+}
+
+#[derive(Debug)]
+struct Args {
+    command: Command,
+    address: ServerAddress,
+    json: bool,
+    full: bool,
+    timeout: Duration,
+    interval: Duration,
+    check: Option<Thresholds>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Thresholds {
+    warning_latency: Option<Duration>,
+    critical_latency: Option<Duration>,
+    warning_players: Option<usize>,
+    critical_players: Option<usize>,
+}
+
+// the states of a monitoring plugin, whose values are its exit codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum State {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl State {
+    fn label(self) -> &'static str {
+        match self {
+            State::Ok => "OK",
+            State::Warning => "WARNING",
+            State::Critical => "CRITICAL",
+            State::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let raw: Vec<String> = std::env::args().skip(1).collect();
+    let args = match parse_args(raw.iter().cloned()) {
+        Ok(args) => args,
+        // a monitoring system expects UNKNOWN for a misconfigured check
+        Err(message) if raw.iter().any(|arg| arg == "--check") => {
+            println!("CRAFTPING {} - {}", State::Unknown.label(), message);
+            return ExitCode::from(State::Unknown as u8);
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if let Some(thresholds) = &args.check {
+        let start = Instant::now();
+        let result = match args.command {
+            Command::Legacy => legacy(&args),
+            _ => pinger(&args).ping(),
+        };
+        let (state, line) = match result {
+            Ok(response) => check(thresholds, &response, start.elapsed()),
+            Err(error) => (State::Critical, error.to_string()),
+        };
+        println!("CRAFTPING {} - {}", state.label(), line);
+        return ExitCode::from(state as u8);
+    }
+    let result = match args.command {
+        Command::Ping => pinger(&args)
+            .ping()
+            .map(|response| print_response(&args, &response)),
+        Command::Legacy => legacy(&args).map(|response| print_response(&args, &response)),
+        Command::Query => query(&args),
+        Command::Watch => {
+            watch(&args);
+            Ok(())
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            print_error(&args, &error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> std::result::Result<Args, String> {
+    let command = match args.next().as_deref() {
+        Some("ping") => Command::Ping,
+        Some("legacy") => Command::Legacy,
+        Some("query") => Command::Query,
+        Some("watch") => Command::Watch,
+        Some(command) => return Err(format!("unknown command `{}`", command)),
+        None => return Err("no command given".to_string()),
+    };
+    let mut address = None;
+    let mut json = false;
+    let mut full = false;
+    let mut timeout = Duration::from_secs(5);
+    let mut interval = Duration::from_secs(10);
+    let mut check = false;
+    let mut thresholds = Thresholds::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--full" => full = true,
+            "--check" => check = true,
+            "--timeout" => timeout = parse_seconds("--timeout", args.next())?,
+            "--interval" => interval = parse_seconds("--interval", args.next())?,
+            "--warning-latency" => {
+                thresholds.warning_latency = Some(parse_millis(&arg, args.next())?)
+            }
+            "--critical-latency" => {
+                thresholds.critical_latency = Some(parse_millis(&arg, args.next())?)
+            }
+            "--warning-players" => {
+                thresholds.warning_players = Some(parse_count(&arg, args.next())?)
+            }
+            "--critical-players" => {
+                thresholds.critical_players = Some(parse_count(&arg, args.next())?)
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option `{}`", arg)),
+            _ if address.is_none() => address = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let address = address.ok_or("no host given")?;
+    let address = address
+        .parse()
+        .map_err(|_| format!("invalid address `{}`", address))?;
+    if check && !matches!(command, Command::Ping | Command::Legacy) {
+        return Err("--check applies only to ping and legacy".to_string());
+    }
+    if !check && thresholds != Thresholds::default() {
+        return Err("the thresholds apply only with --check".to_string());
+    }
+    Ok(Args {
+        command,
+        address,
+        json,
+        full,
+        timeout,
+        interval,
+        check: check.then_some(thresholds),
+    })
+}
+
+fn parse_seconds(option: &str, value: Option<String>) -> std::result::Result<Duration, String> {
+    value
+        .as_deref()
+        .and_then(|value| value.parse::<f64>().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("{} takes a positive number of seconds", option))
+}
+
+fn parse_millis(option: &str, value: Option<String>) -> std::result::Result<Duration, String> {
+    value
+        .as_deref()
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("{} takes a number of milliseconds", option))
+}
+
+fn parse_count(option: &str, value: Option<String>) -> std::result::Result<usize, String> {
+    value
+        .as_deref()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{} takes a number of players", option))
+}
+
+// the worst state whose threshold the value reaches
+fn rate<T: PartialOrd>(value: T, warning: Option<T>, critical: Option<T>) -> State {
+    if critical.is_some_and(|critical| value >= critical) {
+        State::Critical
+    } else if warning.is_some_and(|warning| value >= warning) {
+        State::Warning
+    } else {
+        State::Ok
+    }
+}
+
+// rates the response against the thresholds, returning the state and the line to print
+// with the performance data in the format of the monitoring plugins
+fn check(thresholds: &Thresholds, response: &Response, latency: Duration) -> (State, String) {
+    let latency_state = rate(
+        latency,
+        thresholds.warning_latency,
+        thresholds.critical_latency,
+    );
+    let players_state = rate(
+        response.online_players,
+        thresholds.warning_players,
+        thresholds.critical_players,
+    );
+    let millis = |duration: Option<Duration>| {
+        duration.map_or(String::new(), |duration| duration.as_millis().to_string())
+    };
+    let count = |count: Option<usize>| count.map_or(String::new(), |count| count.to_string());
+    let line = format!(
+        "{}, {}/{} players, {} ms | latency={}ms;{};{};0 players={};{};{};0;{}",
+        response.version,
+        response.online_players,
+        response.max_players,
+        latency.as_millis(),
+        latency.as_millis(),
+        millis(thresholds.warning_latency),
+        millis(thresholds.critical_latency),
+        response.online_players,
+        count(thresholds.warning_players),
+        count(thresholds.critical_players),
+        response.max_players,
+    );
+    (latency_state.max(players_state), line)
+}
+
+fn pinger(args: &Args) -> Pinger {
+    // the SRV records are looked up only if the port is not given
+    Pinger::from_address(args.address.clone())
+        .connect_timeout(args.timeout)
+        .read_timeout(args.timeout)
+        .write_timeout(args.timeout)
+}
+
+fn legacy(args: &Args) -> Result<Response> {
+    let mut last_error = std::io::ErrorKind::NotFound.into();
+    for target in args.address.lookup_all() {
+        for address in target.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, args.timeout) {
+                Ok(mut stream) => {
+                    stream.set_read_timeout(Some(args.timeout))?;
+                    stream.set_write_timeout(Some(args.timeout))?;
+                    return ping_legacy(&mut stream, args.address.hostname(), target.port());
+                }
+                Err(error) => last_error = error,
+            }
+        }
+    }
+    Err(last_error.into())
+}
+
+fn query(args: &Args) -> Result<()> {
+    let address = args
+        .address
+        .to_socket_addrs()?
+        .next()
+        .ok_or(std::io::Error::from(std::io::ErrorKind::NotFound))?;
+    let local = match address {
+        SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(address)?;
+    let retransmission = Retransmission {
+        timeout: args.timeout,
+        ..Default::default()
+    };
+    if args.full {
+        let stat = full_stat_with_retransmission(&socket, &retransmission)?;
+        if args.json {
+            println!("{}", json!(stat));
+        } else {
+            println!("version  {}", stat.version);
+            if let Some(server_mod) = &stat.server_mod {
+                println!("software {}", server_mod);
+            }
+            println!("map      {}", stat.map);
+            println!("players  {}/{}", stat.online_players, stat.max_players);
+            for player in &stat.players {
+                println!("         {}", player);
+            }
+            println!("motd     {}", stat.motd);
+            for plugin in &stat.plugins {
+                println!("plugin   {} {}", plugin.name, plugin.version);
+            }
+        }
+    } else {
+        let stat = basic_stat_with_retransmission(&socket, &retransmission)?;
+        if args.json {
+            println!("{}", json!(stat));
+        } else {
+            println!("map      {}", stat.map);
+            println!("players  {}/{}", stat.online_players, stat.max_players);
+            println!("motd     {}", stat.motd);
+        }
+    }
+    Ok(())
+}
+
+fn watch(args: &Args) {
+    // resolve the hostname again only every few minutes, rather than on every ping
+    let pinger = pinger(args).dns_cache(DnsCache::new(Duration::from_secs(300)));
+    for result in Watcher::new(pinger, args.interval).iter() {
+        match result {
+            Ok(response) => print_response(args, &response),
+            Err(error) => print_error(args, &error),
+        }
+    }
+}
+
+fn print_response(args: &Args, response: &Response) {
+    if args.json {
+        println!("{}", json!(response.without_favicon()));
+        return;
+    }
+    if args.command == Command::Watch {
+        println!(
+            "{} {}/{} online",
+            response.version, response.online_players, response.max_players
+        );
+        return;
+    }
+    println!(
+        "version  {} (protocol {})",
+        response.version, response.protocol
+    );
+    println!(
+        "players  {}/{}",
+        response.online_players, response.max_players
+    );
+    for player in response.sample.iter().flatten() {
+        println!("         {}", player.plain_name());
+    }
+    for (index, line) in response.description.lines().iter().enumerate() {
+        let label = if index == 0 { "motd" } else { "" };
+        println!("{:<8} {}", label, line.plain_text());
+    }
+}
+
+fn print_error(args: &Args, error: &Error) {
+    if args.json {
+        println!("{}", json!({ "error": error.to_string() }));
+    } else {
+        eprintln!("error: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use craftping::Chat;
+
+    fn parse(args: &str) -> std::result::Result<Args, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let args = parse("ping localhost").unwrap();
+        assert_eq!(args.command, Command::Ping);
+        assert_eq!(args.address.hostname(), "localhost");
+        assert!(!args.json && !args.full);
+        assert_eq!(args.timeout, Duration::from_secs(5));
+        assert_eq!(args.interval, Duration::from_secs(10));
+        assert_eq!(args.check, None);
+    }
+
+    #[test]
+    fn parse_options() {
+        let args = parse("watch localhost:25599 --json --timeout 1.5 --interval 30").unwrap();
+        assert_eq!(args.command, Command::Watch);
+        assert_eq!(args.address.port(), 25599);
+        assert!(args.json);
+        assert_eq!(args.timeout, Duration::from_millis(1500));
+        assert_eq!(args.interval, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn parse_check() {
+        let args =
+            parse("legacy localhost --check --warning-latency 200 --critical-players 100").unwrap();
+        assert_eq!(
+            args.check,
+            Some(Thresholds {
+                warning_latency: Some(Duration::from_millis(200)),
+                critical_players: Some(100),
+                ..Thresholds::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("pong localhost").is_err());
+        assert!(parse("ping").is_err());
+        assert!(parse("ping localhost other").is_err());
+        assert!(parse("ping localhost --verbose").is_err());
+        assert!(parse("ping localhost --timeout").is_err());
+        assert!(parse("ping localhost --timeout 0").is_err());
+        assert!(parse("ping localhost --timeout -1").is_err());
+        assert!(parse("ping localhost --check --warning-latency fast").is_err());
+        assert!(parse("query localhost --check").is_err());
+        assert!(parse("ping localhost --critical-players 10").is_err());
+    }
+
+    #[test]
+    fn check_thresholds() {
+        let mut response = Response::new("1.20.4", 765, Chat::from_legacy("A Minecraft Server"));
+        response.online_players = 15;
+        let thresholds = Thresholds {
+            warning_latency: Some(Duration::from_millis(100)),
+            critical_latency: Some(Duration::from_millis(500)),
+            warning_players: Some(15),
+            critical_players: None,
+        };
+        let (state, line) = check(&thresholds, &response, Duration::from_millis(42));
+        assert_eq!(state, State::Warning);
+        assert_eq!(
+            line,
+            "1.20.4, 15/20 players, 42 ms | latency=42ms;100;500;0 players=15;15;;0;20"
+        );
+        let (state, _) = check(&thresholds, &response, Duration::from_millis(600));
+        assert_eq!(state, State::Critical);
+        response.online_players = 3;
+        let (state, _) = check(&thresholds, &response, Duration::from_millis(42));
+        assert_eq!(state, State::Ok);
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Default)]
+/// A token to stop long-running operations, like [`Monitor::run_until`](crate::monitor::Monitor::run_until).
+///
+/// Clones of a token share the same state, so one of them can be handed to another thread to cancel the operation.
+/// A cancelled operation finishes the ping in flight and returns normally, instead of being aborted midway.
+///
+/// # Examples
+///
+/// ```
+/// use craftping::CancelToken;
+///
+/// let token = CancelToken::new();
+/// let handle = token.clone();
+/// std::thread::spawn(move || handle.cancel()).join().unwrap();
+/// assert!(token.is_cancelled());
+/// ```
+pub struct CancelToken {
+    state: Arc<(Mutex<State>, Condvar)>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    cancelled: bool,
+    // the tasks waiting on the token asynchronously
+    wakers: Vec<Waker>,
+}
+
+impl CancelToken {
+    /// Create a token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Cancel the token and wake up every thread and task waiting on it.
+    pub fn cancel(&self) {
+        let mut state = self.lock();
+        state.cancelled = true;
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+        self.state.1.notify_all();
+    }
+
+    /// `true` if the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled
+    }
+
+    /// Block the current thread until the token is cancelled or the timeout elapses.
+    /// Returns `true` if the token has been cancelled.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        // a timeout too long to represent waits until the cancellation
+        let deadline = Instant::now().checked_add(timeout);
+        let mut guard = self.lock();
+        while !guard.cancelled {
+            guard = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    self.state
+                        .1
+                        .wait_timeout(guard, remaining)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self.state.1.wait(guard).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+        guard.cancelled
+    }
+
+    /// Return a future which completes when the token is cancelled, to wait on it in an asynchronous context.
+    ///
+    /// The future does not depend on any runtime.
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            token: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A future which completes when the token is cancelled, created by [`CancelToken::cancelled`](CancelToken::cancelled).
+pub struct Cancelled {
+    token: CancelToken,
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.token.lock();
+        if state.cancelled {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wait_without_deadline() {
+        let token = CancelToken::new();
+        let handle = token.clone();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            handle.cancel();
+        });
+        assert!(token.wait_timeout(Duration::MAX));
+        canceller.join().unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+//! Provides [`CaptureStream`](CaptureStream) to record the bytes exchanged with a server, for debugging.
+//!
+//! Wrap the stream before pinging, and the whole exchange is available afterwards whether the ping succeeded or not,
+//! e.g. to find out why a server is answered with [`UnsupportedProtocol`](crate::Error::UnsupportedProtocol).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use craftping::{capture::CaptureStream, sync::ping};
+//! use std::net::TcpStream;
+//!
+//! let mut stream = CaptureStream::new(TcpStream::connect(("my.server.com", 25565)).unwrap());
+//! if let Err(error) = ping(&mut stream, "my.server.com", 25565) {
+//!     println!("{}\n{}", error, stream.exchange());
+//! }
+//! ```
+use std::{
+    fmt::{self, Display, Write as _},
+    io::{self, Read, Write},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The bytes sent to and received from a server.
+pub struct Exchange {
+    /// The bytes sent to the server, in order.
+    pub sent: Vec<u8>,
+    /// The bytes received from the server, in order.
+    pub received: Vec<u8>,
+}
+
+impl Display for Exchange {
+    /// Format the sent and the received bytes as hexdumps.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "sent {} bytes:", self.sent.len())?;
+        f.write_str(&hexdump(&self.sent))?;
+        writeln!(f, "received {} bytes:", self.received.len())?;
+        f.write_str(&hexdump(&self.received))
+    }
+}
+
+/// Format the bytes as a hexdump, 16 bytes a line with the offset and the printable characters.
+///
+/// # Examples
+///
+/// ```
+/// use craftping::capture::hexdump;
+///
+/// assert_eq!(
+///     hexdump(b"\xfe\x01craft"),
+///     "00000000  fe 01 63 72 61 66 74                             |..craft|\n",
+/// );
+/// ```
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(dump, "{:08x} ", line * 16);
+        for index in 0..16 {
+            match chunk.get(index) {
+                Some(byte) => {
+                    let _ = write!(dump, " {:02x}", byte);
+                }
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        dump.extend(chunk.iter().map(|&byte| match byte {
+            0x20..=0x7e => byte as char,
+            _ => '.',
+        }));
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+#[derive(Debug)]
+/// A stream which records every byte written to and read from the inner stream.
+///
+/// It implements the blocking [`Read`](Read) and [`Write`](Write),
+/// the tokio `AsyncRead` and `AsyncWrite` when `async-tokio` feature is enabled,
+/// and the futures `AsyncRead` and `AsyncWrite` when `async-futures` feature is enabled.
+///
+/// See also [`Pinger::capture`](crate::pinger::Pinger::capture), which captures the pings of a pinger.
+pub struct CaptureStream<S> {
+    stream: S,
+    exchange: Exchange,
+}
+
+impl<S> CaptureStream<S> {
+    /// Wrap the stream to record the bytes through it.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            exchange: Exchange::default(),
+        }
+    }
+
+    /// The bytes exchanged so far.
+    pub fn exchange(&self) -> &Exchange {
+        &self.exchange
+    }
+
+    /// Return the bytes exchanged so far, and start to record from scratch.
+    pub fn take_exchange(&mut self) -> Exchange {
+        std::mem::take(&mut self.exchange)
+    }
+
+    /// Return the inner stream and the bytes exchanged.
+    pub fn into_inner(self) -> (S, Exchange) {
+        (self.stream, self.exchange)
+    }
+}
+
+impl<S: Read> Read for CaptureStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stream.read(buf)?;
+        self.exchange.received.extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<S: Write> Write for CaptureStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.stream.write(buf)?;
+        self.exchange.sent.extend_from_slice(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+mod tokio {
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use ::tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::CaptureStream;
+
+    impl<S: AsyncRead + Unpin> AsyncRead for CaptureStream<S> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            let filled = buf.filled().len();
+            let poll = Pin::new(&mut this.stream).poll_read(cx, buf);
+            if let Poll::Ready(Ok(())) = poll {
+                this.exchange
+                    .received
+                    .extend_from_slice(&buf.filled()[filled..]);
+            }
+            poll
+        }
+    }
+
+    impl<S: AsyncWrite + Unpin> AsyncWrite for CaptureStream<S> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            let poll = Pin::new(&mut this.stream).poll_write(cx, buf);
+            if let Poll::Ready(Ok(written)) = poll {
+                this.exchange.sent.extend_from_slice(&buf[..written]);
+            }
+            poll
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+        }
+    }
+}
+
+#[cfg(feature = "async-futures")]
+mod futures {
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use ::futures::{AsyncRead, AsyncWrite};
+
+    use super::CaptureStream;
+
+    impl<S: AsyncRead + Unpin> AsyncRead for CaptureStream<S> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            let poll = Pin::new(&mut this.stream).poll_read(cx, buf);
+            if let Poll::Ready(Ok(read)) = poll {
+                this.exchange.received.extend_from_slice(&buf[..read]);
+            }
+            poll
+        }
+    }
+
+    impl<S: AsyncWrite + Unpin> AsyncWrite for CaptureStream<S> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            let poll = Pin::new(&mut this.stream).poll_write(cx, buf);
+            if let Poll::Ready(Ok(written)) = poll {
+                this.exchange.sent.extend_from_slice(&buf[..written]);
+            }
+            poll
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().stream).poll_close(cx)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capture_exchange() {
+        let mut writer = CaptureStream::new(Vec::new());
+        writer.write_all(&[0xfe, 0x01]).unwrap();
+        let mut reader = CaptureStream::new(&b"\xff\x00\x03abc"[..]);
+        let mut response = [0u8; 4];
+        reader.read_exact(&mut response).unwrap();
+
+        let exchange = Exchange {
+            sent: writer.take_exchange().sent,
+            received: reader.take_exchange().received,
+        };
+        assert_eq!(exchange.sent, [0xfe, 0x01]);
+        assert_eq!(exchange.received, b"\xff\x00\x03a");
+        assert_eq!(
+            exchange.to_string(),
+            "sent 2 bytes:\n00000000  fe 01                                            |..|\n\
+             received 4 bytes:\n00000000  ff 00 03 61                                      |...a|\n",
+        );
+    }
+
+    #[cfg(feature = "async-futures")]
+    #[test]
+    fn capture_futures_exchange() {
+        use ::futures::{executor::block_on, io::Cursor, AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = CaptureStream::new(Cursor::new(b"\xff\x00".to_vec()));
+        block_on(async {
+            let mut byte = [0u8];
+            stream.read_exact(&mut byte).await.unwrap();
+            stream.write_all(&[0xfe]).await.unwrap();
+        });
+        assert_eq!(stream.exchange().received, [0xff]);
+        assert_eq!(stream.exchange().sent, [0xfe]);
+    }
+}
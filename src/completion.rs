@@ -0,0 +1,219 @@
+use std::{convert::TryInto, io};
+
+use crate::*;
+
+// the size of each read, large enough for most responses in a few reads
+const CHUNK_SIZE: usize = 4096;
+
+// a stream of completion-based I/O, which takes the ownership of the buffer while reading or writing
+pub(crate) trait OwnedIo {
+    // read into the buffer from its start, as both tokio-uring and monoio do for `Vec`,
+    // leaving the bytes read at the front
+    async fn read(&mut self, buffer: Vec<u8>) -> (io::Result<usize>, Vec<u8>);
+
+    async fn write_all(&mut self, buffer: Vec<u8>) -> (io::Result<()>, Vec<u8>);
+}
+
+pub(crate) async fn ping_with_config<S: OwnedIo>(
+    stream: &mut S,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response> {
+    match request_latest(stream, hostname, port, config).await {
+        Ok(response) => Ok(response),
+        Err(error) if !config.legacy_fallback => Err(error),
+        Err(_) => {
+            request_legacy(
+                stream,
+                build_legacy_request(hostname, port, config),
+                &config.limits,
+            )
+            .await
+        }
+    }
+}
+
+// reads the stream in chunks, appending them to a buffer
+struct Reader<'a, S> {
+    stream: &'a mut S,
+    buffer: Vec<u8>,
+    // the owned buffer passed to each read, which is overwritten by the next one
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl<'a, S: OwnedIo> Reader<'a, S> {
+    fn new(stream: &'a mut S) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+
+    // read until `length` bytes are available after the position, and return `false` on EOF
+    async fn fill(&mut self, length: usize) -> Result<bool> {
+        while self.buffer.len() - self.position < length {
+            let mut chunk = std::mem::take(&mut self.chunk);
+            chunk.clear();
+            chunk.reserve(CHUNK_SIZE);
+            let (read, chunk) = self.stream.read(chunk).await;
+            let read = read?;
+            if read == 0 {
+                return Ok(false);
+            }
+            self.buffer
+                .extend_from_slice(&chunk[..read.min(chunk.len())]);
+            self.chunk = chunk;
+        }
+        Ok(true)
+    }
+
+    async fn read_exact(&mut self, length: usize) -> Result<&[u8]> {
+        if !self.fill(length).await? {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let start = self.position;
+        self.position += length;
+        Ok(&self.buffer[start..self.position])
+    }
+
+    async fn read_varint(&mut self) -> Result<i32> {
+        let mut result = 0;
+        let mut read_count = 0u32;
+        loop {
+            let byte = self.read_exact(1).await?[0];
+            result |= (byte as i32 & LAST_SEVEN_BITS)
+                .checked_shl(7 * read_count)
+                .ok_or(Error::UnsupportedProtocol)?;
+
+            read_count += 1;
+            if read_count > 5 {
+                break Err(Error::UnsupportedProtocol);
+            } else if (byte & NEXT_BYTE_EXISTS) == 0 {
+                break Ok(result);
+            }
+        }
+    }
+}
+
+pub(crate) async fn request_latest<S: OwnedIo>(
+    stream: &mut S,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response> {
+    let limits = &config.limits;
+    let request = build_latest_request(hostname, port, config)?;
+    let (written, _) = stream.write_all(request).await;
+    written?;
+
+    let mut reader = Reader::new(stream);
+    let _length = reader.read_varint().await?;
+    let packet_id = reader.read_varint().await?;
+    let response_length = reader.read_varint().await?;
+    if packet_id != 0x00
+        || response_length < 0
+        || response_length as usize > limits.max_response_size
+    {
+        return Err(Error::UnsupportedProtocol);
+    }
+    let buffer = reader.read_exact(response_length as usize).await?.to_vec();
+
+    let mut raw = decode_latest_response(&buffer, limits)?;
+    raw.raw_json = buffer;
+    raw.try_into()
+}
+
+pub(crate) async fn request_legacy<S: OwnedIo>(
+    stream: &mut S,
+    request: Vec<u8>,
+    limits: &Limits,
+) -> Result<Response> {
+    let (written, _) = stream.write_all(request).await;
+    written?;
+
+    // read as long as the header tells, since some servers keep the connection open
+    let mut reader = Reader::new(stream);
+    let header = reader.read_exact(3).await?;
+    let header = [header[0], header[1], header[2]];
+    let length = legacy_body_length(header, limits)?;
+    reader.read_exact(length).await?;
+    let mut buffer = reader.buffer;
+    buffer.truncate(header.len() + length);
+
+    let response = decode_legacy(&buffer)?;
+    parse_legacy(&response, buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // answers with the chunks one by one, writing each at the start of the buffer
+    // and setting the length to it, as monoio does
+    struct Chunks(std::collections::VecDeque<Vec<u8>>);
+
+    impl OwnedIo for Chunks {
+        async fn read(&mut self, mut buffer: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+            let Some(mut chunk) = self.0.pop_front() else {
+                return (Ok(0), buffer);
+            };
+            let rest = chunk.split_off(chunk.len().min(buffer.capacity()));
+            if !rest.is_empty() {
+                self.0.push_front(rest);
+            }
+            buffer.clear();
+            buffer.extend_from_slice(&chunk);
+            (Ok(chunk.len()), buffer)
+        }
+
+        async fn write_all(&mut self, buffer: Vec<u8>) -> (io::Result<()>, Vec<u8>) {
+            (Ok(()), buffer)
+        }
+    }
+
+    fn status(description: &str) -> Vec<u8> {
+        let json = format!(
+            r#"{{"version":{{"name":"1.20.4","protocol":765}},"players":{{"max":20,"online":0}},"description":"{}"}}"#,
+            description
+        );
+        let mut body = vec![0x00];
+        write_varint(&mut body, json.len() as i32);
+        body.extend_from_slice(json.as_bytes());
+        let mut packet = vec![];
+        write_varint(&mut packet, body.len() as i32);
+        packet.extend_from_slice(&body);
+        packet
+    }
+
+    fn ping(chunks: Vec<Vec<u8>>) -> Result<Response> {
+        let mut stream = Chunks(chunks.into());
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(request_latest(
+            &mut stream,
+            "localhost",
+            25565,
+            &PingConfig::default(),
+        ))
+    }
+
+    #[test]
+    fn read_split_response() {
+        let packet = status("Hello");
+        let (head, tail) = packet.split_at(10);
+        let response = ping(vec![head.to_vec(), tail.to_vec()]).unwrap();
+        assert_eq!(response.description.text, "Hello");
+    }
+
+    #[test]
+    fn read_response_larger_than_chunk() {
+        let description = "a".repeat(6000);
+        let response = ping(vec![status(&description)]).unwrap();
+        assert_eq!(response.description.text, description);
+    }
+}
@@ -0,0 +1,176 @@
+//! Provides [`Embed`](Embed) to show a [`Response`](Response) in Discord.
+//!
+//! The embed serializes into the embed object of the Discord API,
+//! so it can be sent with any Discord library or directly to a webhook.
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{Chat, Response};
+
+// the color of the sidebar, same as the `green` chat color
+const ONLINE_COLOR: u32 = 0x55ff55;
+// Discord rejects a field value longer than this
+const MAX_FIELD_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone, Default, Serialize)]
+/// An embed object of the Discord API.
+///
+/// See also [the Discord documentation](https://discord.com/developers/docs/resources/message#embed-object).
+pub struct Embed {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The title of the embed, e.g. the address of the server.
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The description of the embed.
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The color of the sidebar in RGB.
+    pub color: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    /// The fields of the embed.
+    pub fields: Vec<EmbedField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The thumbnail shown on the top right of the embed.
+    pub thumbnail: Option<EmbedThumbnail>,
+    #[serde(skip)]
+    /// The favicon of the server, to be uploaded as an attachment named [`FAVICON_FILENAME`](FAVICON_FILENAME).
+    ///
+    /// Discord does not accept images embedded in the embed object itself,
+    /// so the thumbnail refers to the attachment instead.
+    pub favicon: Option<Vec<u8>>,
+}
+
+/// The filename of the attachment the thumbnail of [`Embed`](Embed) refers to.
+pub const FAVICON_FILENAME: &str = "favicon.png";
+
+#[derive(Debug, Clone, Serialize)]
+/// A field of [`Embed`](Embed).
+pub struct EmbedField {
+    /// The name of the field.
+    pub name: String,
+    /// The value of the field.
+    pub value: String,
+    /// `true` if the field should be displayed side by side with the other inline fields.
+    pub inline: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// A thumbnail of [`Embed`](Embed).
+pub struct EmbedThumbnail {
+    /// The URL of the image.
+    pub url: String,
+}
+
+impl Embed {
+    /// Add the latency field to the embed.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.fields.push(EmbedField {
+            name: "Latency".to_string(),
+            value: format!("{} ms", latency.as_millis()),
+            inline: true,
+        });
+        self
+    }
+}
+
+impl Response {
+    /// Build a Discord embed showing the MOTD, the player counts, the version and the favicon.
+    ///
+    /// The MOTD is shown without styles, including the legacy formatting codes.
+    /// The title is left empty, and the latency can be added with [`with_latency`](Embed::with_latency).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn run(response: craftping::Response, latency: std::time::Duration) {
+    /// let mut embed = response.to_discord_embed().with_latency(latency);
+    /// embed.title = Some("my.server.com".to_string());
+    /// let body = serde_json::json!({ "embeds": [embed] });
+    /// # }
+    /// ```
+    pub fn to_discord_embed(&self) -> Embed {
+        let motd = self
+            .motd_lines()
+            .iter()
+            .map(|line| Chat::from_legacy(&line.plain_text()).plain_text())
+            .map(|line| line.trim().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut players = format!("{}/{}", self.online_players, self.max_players);
+        if let Some(sample) = self.sample.as_ref().filter(|sample| !sample.is_empty()) {
+            let names = sample
+                .iter()
+                .map(|player| player.plain_name())
+                .collect::<Vec<_>>()
+                .join(", ");
+            players.push('\n');
+            players.push_str(&names);
+        }
+        let version = if self.protocol < 0 {
+            Chat::from_legacy(&self.version).plain_text()
+        } else {
+            format!(
+                "{} ({})",
+                Chat::from_legacy(&self.version).plain_text(),
+                self.protocol
+            )
+        };
+
+        let field = |name: &str, value: String| EmbedField {
+            name: name.to_string(),
+            value: truncate(value),
+            inline: true,
+        };
+        Embed {
+            title: None,
+            description: Some(motd).filter(|motd| !motd.is_empty()),
+            color: Some(ONLINE_COLOR),
+            fields: vec![field("Players", players), field("Version", version)],
+            thumbnail: self.favicon.as_ref().map(|_| EmbedThumbnail {
+                url: format!("attachment://{}", FAVICON_FILENAME),
+            }),
+            favicon: self.favicon.clone(),
+        }
+    }
+}
+
+fn truncate(mut value: String) -> String {
+    if value.len() > MAX_FIELD_LENGTH {
+        let mut end = MAX_FIELD_LENGTH - '…'.len_utf8();
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+        value.push('…');
+    }
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::decode_latest_response;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn build_embed() {
+        let raw = r#"{
+            "version": { "name": "Paper 1.20.4", "protocol": 765 },
+            "players": { "max": 20, "online": 1, "sample": [{ "name": "§cAd", "id": "" }] },
+            "description": { "text": "Hello\n", "extra": [{ "text": "World", "bold": true }] }
+        }"#;
+        let latest = decode_latest_response(raw.as_bytes(), &Default::default()).unwrap();
+        let response = Response::try_from(latest).unwrap();
+        let embed = response
+            .to_discord_embed()
+            .with_latency(Duration::from_millis(42));
+        let json = serde_json::to_value(&embed).unwrap();
+        assert_eq!(json["description"], "Hello\nWorld");
+        assert_eq!(json["fields"][0]["value"], "1/20\nAd");
+        assert_eq!(json["fields"][1]["value"], "Paper 1.20.4 (765)");
+        assert_eq!(json["fields"][2]["value"], "42 ms");
+        assert!(json.get("thumbnail").is_none());
+    }
+}
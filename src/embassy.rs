@@ -0,0 +1,67 @@
+//! Provides asynchronous [`ping`](ping) function over the TCP socket of embassy-net.
+//!
+//! The [`ping`](ping) function here connects the socket and pings the server with
+//! [`embedded_io_async`](crate::embedded_io_async), closing the socket afterwards.
+//! The timeout and the buffers are configured on the socket by the caller.
+use ::embassy_net::{
+    tcp::{ConnectError, TcpSocket},
+    IpEndpoint,
+};
+use ::embedded_io_async::ErrorKind;
+
+use crate::*;
+
+/// Connect the socket to the server, send a ping request and return a future response.
+///
+/// The hostname is sent in the handshake, and the port of the endpoint is used for both.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn run(stack: embassy_net::Stack<'_>) {
+/// use craftping::embassy::ping;
+/// use embassy_net::{tcp::TcpSocket, Ipv4Address};
+///
+/// let mut rx_buffer = [0; 4096];
+/// let mut tx_buffer = [0; 256];
+/// let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+/// let endpoint = (Ipv4Address::new(192, 168, 0, 10), 25565);
+/// let response = ping(&mut socket, endpoint, "192.168.0.10").await.unwrap();
+/// # }
+/// ```
+pub async fn ping(
+    socket: &mut TcpSocket<'_>,
+    endpoint: impl Into<IpEndpoint>,
+    hostname: &str,
+) -> Result<Response> {
+    ping_with_config(socket, endpoint, hostname, &PingConfig::default()).await
+}
+
+/// Connect the socket to the server, send a ping request with the options and return a future response.
+///
+/// See also [`PingConfig`](PingConfig).
+pub async fn ping_with_config(
+    socket: &mut TcpSocket<'_>,
+    endpoint: impl Into<IpEndpoint>,
+    hostname: &str,
+    config: &PingConfig,
+) -> Result<Response> {
+    let endpoint = endpoint.into();
+    socket
+        .connect(endpoint)
+        .await
+        .map_err(|error| Error::EmbeddedIo(connect_error_kind(error)))?;
+    let response =
+        crate::embedded_io_async::ping_with_config(socket, hostname, endpoint.port, config).await;
+    socket.close();
+    response
+}
+
+fn connect_error_kind(error: ConnectError) -> ErrorKind {
+    match error {
+        ConnectError::InvalidState => ErrorKind::InvalidInput,
+        ConnectError::ConnectionReset => ErrorKind::ConnectionRefused,
+        ConnectError::TimedOut => ErrorKind::TimedOut,
+        ConnectError::NoRoute => ErrorKind::AddrNotAvailable,
+    }
+}
@@ -0,0 +1,209 @@
+//! Provides blocking [`ping`](ping) function for `embedded-io` streams, without `std`.
+//!
+//! The [`ping`](ping) function here works like [`sync::ping`](crate::sync::ping) on the microcontrollers,
+//! e.g. over the TCP socket of an ESP32 Wi-Fi stack.
+//! If you want to send ping in an asynchronous context, see [`embedded_io_async`](crate::embedded_io_async) module.
+use core::convert::TryInto;
+
+use ::embedded_io::{Read, ReadExactError, Write};
+
+use crate::*;
+
+/// Send a ping request to the server and wait for the response.
+///
+/// See also [`Response`](Response).
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn run<S: embedded_io::Read + embedded_io::Write>(mut socket: S) {
+/// use craftping::embedded_io::ping;
+///
+/// let response = ping(&mut socket, "my.server.com", 25565).unwrap();
+/// # }
+/// ```
+pub fn ping<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    ping_with_config(stream, hostname, port, &PingConfig::default())
+}
+
+/// Send a ping request to the server and wait for the response,
+/// rejecting the response if it exceeds the limits.
+///
+/// Lowering the limits is recommended on the devices with a small heap.
+pub fn ping_with_limits<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    limits: &Limits,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    ping_with_config(stream, hostname, port, &PingConfig::from(*limits))
+}
+
+/// Send a ping request to the server with the options and wait for the response.
+///
+/// See also [`PingConfig`](PingConfig).
+pub fn ping_with_config<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    match request_latest(stream, hostname, port, config) {
+        Ok(response) => Ok(response),
+        Err(error) if !config.legacy_fallback => Err(error),
+        Err(_) => request_legacy(
+            stream,
+            &build_legacy_request(hostname, port, config),
+            &config.limits,
+        ),
+    }
+}
+
+/// Send a ping request of the current protocol (1.7 and later) to the server and wait for the response.
+///
+/// Unlike [`ping`](ping), it does not fall back to the legacy protocol.
+pub fn ping_latest<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    request_latest(stream, hostname, port, &PingConfig::default())
+}
+
+/// Send a ping request of the legacy protocol (1.6) to the server and wait for the response.
+///
+/// `hostname` and `port` are sent to the server as the 1.6 client does. Some proxies reject the request without them.
+pub fn ping_legacy<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    request_legacy(
+        stream,
+        &build_legacy_request(hostname, port, &PingConfig::default()),
+        &Limits::default(),
+    )
+}
+
+fn request_latest<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    let limits = &config.limits;
+    let request = build_latest_request(hostname, port, config)?;
+    stream.write_all(&request).map_err(io_error)?;
+    stream.flush().map_err(io_error)?;
+
+    let _length = read_varint(stream)?;
+    let packet_id = read_varint(stream)?;
+    let response_length = read_varint(stream)?;
+    if packet_id != 0x00
+        || response_length < 0
+        || response_length as usize > limits.max_response_size
+    {
+        return Err(Error::UnsupportedProtocol);
+    }
+    let mut buffer = vec![0; response_length as usize];
+    stream.read_exact(&mut buffer).map_err(read_exact_error)?;
+
+    let mut raw = decode_latest_response(&buffer, limits)?;
+    raw.raw_json = buffer;
+    raw.try_into()
+}
+
+fn request_legacy<Stream>(stream: &mut Stream, request: &[u8], limits: &Limits) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    stream.write_all(request).map_err(io_error)?;
+    stream.flush().map_err(io_error)?;
+
+    // read as long as the header tells, since some servers keep the connection open
+    let mut header = [0u8; 3];
+    stream.read_exact(&mut header).map_err(read_exact_error)?;
+    let length = legacy_body_length(header, limits)?;
+    let mut buffer = header.to_vec();
+    buffer.resize(header.len() + length, 0);
+    stream
+        .read_exact(&mut buffer[header.len()..])
+        .map_err(read_exact_error)?;
+
+    let response = decode_legacy(&buffer)?;
+    parse_legacy(&response, buffer)
+}
+
+fn read_varint<Stream>(stream: &mut Stream) -> Result<i32>
+where
+    Stream: Read,
+{
+    let mut buffer = [0u8];
+    let mut result = 0;
+    let mut read_count = 0u32;
+    loop {
+        stream.read_exact(&mut buffer).map_err(read_exact_error)?;
+        result |= (buffer[0] as i32 & LAST_SEVEN_BITS)
+            .checked_shl(7 * read_count)
+            .ok_or(Error::UnsupportedProtocol)?;
+
+        read_count += 1;
+        if read_count > 5 {
+            break Err(Error::UnsupportedProtocol);
+        } else if (buffer[0] & NEXT_BYTE_EXISTS) == 0 {
+            break Ok(result);
+        }
+    }
+}
+
+pub(crate) fn io_error<E: ::embedded_io::Error>(error: E) -> Error {
+    Error::EmbeddedIo(error.kind())
+}
+
+// the connection closed in the middle of a packet, which is `UnexpectedEof` as in the std streams
+// unless `std` feature is disabled and `embedded-io` has no such kind
+pub(crate) fn read_exact_error<E: ::embedded_io::Error>(error: ReadExactError<E>) -> Error {
+    match error {
+        #[cfg(feature = "std")]
+        ReadExactError::UnexpectedEof => Error::Io(std::io::ErrorKind::UnexpectedEof.into()),
+        #[cfg(not(feature = "std"))]
+        ReadExactError::UnexpectedEof => Error::EmbeddedIo(::embedded_io::ErrorKind::Other),
+        ReadExactError::Other(error) => io_error(error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serialize_varint() {
+        let mut buffer = vec![];
+        let samples = [-2147483648, -1, 0, 1, 2147483647];
+        for &i in samples.iter() {
+            buffer.clear();
+            write_varint(&mut buffer, i);
+            let deserialized = read_varint(&mut buffer.as_slice()).unwrap();
+            assert_eq!(i, deserialized);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn truncated_is_unexpected_eof() {
+        match read_varint(&mut &[0x80u8][..]) {
+            Err(Error::Io(error)) => assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof),
+            result => panic!("{:?}", result),
+        }
+    }
+}
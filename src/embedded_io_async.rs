@@ -0,0 +1,187 @@
+//! Provides asynchronous [`ping`](ping) function for `embedded-io-async` streams, without `std`.
+//!
+//! The [`ping`](ping) function here works like [`embedded_io::ping`](crate::embedded_io::ping)
+//! in the async firmwares, e.g. on the executor of embassy.
+use core::convert::TryInto;
+
+use ::embedded_io_async::{Read, Write};
+
+use crate::{
+    embedded_io::{io_error, read_exact_error},
+    *,
+};
+
+/// Send a ping request to the server and return a future response.
+///
+/// See also [`Response`](Response).
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn run<S: embedded_io_async::Read + embedded_io_async::Write>(mut socket: S) {
+/// use craftping::embedded_io_async::ping;
+///
+/// let response = ping(&mut socket, "my.server.com", 25565).await.unwrap();
+/// # }
+/// ```
+pub async fn ping<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    ping_with_config(stream, hostname, port, &PingConfig::default()).await
+}
+
+/// Send a ping request to the server and return a future response,
+/// rejecting the response if it exceeds the limits.
+///
+/// Lowering the limits is recommended on the devices with a small heap.
+pub async fn ping_with_limits<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    limits: &Limits,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    ping_with_config(stream, hostname, port, &PingConfig::from(*limits)).await
+}
+
+/// Send a ping request to the server with the options and return a future response.
+///
+/// See also [`PingConfig`](PingConfig).
+pub async fn ping_with_config<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    match request_latest(stream, hostname, port, config).await {
+        Ok(response) => Ok(response),
+        Err(error) if !config.legacy_fallback => Err(error),
+        Err(_) => {
+            request_legacy(
+                stream,
+                &build_legacy_request(hostname, port, config),
+                &config.limits,
+            )
+            .await
+        }
+    }
+}
+
+/// Send a ping request of the current protocol (1.7 and later) to the server and return a future response.
+///
+/// Unlike [`ping`](ping), it does not fall back to the legacy protocol.
+pub async fn ping_latest<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    request_latest(stream, hostname, port, &PingConfig::default()).await
+}
+
+/// Send a ping request of the legacy protocol (1.6) to the server and return a future response.
+///
+/// `hostname` and `port` are sent to the server as the 1.6 client does. Some proxies reject the request without them.
+pub async fn ping_legacy<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    request_legacy(
+        stream,
+        &build_legacy_request(hostname, port, &PingConfig::default()),
+        &Limits::default(),
+    )
+    .await
+}
+
+async fn request_latest<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    let limits = &config.limits;
+    let request = build_latest_request(hostname, port, config)?;
+    stream.write_all(&request).await.map_err(io_error)?;
+    stream.flush().await.map_err(io_error)?;
+
+    let _length = read_varint(stream).await?;
+    let packet_id = read_varint(stream).await?;
+    let response_length = read_varint(stream).await?;
+    if packet_id != 0x00
+        || response_length < 0
+        || response_length as usize > limits.max_response_size
+    {
+        return Err(Error::UnsupportedProtocol);
+    }
+    let mut buffer = vec![0; response_length as usize];
+    stream
+        .read_exact(&mut buffer)
+        .await
+        .map_err(read_exact_error)?;
+
+    let mut raw = decode_latest_response(&buffer, limits)?;
+    raw.raw_json = buffer;
+    raw.try_into()
+}
+
+async fn request_legacy<Stream>(
+    stream: &mut Stream,
+    request: &[u8],
+    limits: &Limits,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    stream.write_all(request).await.map_err(io_error)?;
+    stream.flush().await.map_err(io_error)?;
+
+    // read as long as the header tells, since some servers keep the connection open
+    let mut header = [0u8; 3];
+    stream
+        .read_exact(&mut header)
+        .await
+        .map_err(read_exact_error)?;
+    let length = legacy_body_length(header, limits)?;
+    let mut buffer = header.to_vec();
+    buffer.resize(header.len() + length, 0);
+    stream
+        .read_exact(&mut buffer[header.len()..])
+        .await
+        .map_err(read_exact_error)?;
+
+    let response = decode_legacy(&buffer)?;
+    parse_legacy(&response, buffer)
+}
+
+async fn read_varint<Stream>(stream: &mut Stream) -> Result<i32>
+where
+    Stream: Read,
+{
+    let mut buffer = [0u8];
+    let mut result = 0;
+    let mut read_count = 0u32;
+    loop {
+        stream
+            .read_exact(&mut buffer)
+            .await
+            .map_err(read_exact_error)?;
+        result |= (buffer[0] as i32 & LAST_SEVEN_BITS)
+            .checked_shl(7 * read_count)
+            .ok_or(Error::UnsupportedProtocol)?;
+
+        read_count += 1;
+        if read_count > 5 {
+            break Err(Error::UnsupportedProtocol);
+        } else if (buffer[0] & NEXT_BYTE_EXISTS) == 0 {
+            break Ok(result);
+        }
+    }
+}
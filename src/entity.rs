@@ -1,29 +1,71 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
-use std::{convert::TryFrom, fmt};
+use core::{convert::TryFrom, fmt};
 
 use crate::Error;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub(crate) struct RawLatest {
     pub version: Version,
     pub players: Players,
     pub description: RawDescription,
+    #[serde(
+        default,
+        deserialize_with = "crate::limits::favicon",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub favicon: Option<String>,
-    #[serde(rename = "enforcesSecureChat")]
+    #[serde(rename = "enforcesSecureChat", skip_serializing_if = "Option::is_none")]
     pub enforces_secure_chat: Option<bool>,
-    #[serde(rename = "previewsChat")]
+    #[serde(rename = "previewsChat", skip_serializing_if = "Option::is_none")]
     pub previews_chat: Option<bool>,
-    #[serde(rename = "modinfo")]
+    #[serde(rename = "modinfo", skip_serializing_if = "Option::is_none")]
     pub mod_info: Option<ModInfo>,
-    #[serde(rename = "forgeData")]
+    #[serde(rename = "forgeData", skip_serializing_if = "Option::is_none")]
     pub forge_data: Option<ForgeData>,
+    #[serde(flatten)]
+    pub extras: Map<String, Value>,
     #[serde(skip)]
     pub raw_json: Vec<u8>,
 }
 
+impl From<&Response> for RawLatest {
+    fn from(response: &Response) -> Self {
+        Self {
+            version: Version {
+                name: response.version.clone(),
+                protocol: response.protocol,
+            },
+            players: Players {
+                max: response.max_players,
+                online: response.online_players,
+                sample: response.sample.clone(),
+            },
+            description: RawDescription::Chat(response.description.clone()),
+            favicon: response
+                .favicon
+                .as_ref()
+                .map(|favicon| format!("data:image/png;base64,{}", STANDARD.encode(favicon))),
+            enforces_secure_chat: response.enforces_secure_chat,
+            previews_chat: response.previews_chat,
+            mod_info: response.mod_info.clone(),
+            forge_data: response.forge_data.clone(),
+            extras: response.extras.clone(),
+            raw_json: vec![],
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[non_exhaustive]
 /// A ping response returned from server.
 /// The response schema can be altered anytime, thus `#[non_exhaustive]`.
@@ -56,20 +98,283 @@ pub struct Response {
     /// See also [the minecraft protocol wiki](https://wiki.vg/Minecraft_Forge_Handshake#FML2_protocol_.281.13_-_Current.29)
     /// for the [`ForgeData`](ForgeData) format.
     pub forge_data: Option<ForgeData>,
+    #[serde(default)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    /// The fields of the response which are not recognized by this crate.
+    /// It is always empty for legacy responses.
+    pub extras: Map<String, Value>,
     /// The raw response returned from the server.
     /// It is `Vec<u8>` because server is not guaranteed to return valid UTF-8,
     /// even not a json at all.
     #[serde(skip)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
     pub(crate) raw: Vec<u8>,
 }
 
 impl Response {
+    /// Create a response with the version name, the protocol number and the description,
+    /// and no players online out of 20.
+    ///
+    /// The other fields can be set afterwards, e.g. to answer the ping requests with the [`server`](crate::server) module.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use craftping::{Chat, Response};
+    ///
+    /// let mut response = Response::new("1.20.4", 765, Chat::from_legacy("§cUnder maintenance"));
+    /// response.max_players = 0;
+    /// ```
+    pub fn new(version: impl Into<String>, protocol: i32, description: Chat) -> Response {
+        Response {
+            version: version.into(),
+            protocol,
+            enforces_secure_chat: None,
+            previews_chat: None,
+            max_players: 20,
+            online_players: 0,
+            sample: None,
+            description,
+            favicon: None,
+            mod_info: None,
+            forge_data: None,
+            extras: Map::new(),
+            raw: vec![],
+        }
+    }
+
+    /// Parse the status JSON, e.g. a [`raw`](Response::raw) response stored before, without any network.
+    ///
+    /// The default [`Limits`](crate::Limits) apply. See also [`from_status_json_with_limits`](Response::from_status_json_with_limits).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use craftping::Response;
+    ///
+    /// let json = br#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":3},"description":"A"}"#;
+    /// let response = Response::from_status_json(json).unwrap();
+    /// assert_eq!(response.online_players, 3);
+    /// assert_eq!(response.raw(), json);
+    /// ```
+    pub fn from_status_json(json: &[u8]) -> Result<Response, Error> {
+        Self::from_status_json_with_limits(json, &crate::Limits::default())
+    }
+
+    /// Parse the status JSON with the limits, without any network.
+    ///
+    /// See also [`from_status_json`](Response::from_status_json).
+    pub fn from_status_json_with_limits(
+        json: &[u8],
+        limits: &crate::Limits,
+    ) -> Result<Response, Error> {
+        let mut raw = crate::decode_latest_response(json, limits)?;
+        raw.raw_json = json.to_vec();
+        raw.try_into()
+    }
+
     /// The raw response returned from the server.
     /// It is `Vec<u8>` because server is not guaranteed to return valid UTF-8,
     /// even not a json at all.
     pub fn raw(&self) -> &[u8] {
         &self.raw
     }
+
+    /// The description split into lines, as the server list renders it.
+    ///
+    /// See also [`Chat::lines`](Chat::lines).
+    pub fn motd_lines(&self) -> Vec<Chat> {
+        self.description.lines()
+    }
+
+    /// A hash of the fields identifying the server, to tell the same server behind multiple addresses.
+    ///
+    /// The version, the protocol, the MOTD text (without styles), the installed mods and the favicon are hashed.
+    /// Volatile fields like the player counts and the sample are not.
+    /// The hash is stable across platforms and releases of this crate.
+    pub fn fingerprint(&self) -> u64 {
+        let mut mods: Vec<(&str, &str)> = vec![];
+        if let Some(mod_info) = &self.mod_info {
+            mods.extend(
+                mod_info
+                    .mod_list
+                    .iter()
+                    .map(|item| (item.mod_id.as_str(), item.version.as_str())),
+            );
+        }
+        if let Some(forge_data) = &self.forge_data {
+            mods.extend(
+                forge_data
+                    .mods
+                    .iter()
+                    .map(|item| (item.mod_id.as_str(), item.mod_marker.as_str())),
+            );
+        }
+        mods.sort_unstable();
+        mods.dedup();
+
+        let motd = self.description.plain_text();
+        let motd = motd.split_whitespace().collect::<Vec<_>>().join(" ");
+        let favicon = self.favicon.as_deref().map(fnv1a);
+
+        let mut fields = vec![];
+        fields.push(self.version.as_bytes().to_vec());
+        fields.push(self.protocol.to_be_bytes().to_vec());
+        fields.push(motd.into_bytes());
+        for (mod_id, version) in mods {
+            fields.push(mod_id.as_bytes().to_vec());
+            fields.push(version.as_bytes().to_vec());
+        }
+        if let Some(favicon) = favicon {
+            fields.push(favicon.to_be_bytes().to_vec());
+        }
+        // 0xff never appears in UTF-8, so it separates the variable-length fields unambiguously
+        fnv1a(&fields.join(&0xff))
+    }
+
+    /// A copy of the response with the player names and ids in the sample replaced with their hashes,
+    /// for storing or publishing responses without identifying the players.
+    ///
+    /// The same player is hashed into the same value, so the players can still be told apart.
+    /// Note that the hash is not cryptographic and player names are easy to guess,
+    /// so it should not be considered as irreversible.
+    /// The raw response is also removed, since it contains the original sample.
+    pub fn anonymized(&self) -> Response {
+        let sample = self.sample.as_ref().map(|sample| {
+            sample
+                .iter()
+                .map(|player| Player {
+                    name: format!("{:016x}", fnv1a(player.name.as_bytes())),
+                    id: format!("{:016x}", fnv1a(player.id.as_bytes())),
+                })
+                .collect()
+        });
+        Response {
+            sample,
+            raw: vec![],
+            ..self.clone()
+        }
+    }
+
+    /// A copy of the response without the favicon.
+    pub fn without_favicon(&self) -> Response {
+        Response {
+            favicon: None,
+            ..self.clone()
+        }
+    }
+
+    /// Deserialize the unrecognized field `key` of the response.
+    /// Returns `Ok(None)` if the field does not exist.
+    ///
+    /// See also [`extras`](Response::extras).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn run(response: craftping::Response) -> craftping::Result<()> {
+    /// let queue_length: Option<u32> = response.get_extra("queueLength")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_extra<'a, T: Deserialize<'a>>(&'a self, key: &str) -> Result<Option<T>, Error> {
+        self.extras
+            .get(key)
+            .map(|value| T::deserialize(value).map_err(|_| Error::UnsupportedProtocol))
+            .transpose()
+    }
+
+    /// Serialize the response into the status JSON, in the shape the vanilla server sends.
+    ///
+    /// Unlike serializing the response, the version and the players are nested objects,
+    /// the favicon is a `data:image/png;base64,` URI, the unset fields are omitted and the extras are flattened,
+    /// so it can be sent to the clients or replayed by a caching proxy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use craftping::{Chat, Response};
+    ///
+    /// let response = Response::new("1.20.4", 765, Chat::from_legacy("A Minecraft Server"));
+    /// assert_eq!(
+    ///     response.to_status_json(),
+    ///     r#"{"description":{"extra":[{"text":"A Minecraft Server"}],"text":""},"players":{"max":20,"online":0},"version":{"name":"1.20.4","protocol":765}}"#,
+    /// );
+    /// ```
+    pub fn to_status_json(&self) -> String {
+        let mut status =
+            serde_json::to_value(RawLatest::from(self)).expect("the status is always serializable");
+        status["description"] = compact_chat(&self.description);
+        status.to_string()
+    }
+
+    /// Describe the changes from this response to the other, newer one.
+    ///
+    /// It is useful to compare the stored snapshots of a server.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn run(yesterday: craftping::Response, today: craftping::Response) {
+    /// let diff = yesterday.diff(&today);
+    /// for player in &diff.joined {
+    ///     println!("{} joined", player.name);
+    /// }
+    /// println!("{:+} player(s) online", diff.online_players);
+    /// # }
+    /// ```
+    pub fn diff(&self, other: &Response) -> ResponseDiff {
+        let old_sample = self.sample.as_deref().unwrap_or_default();
+        let new_sample = other.sample.as_deref().unwrap_or_default();
+        let missing_from =
+            |sample: &[Player], player: &&Player| !sample.iter().any(|other| other.id == player.id);
+        ResponseDiff {
+            online_players: other.online_players as isize - self.online_players as isize,
+            max_players: other.max_players as isize - self.max_players as isize,
+            joined: new_sample
+                .iter()
+                .filter(|player| missing_from(old_sample, player))
+                .cloned()
+                .collect(),
+            left: old_sample
+                .iter()
+                .filter(|player| missing_from(new_sample, player))
+                .cloned()
+                .collect(),
+            description: (self.description != other.description).then(|| other.description.clone()),
+            favicon_changed: self.favicon != other.favicon,
+            version_changed: self.version != other.version || self.protocol != other.protocol,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+/// The changes between two responses of a server, returned by [`Response::diff`](Response::diff).
+///
+/// The players are compared by their ids in the samples, so only the players in the samples are reported.
+pub struct ResponseDiff {
+    /// The change of the number of the connected players.
+    pub online_players: isize,
+    /// The change of the maximum number of the connected players.
+    pub max_players: isize,
+    /// The players which appeared in the sample.
+    pub joined: Vec<Player>,
+    /// The players which disappeared from the sample.
+    pub left: Vec<Player>,
+    /// The new description, if it changed.
+    pub description: Option<Chat>,
+    /// If the favicon changed, including being added or removed.
+    pub favicon_changed: bool,
+    /// If the version name or the protocol number changed.
+    pub version_changed: bool,
+}
+
+impl ResponseDiff {
+    /// If nothing changed.
+    pub fn is_empty(&self) -> bool {
+        *self == ResponseDiff::default()
+    }
 }
 
 impl TryFrom<RawLatest> for Response {
@@ -99,11 +404,142 @@ impl TryFrom<RawLatest> for Response {
             favicon,
             mod_info: raw.mod_info,
             forge_data: raw.forge_data,
+            extras: raw.extras,
             raw: raw.raw_json,
         })
     }
 }
 
+impl From<Response> for Value {
+    /// Convert the response into a JSON value with the same fields as the response.
+    ///
+    /// Unlike serializing the response, the favicon is a `data:image/png;base64,` URI.
+    fn from(response: Response) -> Self {
+        let favicon = response
+            .favicon
+            .map(|favicon| format!("data:image/png;base64,{}", STANDARD.encode(favicon)));
+        json!({
+            "version": response.version,
+            "protocol": response.protocol,
+            "enforces_secure_chat": response.enforces_secure_chat,
+            "previews_chat": response.previews_chat,
+            "max_players": response.max_players,
+            "online_players": response.online_players,
+            "sample": response.sample,
+            "description": response.description,
+            "favicon": favicon,
+            "mod_info": response.mod_info,
+            "forge_data": response.forge_data,
+            "extras": response.extras,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+/// The buffer reused across the pings, used by `ping_into` functions.
+///
+/// The memory of the raw response is reused to receive the next one.
+/// The fields of the response, like the description and the player names, are allocated anew by each ping.
+pub struct ResponseBuf {
+    response: Option<Response>,
+    #[cfg(any(feature = "sync", feature = "async-tokio", feature = "async-futures"))]
+    buffer: Vec<u8>,
+}
+
+impl ResponseBuf {
+    /// Create an empty buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The response of the last successful ping with this buffer.
+    pub fn response(&self) -> Option<&Response> {
+        self.response.as_ref()
+    }
+
+    /// Take the response of the last successful ping out of this buffer.
+    pub fn take_response(&mut self) -> Option<Response> {
+        self.response.take()
+    }
+
+    #[cfg(any(feature = "sync", feature = "async-tokio", feature = "async-futures"))]
+    // drop the last response, and return the buffer with the memory of its raw response
+    pub(crate) fn reclaim(&mut self) -> &mut Vec<u8> {
+        if let Some(response) = self.response.take() {
+            if response.raw.capacity() > self.buffer.capacity() {
+                self.buffer = response.raw;
+            }
+        }
+        &mut self.buffer
+    }
+
+    #[cfg(any(feature = "sync", feature = "async-tokio", feature = "async-futures"))]
+    pub(crate) fn store(&mut self, response: Response) -> &Response {
+        self.response.insert(response)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A wrapper of [`Response`](Response) which keeps the raw response through serialization.
+///
+/// [`Response`](Response) skips the raw response when serialized.
+/// This wrapper serializes the fields of the response along with the raw response,
+/// base64-encoded in the `raw` field, and restores it when deserialized.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn run(response: craftping::Response) {
+/// use craftping::ResponseWithRaw;
+///
+/// let json = serde_json::to_string(&ResponseWithRaw(response)).unwrap();
+/// let ResponseWithRaw(restored) = serde_json::from_str(&json).unwrap();
+/// # }
+/// ```
+pub struct ResponseWithRaw(pub Response);
+
+#[derive(Serialize)]
+struct SerializeWithRaw<'a> {
+    #[serde(flatten)]
+    response: &'a Response,
+    raw: String,
+}
+
+#[derive(Deserialize)]
+struct DeserializeWithRaw {
+    #[serde(flatten)]
+    response: Response,
+    #[serde(default)]
+    raw: String,
+}
+
+impl Serialize for ResponseWithRaw {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializeWithRaw {
+            response: &self.0,
+            raw: STANDARD.encode(&self.0.raw),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ResponseWithRaw {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let DeserializeWithRaw { mut response, raw } =
+            DeserializeWithRaw::deserialize(deserializer)?;
+        response.raw = STANDARD
+            .decode(raw)
+            .map_err(<D::Error as serde::de::Error>::custom)?;
+        Ok(Self(response))
+    }
+}
+
+impl From<Response> for ResponseWithRaw {
+    fn from(response: Response) -> Self {
+        Self(response)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub(crate) struct Version {
     pub name: String,
@@ -114,10 +550,16 @@ pub(crate) struct Version {
 pub(crate) struct Players {
     pub max: usize,
     pub online: usize,
+    #[serde(
+        default,
+        deserialize_with = "crate::limits::sample",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub sample: Option<Vec<Player>>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The sample players' information.
 pub struct Player {
     /// The name of the player.
@@ -127,6 +569,21 @@ pub struct Player {
     pub id: String,
 }
 
+impl Player {
+    /// The name parsed as a text with legacy formatting codes (`§`).
+    ///
+    /// Some servers put advertisements with formatting codes in the sample instead of the player names.
+    /// See also [`Chat::from_legacy`](Chat::from_legacy).
+    pub fn name_chat(&self) -> Chat {
+        Chat::from_legacy(&self.name)
+    }
+
+    /// The name without legacy formatting codes (`§`).
+    pub fn plain_name(&self) -> String {
+        self.name_chat().plain_text()
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(untagged)]
 pub(crate) enum RawDescription {
@@ -135,18 +592,20 @@ pub(crate) enum RawDescription {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The mod information object used in FML protocol (version 1.7 - 1.12).
 pub struct ModInfo {
     #[serde(rename = "type")]
     /// The field `type` of `modinfo`. It should be FML if forge is installed.
     pub mod_type: String,
-    #[serde(rename = "modList")]
+    #[serde(rename = "modList", deserialize_with = "crate::limits::mod_info_list")]
     /// The list of the mod installed on the server.
     /// See also [`ModInfoItem`](ModInfoItem)
     pub mod_list: Vec<ModInfoItem>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The information of an installed mod.
 pub struct ModInfoItem {
     #[serde(rename = "modid")]
@@ -157,11 +616,13 @@ pub struct ModInfoItem {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The forge information object used in FML2 protocol (version 1.13 - current).
 pub struct ForgeData {
     /// The list of the channels used by the mods.
     /// See [the minecraft protocol wiki](https://wiki.vg/Plugin_channels) for more information.
     pub channels: Vec<ForgeChannel>,
+    #[serde(deserialize_with = "crate::limits::forge_mods")]
     /// The list of the mods installed on the server.
     pub mods: Vec<ForgeMod>,
     #[serde(rename = "fmlNetworkVersion")]
@@ -169,6 +630,7 @@ pub struct ForgeData {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The information of the channels used by the mods.
 ///
 /// See [the minecraft protocol wiki](https://wiki.vg/Plugin_channels) for more information.
@@ -184,6 +646,7 @@ pub struct ForgeChannel {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The information of an installed mod.
 pub struct ForgeMod {
     #[serde(rename = "modId")]
@@ -194,7 +657,8 @@ pub struct ForgeMod {
     pub mod_marker: String,
 }
 
-#[derive(Deserialize, Serialize, Default, Clone)]
+#[derive(Deserialize, Serialize, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The chat component used in the server description.
 ///
 /// See also [the minecraft protocol wiki](https://wiki.vg/Chat#Current_system_.28JSON_Chat.29).
@@ -219,12 +683,270 @@ pub struct Chat {
     /// The color which the text and the extras should have.
     /// `None` to use default color.
     pub color: Option<String>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "crate::limits::chat_extra")]
     /// The extra text components following this text.
     /// They should inherit this chat component's properties (bold, italic, etc.) but can also override the properties.
     pub extra: Vec<Chat>,
 }
 
+impl Chat {
+    /// Parse a text with legacy formatting codes (`§` followed by a code) into a `Chat`.
+    ///
+    /// The returned chat has an empty text, and its extras are the runs of the text with the same style.
+    /// As in the vanilla client, a color code resets the formats, and `§r` resets both the color and the formats.
+    /// Unknown codes are removed.
+    pub fn from_legacy(text: &str) -> Chat {
+        let mut chat = Chat::default();
+        let mut style = Chat::default();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c != '\u{00a7}' {
+                style.text.push(c);
+                continue;
+            }
+            let code = match chars.next() {
+                Some(code) => code.to_ascii_lowercase(),
+                None => break,
+            };
+            let current = Chat {
+                text: String::new(),
+                ..style.clone()
+            };
+            let next = match code {
+                'k' => Chat {
+                    obfuscated: true,
+                    ..current
+                },
+                'l' => Chat {
+                    bold: true,
+                    ..current
+                },
+                'm' => Chat {
+                    strikethrough: true,
+                    ..current
+                },
+                'n' => Chat {
+                    underlined: true,
+                    ..current
+                },
+                'o' => Chat {
+                    italic: true,
+                    ..current
+                },
+                'r' => Chat::default(),
+                code => match NAMED_COLORS.iter().find(|(_, legacy, _)| *legacy == code) {
+                    Some((name, _, _)) => Chat {
+                        color: Some(name.to_string()),
+                        ..Default::default()
+                    },
+                    None => continue,
+                },
+            };
+            let previous = core::mem::replace(&mut style, next);
+            if !previous.text.is_empty() {
+                chat.extra.push(previous);
+            }
+        }
+        if !style.text.is_empty() {
+            chat.extra.push(style);
+        }
+        chat
+    }
+
+    /// Split the chat into lines on every newline.
+    ///
+    /// Each line is a `Chat` with an empty text whose extras are the pieces of the line.
+    /// The properties each piece inherited from its parents are set on the piece itself,
+    /// so the lines are styled the same as they were in the original chat.
+    pub fn lines(&self) -> Vec<Chat> {
+        let mut segments = vec![];
+        self.flatten(&Chat::default(), &mut segments);
+
+        let mut lines = vec![Chat::default()];
+        for segment in segments {
+            for (index, part) in segment.text.split('\n').enumerate() {
+                if index > 0 {
+                    lines.push(Chat::default());
+                }
+                if !part.is_empty() {
+                    let line = lines.last_mut().unwrap();
+                    line.extra.push(Chat {
+                        text: part.to_string(),
+                        ..segment.clone()
+                    });
+                }
+            }
+        }
+        lines
+    }
+
+    /// Render the chat as BBCode, for forums and server lists taking BBCode descriptions.
+    ///
+    /// Named colors are converted to their RGB values.
+    /// Obfuscated text is rendered as a plain text since BBCode has no equivalent.
+    /// `[` in the text is escaped as `&#91;`, so the text cannot open a tag by itself.
+    pub fn to_bbcode(&self) -> String {
+        let mut segments = vec![];
+        self.flatten(&Chat::default(), &mut segments);
+
+        let mut bbcode = String::new();
+        for segment in segments.iter().filter(|segment| !segment.text.is_empty()) {
+            let mut closing = vec![];
+            if let Some(rgb) = segment.color.as_deref().and_then(color_rgb) {
+                bbcode.push_str(&format!("[color=#{:06X}]", rgb));
+                closing.push("[/color]");
+            }
+            let tags = [
+                (segment.bold, "[b]", "[/b]"),
+                (segment.italic, "[i]", "[/i]"),
+                (segment.underlined, "[u]", "[/u]"),
+                (segment.strikethrough, "[s]", "[/s]"),
+            ];
+            for (enabled, open, close) in tags {
+                if enabled {
+                    bbcode.push_str(open);
+                    closing.push(close);
+                }
+            }
+            bbcode.push_str(&segment.text.replace('[', "&#91;"));
+            for tag in closing.iter().rev() {
+                bbcode.push_str(tag);
+            }
+        }
+        bbcode
+    }
+
+    /// The named color nearest to `color`, for clients before 1.16 which only support the named colors.
+    ///
+    /// `color` can be either a named color, which is returned as is, or `#RRGGBB`.
+    /// Returns `None` if the color cannot be recognized.
+    pub fn nearest_named_color(color: &str) -> Option<&'static str> {
+        let rgb = color_rgb(color)?;
+        let distance = |other: u32| -> u32 {
+            [16, 8, 0]
+                .iter()
+                .map(|shift| {
+                    let a = (rgb >> shift) & 0xff;
+                    let b = (other >> shift) & 0xff;
+                    a.abs_diff(b).pow(2)
+                })
+                .sum()
+        };
+        NAMED_COLORS
+            .iter()
+            .min_by_key(|(_, _, other)| distance(*other))
+            .map(|(name, _, _)| *name)
+    }
+
+    /// Replace every `#RRGGBB` color in the chat and its extras with the nearest named color.
+    ///
+    /// See also [`nearest_named_color`](Chat::nearest_named_color).
+    pub fn downsample_colors(&mut self) {
+        if let Some(color) = &self.color {
+            if let Some(named) = Self::nearest_named_color(color) {
+                self.color = Some(named.to_string());
+            }
+        }
+        for extra in &mut self.extra {
+            extra.downsample_colors();
+        }
+    }
+
+    /// The texts of the chat and its extras, without any styles, e.g. to print the description on a terminal.
+    pub fn plain_text(&self) -> String {
+        let mut text = self.text.clone();
+        for extra in &self.extra {
+            text.push_str(&extra.plain_text());
+        }
+        text
+    }
+
+    // Collect every text component in order, with the inherited properties applied and without extras
+    fn flatten(&self, parent: &Chat, segments: &mut Vec<Chat>) {
+        let segment = Chat {
+            text: self.text.clone(),
+            bold: parent.bold || self.bold,
+            italic: parent.italic || self.italic,
+            underlined: parent.underlined || self.underlined,
+            strikethrough: parent.strikethrough || self.strikethrough,
+            obfuscated: parent.obfuscated || self.obfuscated,
+            color: self.color.clone().or_else(|| parent.color.clone()),
+            extra: vec![],
+        };
+        segments.push(segment.clone());
+        for extra in &self.extra {
+            extra.flatten(&segment, segments);
+        }
+    }
+}
+
+// 64-bit FNV-1a, used where a hash must be stable
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// the named colors of the chat, with their legacy formatting codes and RGB values
+pub(crate) const NAMED_COLORS: [(&str, char, u32); 16] = [
+    ("black", '0', 0x000000),
+    ("dark_blue", '1', 0x0000aa),
+    ("dark_green", '2', 0x00aa00),
+    ("dark_aqua", '3', 0x00aaaa),
+    ("dark_red", '4', 0xaa0000),
+    ("dark_purple", '5', 0xaa00aa),
+    ("gold", '6', 0xffaa00),
+    ("gray", '7', 0xaaaaaa),
+    ("dark_gray", '8', 0x555555),
+    ("blue", '9', 0x5555ff),
+    ("green", 'a', 0x55ff55),
+    ("aqua", 'b', 0x55ffff),
+    ("red", 'c', 0xff5555),
+    ("light_purple", 'd', 0xff55ff),
+    ("yellow", 'e', 0xffff55),
+    ("white", 'f', 0xffffff),
+];
+
+// the RGB value of both named and `#RRGGBB` colors, or `None` for unknown colors
+pub(crate) fn color_rgb(color: &str) -> Option<u32> {
+    match color.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            u32::from_str_radix(hex, 16).ok()
+        }
+        Some(_) => None,
+        None => NAMED_COLORS
+            .iter()
+            .find(|(name, _, _)| *name == color)
+            .map(|(_, _, rgb)| *rgb),
+    }
+}
+
+// the chat without the unset styles, as the vanilla server omits them
+fn compact_chat(chat: &Chat) -> Value {
+    let mut compact = Map::new();
+    compact.insert("text".to_string(), chat.text.clone().into());
+    let styles = [
+        ("bold", chat.bold),
+        ("italic", chat.italic),
+        ("underlined", chat.underlined),
+        ("strikethrough", chat.strikethrough),
+        ("obfuscated", chat.obfuscated),
+    ];
+    for (style, enabled) in styles {
+        if enabled {
+            compact.insert(style.to_string(), true.into());
+        }
+    }
+    if let Some(color) = &chat.color {
+        compact.insert("color".to_string(), color.clone().into());
+    }
+    if !chat.extra.is_empty() {
+        let extra = chat.extra.iter().map(compact_chat).collect();
+        compact.insert("extra".to_string(), Value::Array(extra));
+    }
+    Value::Object(compact)
+}
+
 impl From<RawDescription> for Chat {
     fn from(description: RawDescription) -> Self {
         match description {
@@ -256,4 +978,128 @@ impl fmt::Debug for Chat {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_lines() {
+        let chat: Chat = serde_json::from_str(
+            r#"{
+                "text": "",
+                "bold": true,
+                "extra": [
+                    { "text": "Hello\nWor", "color": "red" },
+                    { "text": "ld" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let lines = chat.lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].extra.len(), 1);
+        assert_eq!(lines[0].extra[0].text, "Hello");
+        assert_eq!(lines[1].extra.len(), 2);
+        assert_eq!(lines[1].extra[0].text, "Wor");
+        assert_eq!(lines[1].extra[0].color.as_deref(), Some("red"));
+        assert!(lines[1].extra[1].bold);
+        assert_eq!(lines[1].extra[1].color, None);
+    }
+
+    #[test]
+    fn render_bbcode() {
+        let chat: Chat = serde_json::from_str(
+            r#"{ "text": "A ", "extra": [{ "text": "B", "bold": true, "color": "gold" }] }"#,
+        )
+        .unwrap();
+        assert_eq!(chat.to_bbcode(), "A [color=#FFAA00][b]B[/b][/color]");
+    }
+
+    #[test]
+    fn escape_bbcode_brackets() {
+        let chat = Chat::from_legacy("[url=http://evil]\u{a7}lJoin[/url]");
+        assert_eq!(
+            chat.to_bbcode(),
+            "&#91;url=http://evil][b]Join&#91;/url][/b]"
+        );
+    }
+
+    #[test]
+    fn round_trip_raw() {
+        let raw = br#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":0},"description":"A"}"#;
+        let mut latest: RawLatest = serde_json::from_slice(raw).unwrap();
+        latest.raw_json = raw.to_vec();
+        let response = Response::try_from(latest).unwrap();
+
+        let json = serde_json::to_string(&ResponseWithRaw(response)).unwrap();
+        let ResponseWithRaw(restored) = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.raw(), raw);
+        assert_eq!(restored.description.text, "A");
+    }
+
+    #[test]
+    fn diff_responses() {
+        let parse = |raw: &[u8]| {
+            let latest: RawLatest = serde_json::from_slice(raw).unwrap();
+            Response::try_from(latest).unwrap()
+        };
+        let old = parse(br#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":2,"sample":[{"name":"A","id":"a"}]},"description":"A"}"#);
+        let new = parse(br#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":1,"sample":[{"name":"B","id":"b"}]},"description":"A"}"#);
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.online_players, -1);
+        assert_eq!(diff.joined[0].name, "B");
+        assert_eq!(diff.left[0].name, "A");
+        assert!(diff.description.is_none() && !diff.version_changed);
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn serialize_status_json() {
+        let raw = br#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":1,"sample":[{"name":"A","id":"a"}]},"description":{"text":"A","color":"red"},"favicon":"data:image/png;base64,AAEC","queueLength":3}"#;
+        let response = Response::from_status_json(raw).unwrap();
+
+        let status: Value = serde_json::from_str(&response.to_status_json()).unwrap();
+        assert_eq!(status, serde_json::from_slice::<Value>(raw).unwrap());
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn round_trip_arbitrary() {
+        let seed: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let mut data = arbitrary::Unstructured::new(&seed);
+        while !data.is_empty() {
+            let response: Response = data.arbitrary().unwrap();
+            let restored =
+                Response::from_status_json(response.to_status_json().as_bytes()).unwrap();
+            assert_eq!(restored.version, response.version);
+            assert_eq!(restored.description, response.description);
+            assert_eq!(restored.sample, response.sample);
+            assert_eq!(restored.favicon, response.favicon);
+        }
+    }
+
+    #[test]
+    fn downsample_hex_color() {
+        assert_eq!(Chat::nearest_named_color("#FF0000"), Some("dark_red"));
+        assert_eq!(Chat::nearest_named_color("#0a0a0a"), Some("black"));
+        assert_eq!(Chat::nearest_named_color("gold"), Some("gold"));
+        assert_eq!(Chat::nearest_named_color("#GGGGGG"), None);
+    }
+
+    #[test]
+    fn parse_legacy_codes() {
+        let chat = Chat::from_legacy("\u{00a7}6\u{00a7}lBuy\u{00a7}r at \u{00a7}zshop");
+        assert_eq!(chat.extra.len(), 2);
+        assert_eq!(chat.extra[0].text, "Buy");
+        assert_eq!(chat.extra[0].color.as_deref(), Some("gold"));
+        assert!(chat.extra[0].bold);
+        assert_eq!(chat.extra[1].text, " at shop");
+        assert_eq!(chat.extra[1].color, None);
+        assert!(!chat.extra[1].bold);
+    }
+}
@@ -0,0 +1,146 @@
+//! Provides a corpus of the responses captured from the real servers, to replay them through the parser.
+//!
+//! Each [`Fixture`](Fixture) is what a server sent, and [`Fixture::replay`](Fixture::replay) parses it
+//! exactly as a ping over the network does, without any network.
+//! The malformed ones are included as well, so the regressions of the parser can be reproduced in the tests.
+//!
+//! # Examples
+//!
+//! ```
+//! use craftping::fixtures::FIXTURES;
+//!
+//! for fixture in FIXTURES {
+//!     assert_eq!(fixture.replay().is_ok(), fixture.valid, "{}", fixture.name);
+//! }
+//! ```
+use std::io::{self, Read, Write};
+
+use crate::{
+    server::build_status_packet, sync, testing::Reply, PingConfig, Response, Result, Strategy,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A response captured from a server.
+pub struct Fixture {
+    /// The name of the fixture, after the server software and its version.
+    pub name: &'static str,
+    /// The protocol the server answered with, either [`Modern`](Strategy::Modern) or [`Legacy16`](Strategy::Legacy16).
+    pub strategy: Strategy,
+    /// The status JSON for the current protocol, or the whole response packet for the legacy protocol.
+    pub data: &'static [u8],
+    /// `true` if the response is expected to be parsed successfully.
+    pub valid: bool,
+}
+
+// the status JSON of the current protocol in fixtures/<name>.json
+macro_rules! modern {
+    ($name:literal, $valid:literal) => {
+        Fixture {
+            name: $name,
+            strategy: Strategy::Modern,
+            data: include_bytes!(concat!("../fixtures/", $name, ".json")),
+            valid: $valid,
+        }
+    };
+}
+
+// the response packet of the legacy protocol in fixtures/<name>.bin
+macro_rules! legacy {
+    ($name:literal, $valid:literal) => {
+        Fixture {
+            name: $name,
+            strategy: Strategy::Legacy16,
+            data: include_bytes!(concat!("../fixtures/", $name, ".bin")),
+            valid: $valid,
+        }
+    };
+}
+
+/// Every fixture in the corpus.
+pub const FIXTURES: &[Fixture] = &[
+    modern!("vanilla-1.20.4", true),
+    modern!("paper-1.20.4", true),
+    modern!("forge-1.12.2", true),
+    modern!("forge-1.16.5", true),
+    modern!("forge-1.20.1", true),
+    modern!("bungeecord", true),
+    // the favicon without the data URI prefix once panicked the parser
+    modern!("short-favicon", false),
+    legacy!("legacy-1.6.4", true),
+    legacy!("legacy-truncated", false),
+    legacy!("legacy-missing-fields", false),
+];
+
+/// Find the fixture by the name.
+pub fn find(name: &str) -> Option<&'static Fixture> {
+    FIXTURES.iter().find(|fixture| fixture.name == name)
+}
+
+impl Fixture {
+    /// Parse the response as if the server sent it over the network.
+    pub fn replay(&self) -> Result<Response> {
+        let packet = self.packet();
+        let mut stream = ReplayStream(&packet);
+        match self.strategy {
+            Strategy::Modern => {
+                let config = PingConfig {
+                    legacy_fallback: false,
+                    ..Default::default()
+                };
+                sync::ping_with_config(&mut stream, "localhost", 25565, &config)
+            }
+            _ => sync::ping_legacy(&mut stream, "localhost", 25565),
+        }
+    }
+
+    /// The reply of [`MockServer`](crate::testing::MockServer) sending the response as is.
+    pub fn reply(&self) -> Reply {
+        Reply::Raw(self.packet())
+    }
+
+    // the bytes the server sent
+    fn packet(&self) -> Vec<u8> {
+        match self.strategy {
+            Strategy::Modern => build_status_packet(self.data),
+            _ => self.data.to_vec(),
+        }
+    }
+}
+
+// reads the captured response, and ignores the request
+struct ReplayStream<'a>(&'a [u8]);
+
+impl Read for ReplayStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for ReplayStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replay_fixtures() {
+        for fixture in FIXTURES {
+            assert_eq!(fixture.replay().is_ok(), fixture.valid, "{}", fixture.name);
+        }
+        let paper = find("paper-1.20.4").unwrap().replay().unwrap();
+        assert_eq!(paper.sample.unwrap().len(), 2);
+        assert!(paper.favicon.is_some());
+        let forge = find("forge-1.12.2").unwrap().replay().unwrap();
+        assert_eq!(forge.mod_info.unwrap().mod_list.len(), 4);
+        let legacy = find("legacy-1.6.4").unwrap().replay().unwrap();
+        assert_eq!(legacy.max_players, 20);
+    }
+}
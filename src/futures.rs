@@ -2,11 +2,20 @@
 //!
 //! The [`ping`](ping) function here sends a ping request, and returns a [`Future`](std::future::Future) resolves to a result of [`Response`](Response).
 //! If you want to send ping synchronously, see [`sync`](sync) module.
-use std::convert::TryInto;
+//!
+//! The functions take the stream by value and pin it while pinging, so a stream which is not [`Unpin`](Unpin)
+//! can be passed as is, and a mutable reference like `&mut stream` works for the other streams.
+use std::{future::Future, pin::pin, time::Duration};
 
-use ::futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use ::futures::{
+    future::{select, Either},
+    AsyncRead, AsyncWrite,
+};
 
-use crate::*;
+use crate::{
+    async_transport::{self, request_latest, request_legacy, FuturesIo},
+    *,
+};
 
 /// Send a ping request to the server and return a future response.
 ///
@@ -30,71 +39,294 @@ use crate::*;
 /// );
 /// # }
 /// ```
-pub async fn ping<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+pub async fn ping<Stream>(stream: Stream, hostname: &str, port: u16) -> Result<Response>
 where
-    Stream: AsyncRead + AsyncWrite + Unpin,
+    Stream: AsyncRead + AsyncWrite,
 {
-    match ping_latest(stream, hostname, port).await {
-        ok @ Ok(_) => ok,
-        Err(_) => ping_legacy(stream).await,
-    }
+    ping_with_limits(stream, hostname, port, &Limits::default()).await
 }
 
-async fn ping_latest<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+/// Send a ping request to the server and return a future response,
+/// rejecting the response if it exceeds the limits.
+///
+/// [`ping`](ping) uses the default [`Limits`](Limits).
+pub async fn ping_with_limits<Stream>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+    limits: &Limits,
+) -> Result<Response>
 where
-    Stream: AsyncRead + AsyncWrite + Unpin,
+    Stream: AsyncRead + AsyncWrite,
 {
-    let request = build_latest_request(hostname, port)?;
-    stream.write_all(&request).await?;
-    stream.flush().await?;
-
-    let _length = read_varint(stream).await?;
-    let packet_id = read_varint(stream).await?;
-    let response_length = read_varint(stream).await?;
-    if packet_id != 0x00 || response_length < 0 {
-        return Err(Error::UnsupportedProtocol);
-    }
-    let mut response_buffer = vec![0; response_length as usize];
-    stream.read_exact(&mut response_buffer).await?;
+    ping_with_config(stream, hostname, port, &PingConfig::from(*limits)).await
+}
+
+/// Send a ping request to the server with the options and return a future response.
+///
+/// See also [`PingConfig`](PingConfig).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{futures::ping_with_config, PingConfig};
+/// use async_std::net::TcpStream;
+///
+/// # async fn run() {
+/// let mut stream = TcpStream::connect(("127.0.0.1", 25565)).await.unwrap();
+/// let config = PingConfig {
+///     virtual_host: Some("my.server.com".to_string()),
+///     legacy_fallback: false,
+///     ..Default::default()
+/// };
+/// let response = ping_with_config(&mut stream, "127.0.0.1", 25565, &config).await.unwrap();
+/// # }
+/// ```
+pub async fn ping_with_config<Stream>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response>
+where
+    Stream: AsyncRead + AsyncWrite,
+{
+    async_transport::ping_with_config(&mut FuturesIo(pin!(stream)), hostname, port, config).await
+}
 
-    let mut raw = decode_latest_response(&response_buffer)?;
-    raw.raw_json = response_buffer;
-    raw.try_into()
+/// Send a ping request to the server and return a future response,
+/// failing with [`TimedOut`](std::io::ErrorKind::TimedOut) if the `timeout` future completes first.
+///
+/// The timeout is a future of the timer of the runtime in use, e.g. `async_std::task::sleep`,
+/// `smol::Timer::after` or `futures_timer::Delay::new`, so that no particular runtime is required.
+/// It bounds the exchange as a whole, including the legacy ping after the modern one fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::futures::ping_with_timeout;
+/// use async_std::{net::TcpStream, task::sleep};
+/// use std::time::Duration;
+///
+/// # async fn run() {
+/// let mut stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+/// let timeout = sleep(Duration::from_secs(5));
+/// let response = ping_with_timeout(&mut stream, "my.server.com", 25565, timeout)
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub async fn ping_with_timeout<Stream, Timeout>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+    timeout: Timeout,
+) -> Result<Response>
+where
+    Stream: AsyncRead + AsyncWrite,
+    Timeout: Future<Output = ()>,
+{
+    let config = PingConfig::default();
+    let ping = pin!(ping_with_config(stream, hostname, port, &config));
+    match select(ping, pin!(timeout)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(Error::Io(std::io::ErrorKind::TimedOut.into())),
+    }
 }
 
-async fn ping_legacy<Stream>(stream: &mut Stream) -> Result<Response>
+/// Send a ping request of the current protocol (1.7 and later) to the server and return a future response.
+///
+/// Unlike [`ping`](ping), it does not fall back to the legacy protocol,
+/// so the error of the current protocol is returned as is.
+pub async fn ping_latest<Stream>(stream: Stream, hostname: &str, port: u16) -> Result<Response>
 where
-    Stream: AsyncRead + AsyncWrite + Unpin,
+    Stream: AsyncRead + AsyncWrite,
 {
-    stream.write_all(&LEGACY_REQUEST).await?;
-    stream.flush().await?;
+    let mut stream = FuturesIo(pin!(stream));
+    request_latest(
+        &mut stream,
+        hostname,
+        port,
+        &PingConfig::default(),
+        &mut Vec::new(),
+    )
+    .await
+}
 
-    let mut buffer = Vec::new();
-    stream.read_to_end(&mut buffer).await?;
+/// Send a ping request of the legacy protocol (1.6) to the server and return a future response.
+///
+/// Servers before 1.7 only understand this protocol.
+/// Most of the later servers also answer it, without the fields added since 1.7.
+///
+/// `hostname` and `port` are sent to the server as the 1.6 client does. Some proxies reject the request without them.
+pub async fn ping_legacy<Stream>(stream: Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: AsyncRead + AsyncWrite,
+{
+    let mut stream = FuturesIo(pin!(stream));
+    request_legacy(
+        &mut stream,
+        &build_legacy_request(hostname, port, &PingConfig::default()),
+        &Limits::default(),
+        &mut Vec::new(),
+    )
+    .await
+}
 
-    let response = decode_legacy(&buffer)?;
-    parse_legacy(&response, buffer)
+/// Send a ping request to the server and return a future response,
+/// reusing the memory of the raw response received by the previous ping with the same `buf`.
+///
+/// It is useful when the same server is pinged repeatedly.
+/// The response is stored in `buf` until the next ping with it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{futures::ping_into, ResponseBuf};
+/// use async_std::net::TcpStream;
+///
+/// # async fn run() {
+/// let mut buf = ResponseBuf::new();
+/// for _ in 0..10 {
+///     let mut stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+///     let response = ping_into(&mut stream, "my.server.com", 25565, &mut buf).await.unwrap();
+///     println!("Players online: {}", response.online_players);
+/// }
+/// # }
+/// ```
+pub async fn ping_into<'a, Stream>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+    buf: &'a mut ResponseBuf,
+) -> Result<&'a Response>
+where
+    Stream: AsyncRead + AsyncWrite,
+{
+    async_transport::ping_into(&mut FuturesIo(pin!(stream)), hostname, port, buf).await
 }
 
-async fn read_varint<Stream>(stream: &mut Stream) -> Result<i32>
+/// Send a ping request to the server, and measure the round-trip time with the ping packet after the response.
+///
+/// The latency is measured the same way as the vanilla client does.
+/// For the legacy servers, which do not support the ping packet, the time taken by the legacy request is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::futures::ping_with_latency;
+/// use async_std::net::TcpStream;
+///
+/// # async fn run() {
+/// let mut stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+/// let (response, latency) = ping_with_latency(&mut stream, "my.server.com", 25565).await.unwrap();
+/// println!("{} player(s) online, {:?}", response.online_players, latency);
+/// # }
+/// ```
+pub async fn ping_with_latency<Stream>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+) -> Result<(Response, Duration)>
 where
-    Stream: AsyncRead + Unpin,
+    Stream: AsyncRead + AsyncWrite,
 {
-    let mut buffer = [0u8];
-    let mut result = 0;
-    let mut read_count = 0u32;
-    loop {
-        stream.read_exact(&mut buffer).await?;
-        result |= (buffer[0] as i32 & LAST_SEVEN_BITS)
-            .checked_shl(7 * read_count)
-            .ok_or(Error::UnsupportedProtocol)?;
-
-        read_count += 1;
-        if read_count > 5 {
-            break Err(Error::UnsupportedProtocol);
-        } else if (buffer[0] & NEXT_BYTE_EXISTS) == 0 {
-            break Ok(result);
-        }
+    async_transport::ping_with_latency(&mut FuturesIo(pin!(stream)), hostname, port).await
+}
+
+/// An extension trait to ping the server through the stream, as in `stream.slp_ping(hostname, port).await`.
+///
+/// It is implemented for every [`Unpin`](Unpin) stream, and the methods are the same as the functions of this module.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::futures::PingExt;
+/// use async_std::{net::TcpStream, task::sleep};
+/// use std::time::Duration;
+///
+/// # async fn run() {
+/// let mut stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+/// let response = stream
+///     .slp_ping_with_timeout("my.server.com", 25565, sleep(Duration::from_secs(5)))
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub trait PingExt {
+    /// Send a ping request to the server and return a future response.
+    /// See also [`ping`](ping).
+    fn slp_ping<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+    ) -> impl Future<Output = Result<Response>> + 'a;
+
+    /// Send a ping request to the server with the options and return a future response.
+    /// See also [`ping_with_config`](ping_with_config).
+    fn slp_ping_with_config<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+        config: &'a PingConfig,
+    ) -> impl Future<Output = Result<Response>> + 'a;
+
+    /// Send a ping request to the server and return a future response, failing if the `timeout` future completes first.
+    /// See also [`ping_with_timeout`](ping_with_timeout).
+    fn slp_ping_with_timeout<'a, Timeout>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+        timeout: Timeout,
+    ) -> impl Future<Output = Result<Response>> + 'a
+    where
+        Timeout: Future<Output = ()> + 'a;
+
+    /// Send a ping request of the legacy protocol (1.6) to the server and return a future response.
+    /// See also [`ping_legacy`](ping_legacy).
+    fn slp_ping_legacy<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+    ) -> impl Future<Output = Result<Response>> + 'a;
+}
+
+impl<Stream: AsyncRead + AsyncWrite + Unpin> PingExt for Stream {
+    fn slp_ping<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+    ) -> impl Future<Output = Result<Response>> + 'a {
+        ping(self, hostname, port)
+    }
+
+    fn slp_ping_with_config<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+        config: &'a PingConfig,
+    ) -> impl Future<Output = Result<Response>> + 'a {
+        ping_with_config(self, hostname, port, config)
+    }
+
+    fn slp_ping_with_timeout<'a, Timeout>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+        timeout: Timeout,
+    ) -> impl Future<Output = Result<Response>> + 'a
+    where
+        Timeout: Future<Output = ()> + 'a,
+    {
+        ping_with_timeout(self, hostname, port, timeout)
+    }
+
+    fn slp_ping_legacy<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+    ) -> impl Future<Output = Result<Response>> + 'a {
+        ping_legacy(self, hostname, port)
     }
 }
 
@@ -102,6 +334,8 @@ where
 mod test {
     use super::*;
 
+    use crate::async_transport::read_varint;
+
     use ::futures::io::Cursor;
 
     #[test]
@@ -114,13 +348,28 @@ mod test {
         for &i in samples.iter() {
             buffer.clear();
             write_varint(&mut buffer, i);
-            let mut reader = Cursor::new(buffer);
-            let task = read_varint(&mut reader);
+            let mut transport = FuturesIo(Cursor::new(buffer));
+            let task = read_varint(&mut transport);
 
             let deserialized = runtime.block_on(task).unwrap();
 
             assert_eq!(i, deserialized);
-            buffer = reader.into_inner();
+            buffer = transport.0.into_inner();
+        }
+    }
+
+    #[async_std::test]
+    async fn time_out_whole_ping() {
+        // the server never answers, so neither the modern nor the legacy ping finishes
+        let listener = async_std::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut stream = async_std::net::TcpStream::connect(address).await.unwrap();
+        let timeout = async_std::task::sleep(Duration::from_millis(100));
+        match ping_with_timeout(&mut stream, "localhost", address.port(), timeout).await {
+            Err(Error::Io(error)) => assert_eq!(error.kind(), std::io::ErrorKind::TimedOut),
+            result => panic!("unexpected result: {:?}", result),
         }
     }
 }
@@ -0,0 +1,122 @@
+//! Provides ICMP [`echo`](echo) function to measure the network latency to a server.
+//!
+//! Comparing the network latency with the time the server takes to answer a ping request
+//! tells whether a slow server is slow because of the network or because of the server itself.
+//!
+//! It uses raw sockets, thus requires a privilege (e.g. root or `CAP_NET_RAW` on Linux).
+use std::{
+    io::{self, Read},
+    net::{IpAddr, SocketAddr},
+    sync::atomic::{AtomicU16, Ordering},
+    time::{Duration, Instant},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::Result;
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+
+// distinguishes the requests sent from this process
+static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+/// Send an ICMP echo request to the address and return the round-trip time of the reply.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::icmp::echo;
+/// use std::time::Duration;
+///
+/// let latency = echo("127.0.0.1".parse().unwrap(), Duration::from_secs(1)).unwrap();
+/// println!("Network latency: {:?}", latency);
+/// ```
+pub fn echo(address: IpAddr, timeout: Duration) -> Result<Duration> {
+    let (domain, protocol, request_type, reply_type) = match address {
+        IpAddr::V4(_) => (
+            Domain::IPV4,
+            Protocol::ICMPV4,
+            ECHO_REQUEST_V4,
+            ECHO_REPLY_V4,
+        ),
+        IpAddr::V6(_) => (
+            Domain::IPV6,
+            Protocol::ICMPV6,
+            ECHO_REQUEST_V6,
+            ECHO_REPLY_V6,
+        ),
+    };
+    let socket = Socket::new(domain, Type::RAW, Some(protocol))?;
+    socket.connect(&SocketAddr::new(address, 0).into())?;
+
+    let identifier = std::process::id() as u16;
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let mut request = vec![request_type, 0, 0, 0];
+    request.extend_from_slice(&identifier.to_be_bytes());
+    request.extend_from_slice(&sequence.to_be_bytes());
+    request.extend_from_slice(b"craftpng");
+    if address.is_ipv4() {
+        // the kernel computes the checksum for ICMPv6
+        let checksum = checksum(&request);
+        request[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    let start = Instant::now();
+    socket.send(&request)?;
+    let mut buffer = [0u8; 1024];
+    loop {
+        let remaining = timeout
+            .checked_sub(start.elapsed())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::TimedOut))?;
+        socket.set_read_timeout(Some(remaining))?;
+        let length = crate::read_timed_out((&socket).read(&mut buffer))?;
+        let elapsed = start.elapsed();
+
+        // the IPv4 header is included in the packet received from a raw socket
+        let header_length = if address.is_ipv4() {
+            (buffer[0] & 0x0f) as usize * 4
+        } else {
+            0
+        };
+        let reply = match buffer[..length].get(header_length..header_length + 8) {
+            Some(reply) => reply,
+            None => continue,
+        };
+        if reply[0] == reply_type
+            && reply[4..6] == identifier.to_be_bytes()
+            && reply[6..8] == sequence.to_be_bytes()
+        {
+            break Ok(elapsed);
+        }
+    }
+}
+
+// the internet checksum (RFC 1071)
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|chunk| u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compute_checksum() {
+        let packet = [ECHO_REQUEST_V4, 0, 0, 0, 0x12, 0x34, 0x00, 0x01];
+        let sum = checksum(&packet);
+        let mut filled = packet;
+        filled[2..4].copy_from_slice(&sum.to_be_bytes());
+        assert_eq!(checksum(&filled), 0);
+    }
+}
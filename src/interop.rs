@@ -0,0 +1,219 @@
+//! Provides [`import_status`](import_status) function to read the status dumps of other tools.
+//!
+//! Tools like mcstatus or MineStat export the status with their own, mostly flattened schemas,
+//! e.g. `player_count` instead of `players.online`.
+//! Importing the dumps lets the historical data collected by those tools be processed along with
+//! the responses of craftping.
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{Map, Value};
+
+use crate::{Chat, Error, Player, Response, Result};
+
+// the fields read by `import_status`, in the order of preference
+const VERSION: &[&str] = &["version", "version_name", "server_version"];
+const PROTOCOL: &[&str] = &["protocol", "protocol_version"];
+const MOTD: &[&str] = &["motd", "description", "stripped_motd", "motd_clean"];
+const ONLINE_PLAYERS: &[&str] = &[
+    "online_players",
+    "player_count",
+    "players_online",
+    "current_players",
+];
+const MAX_PLAYERS: &[&str] = &["max_players", "player_max", "players_max"];
+const SAMPLE: &[&str] = &["sample", "players", "player_list"];
+const FAVICON: &[&str] = &["favicon", "icon"];
+// fields which carry nothing more than the ones above
+const DERIVED: &[&str] = &["online", "players", "motd_html", "motd_ansi", "motd_raw"];
+
+/// Parse a status dump of another tool into a [`Response`](Response).
+///
+/// Both the nested schema of the Server List Ping (`players.online`, `version.name`, ...)
+/// and the flattened schemas (`player_count`, `max_players`, ...) are recognized.
+/// A dump wrapped in a `status` object, as in the json output of mcstatus, is unwrapped.
+/// The MOTD is kept as is, with its formatting codes, like the legacy responses.
+///
+/// The fields which are not recognized (e.g. the latency) are kept in [`extras`](Response::extras),
+/// and [`raw`](Response::raw) is empty as the response did not come from a server.
+/// A dump of an offline server, or without the player counts, is rejected with
+/// [`UnsupportedProtocol`](Error::UnsupportedProtocol).
+///
+/// # Examples
+///
+/// ```
+/// use craftping::interop::import_status;
+///
+/// let dump = br#"{
+///     "online": true,
+///     "version": "1.20.4",
+///     "protocol": 765,
+///     "motd": "A Minecraft Server",
+///     "player_count": 3,
+///     "player_max": 20,
+///     "ping": 42.5
+/// }"#;
+/// let response = import_status(dump).unwrap();
+/// assert_eq!(response.online_players, 3);
+/// assert_eq!(response.extras["ping"], 42.5);
+/// ```
+pub fn import_status(json: &[u8]) -> Result<Response> {
+    let mut dump: Map<String, Value> =
+        serde_json::from_slice(json).map_err(|_| Error::UnsupportedProtocol)?;
+    if dump.get("online") == Some(&Value::Bool(false)) {
+        return Err(Error::UnsupportedProtocol);
+    }
+    if let Some(Value::Object(status)) = dump.remove("status") {
+        dump.extend(status);
+    }
+
+    let nested = |object: &str, key: &str| dump.get(object).and_then(|value| value.get(key));
+    let version = find(&dump, VERSION)
+        .and_then(|version| match version {
+            Value::Object(version) => version.get("name"),
+            version => Some(version),
+        })
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let protocol = find(&dump, PROTOCOL)
+        .or_else(|| nested("version", "protocol"))
+        .and_then(Value::as_i64)
+        .and_then(|protocol| protocol.try_into().ok())
+        .unwrap_or(-1);
+    let online_players = find(&dump, ONLINE_PLAYERS)
+        .or_else(|| nested("players", "online"))
+        .and_then(as_count)
+        .ok_or(Error::UnsupportedProtocol)?;
+    let max_players = find(&dump, MAX_PLAYERS)
+        .or_else(|| nested("players", "max"))
+        .and_then(as_count)
+        .ok_or(Error::UnsupportedProtocol)?;
+    let description = match find(&dump, MOTD) {
+        Some(Value::String(motd)) => Chat {
+            text: motd.clone(),
+            ..Default::default()
+        },
+        Some(motd @ Value::Object(_)) => {
+            serde_json::from_value(motd.clone()).map_err(|_| Error::UnsupportedProtocol)?
+        }
+        _ => Chat::default(),
+    };
+    let sample = match nested("players", "sample").or_else(|| find(&dump, SAMPLE)) {
+        Some(Value::Array(sample)) => Some(sample.iter().filter_map(as_player).collect()),
+        _ => None,
+    };
+    let favicon = match find(&dump, FAVICON).and_then(Value::as_str) {
+        Some(favicon) => {
+            let encoded = favicon.strip_prefix("data:image/png;base64,").unwrap_or(favicon);
+            Some(
+                STANDARD
+                    .decode(encoded)
+                    .map_err(|_| Error::UnsupportedProtocol)?,
+            )
+        }
+        None => None,
+    };
+    let known = [
+        VERSION,
+        PROTOCOL,
+        MOTD,
+        ONLINE_PLAYERS,
+        MAX_PLAYERS,
+        SAMPLE,
+        FAVICON,
+        DERIVED,
+    ];
+    let extras = dump
+        .iter()
+        .filter(|(key, _)| !known.iter().any(|keys| keys.contains(&key.as_str())))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+
+    Ok(Response {
+        version,
+        protocol,
+        enforces_secure_chat: None,
+        previews_chat: None,
+        max_players,
+        online_players,
+        sample,
+        description,
+        favicon,
+        mod_info: None,
+        forge_data: None,
+        extras,
+        raw: Vec::new(),
+    })
+}
+
+fn find<'a>(dump: &'a Map<String, Value>, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter()
+        .filter_map(|key| dump.get(*key))
+        .find(|value| !value.is_null())
+}
+
+// some tools dump the player counts as strings
+fn as_count(value: &Value) -> Option<usize> {
+    match value {
+        Value::Number(count) => count.as_u64()?.try_into().ok(),
+        Value::String(count) => count.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn as_player(value: &Value) -> Option<Player> {
+    match value {
+        Value::String(name) => Some(Player {
+            name: name.clone(),
+            id: String::new(),
+        }),
+        Value::Object(player) => Some(Player {
+            name: player.get("name")?.as_str()?.to_string(),
+            id: player
+                .get("id")
+                .or_else(|| player.get("uuid"))
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn import_nested_status() {
+        let dump = br#"{
+            "online": true,
+            "kind": "Java",
+            "status": {
+                "players": {
+                    "online": 1,
+                    "max": 20,
+                    "sample": [{ "name": "Notch", "id": "069a79f4-44e9-4726-a5be-fca90e38aaf5" }]
+                },
+                "version": { "name": "1.20.4", "protocol": 765 },
+                "motd": "\u00a7aHello",
+                "latency": 12.3
+            }
+        }"#;
+        let response = import_status(dump).unwrap();
+        assert_eq!(response.version, "1.20.4");
+        assert_eq!(response.protocol, 765);
+        assert_eq!(response.online_players, 1);
+        assert_eq!(response.max_players, 20);
+        assert_eq!(response.sample.unwrap()[0].name, "Notch");
+        assert_eq!(response.description.text, "\u{00a7}aHello");
+        assert_eq!(response.extras["kind"], "Java");
+        assert_eq!(response.extras["latency"], 12.3);
+
+        assert!(import_status(br#"{ "online": false, "player_count": 0 }"#).is_err());
+    }
+}
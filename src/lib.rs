@@ -1,12 +1,56 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(clippy::needless_doctest_main)]
 //! craftping provides a `ping` function to send Server List Ping requests to a Minecraft server.
 //!
 //! # Feature flags
 //!
-//! - `sync` (default): Enables synchronous, blocking [`ping`](crate::sync::ping) function.
+//! - `std` (default): Enables the standard library. Every other feature except `embedded-io`, `embedded-io-async` and `embassy` implies it.
+//! - `sync` (default): Enables synchronous, blocking [`ping`](crate::sync::ping) function,
+//!   the [`query`](crate::query) protocol and the SRV [`lookup`](crate::resolve::lookup).
 //! - `async-tokio`: Enables asynchronous, `tokio`-based [`ping`](crate::tokio::ping) function.
 //! - `async-futures`: Enables asynchronous, `futures`-based [`ping`](crate::futures::ping) function.
+//! - `embedded-io`: Enables blocking [`ping`](crate::embedded_io::ping) function for `embedded-io` streams.
+//! - `embedded-io-async`: Enables asynchronous [`ping`](crate::embedded_io_async::ping) function for `embedded-io-async` streams.
+//!   Implies `embedded-io`.
+//! - `embassy`: Enables asynchronous [`ping`](crate::embassy::ping) function over the TCP socket of embassy-net.
+//!   Implies `embedded-io-async`.
+//! - `arbitrary`: Implements `arbitrary::Arbitrary` for [`Response`](crate::Response), [`Chat`](crate::Chat), [`Player`](crate::Player)
+//!   and the mod information, to property-test and fuzz the code consuming them.
+//! - `arti`: Enables [`Pinger::ping_tor`](crate::pinger::Pinger::ping_tor) to ping over the Tor network with arti. Implies `async-tokio`.
+//! - `cli`: Builds the `craftping` binary with `ping`, `legacy`, `query` and `watch` commands,
+//!   printing the results for humans or as JSON with `--json`. Implies `watch`.
+//! - `discord`: Enables [`Response::to_discord_embed`](crate::Response::to_discord_embed) to build a Discord embed.
+//! - `hickory`: Enables [`HickoryResolver`](crate::resolve::HickoryResolver), a DNS resolver backed by hickory-dns. Implies `sync`.
+//! - `icmp`: Enables ICMP [`echo`](crate::icmp::echo) function to measure the network latency. Requires a privilege to use.
+//! - `log`: Emits the debug and trace messages of each stage of the requests through the `log` facade,
+//!   for the applications not on `tracing`.
+//! - `metrics`: Emits the counters and the histograms of the requests through the `metrics` facade,
+//!   like `craftping_requests_total`, `craftping_request_failures_total` and `craftping_request_duration_seconds`.
+//! - `monitor`: Enables config-driven [`Monitor`](crate::monitor::Monitor) runtime. Implies `watch`.
+//! - `monoio`: Enables asynchronous, `monoio`-based [`ping`](crate::monoio::ping) function.
+//! - `scan`: Enables [`Scanner`](crate::scan::Scanner) to scan IP ranges for servers concurrently. Implies `async-tokio`.
+//! - `server`: Enables [`server`](crate::server) module to answer the ping requests as a server. Implies `sync`.
+//! - `socket`: Enables [`Pinger::bind`](crate::pinger::Pinger::bind) and [`Pinger::bind_device`](crate::pinger::Pinger::bind_device)
+//!   to choose the local address and the network interface of the connections,
+//!   and the socket options like [`Pinger::nodelay`](crate::pinger::Pinger::nodelay) and [`Pinger::linger`](crate::pinger::Pinger::linger),
+//!   which [`Scanner`](crate::scan::Scanner::socket_options) also accepts as [`SocketOptions`](crate::pinger::SocketOptions).
+//! - `testing`: Enables [`MockServer`](crate::testing::MockServer) to test the code pinging servers without real ones,
+//!   and the [`fixtures`](crate::fixtures) of the captured responses. Implies `server`.
+//! - `tls`: Enables [`Pinger::tls`](crate::pinger::Pinger::tls) to ping through a TLS connection with rustls.
+//! - `tower`: Enables [`PingService`](crate::tower::PingService), a `tower::Service` pinging the requested [`ServerAddress`](crate::ServerAddress),
+//!   to compose the pings with the tower middleware. Implies `async-tokio`.
+//! - `tracing`: Emits the spans and the events of each stage of the requests through `tracing`,
+//!   like the handshake, the status read and the JSON decode, to find out which stage failed.
+//! - `uring`: Enables asynchronous, `tokio-uring`-based [`ping`](crate::uring::ping) function on io_uring. Linux only.
+//! - `watch`: Enables [`Watcher`](crate::watch::Watcher) to ping a server periodically. Implies `sync`.
+//! - `websocket`: Enables [`websocket`](crate::websocket) module to ping over a WebSocket. Implies `async-tokio`.
+//!
+//! The `sync` and `async-futures` features also support `wasm32-wasip2` target.
+//!
+//! Without the `std` feature (enabled by default), the crate is `no_std` and only needs an allocator.
+//! The [`Response`](crate::Response) and the `embedded-io` functions are available then,
+//! for the microcontrollers like ESP32.
 //!
 //! # Examples
 //!
@@ -23,44 +67,155 @@
 //! }
 //! ```
 
-use std::{
-    fmt::Display,
-    io::{Read, Write},
+extern crate alloc;
+
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
 };
+use core::{fmt::Display, net::SocketAddr, time::Duration};
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
 
+use serde::{Deserialize, Serialize};
+
+mod address;
+#[cfg(any(feature = "async-tokio", feature = "async-futures"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(feature = "async-tokio", feature = "async-futures")))
+)]
+pub mod async_transport;
+#[cfg(feature = "std")]
+mod cancel;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub mod capture;
+#[cfg(any(all(feature = "uring", target_os = "linux"), feature = "monoio"))]
+mod completion;
+#[cfg(feature = "discord")]
+#[cfg_attr(docsrs, doc(cfg(feature = "discord")))]
+pub mod discord;
+#[cfg(feature = "embassy")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embassy")))]
+pub mod embassy;
+#[cfg(feature = "embedded-io")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embedded-io")))]
+pub mod embedded_io;
+#[cfg(feature = "embedded-io-async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embedded-io-async")))]
+pub mod embedded_io_async;
 mod entity;
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub mod fixtures;
 #[cfg(feature = "async-futures")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async-futures")))]
 pub mod futures;
+#[cfg(feature = "icmp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "icmp")))]
+pub mod icmp;
+pub mod interop;
+mod limits;
+#[cfg(feature = "monitor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "monitor")))]
+pub mod monitor;
+#[cfg(feature = "monoio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "monoio")))]
+pub mod monoio;
+#[cfg(any(feature = "sync", feature = "async-tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "async-tokio"))))]
+pub mod pinger;
+mod proxy;
+#[cfg(feature = "sync")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+pub mod query;
+#[cfg(any(feature = "sync", feature = "async-tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "async-tokio"))))]
+pub mod resolve;
+#[cfg(feature = "scan")]
+#[cfg_attr(docsrs, doc(cfg(feature = "scan")))]
+pub mod scan;
+#[cfg(feature = "server")]
+#[cfg_attr(docsrs, doc(cfg(feature = "server")))]
+pub mod server;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub mod sink;
+pub mod stats;
 #[cfg(feature = "sync")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
 pub mod sync;
+#[cfg(any(feature = "sync", feature = "async-tokio", feature = "async-futures"))]
+mod telemetry;
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub mod testing;
 #[cfg(feature = "async-tokio")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
 pub mod tokio;
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub mod tower;
+#[cfg(any(feature = "sync", feature = "async-tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "async-tokio"))))]
+pub mod transport;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "uring", target_os = "linux"))))]
+pub mod uring;
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+pub mod watch;
+#[cfg(feature = "websocket")]
+#[cfg_attr(docsrs, doc(cfg(feature = "websocket")))]
+pub mod websocket;
 
+pub use address::{ParseServerAddressError, ServerAddress};
+#[cfg(feature = "std")]
+pub use cancel::{CancelToken, Cancelled};
 pub use entity::*;
+pub use limits::Limits;
+pub use proxy::{ProxyHeader, ProxyVersion};
 
 #[derive(Debug)]
+#[non_exhaustive]
 /// The ping error type.
+///
+/// The variants depend on the enabled features, so it is non-exhaustive
+/// and must be matched with a wildcard arm, which keeps the features additive.
+/// It became non-exhaustive with the addition of [`EmbeddedIo`](Error::EmbeddedIo).
 pub enum Error {
+    #[cfg(feature = "std")]
     /// Returned when I/O (especially networking) failed.
     Io(std::io::Error),
+    #[cfg(feature = "embedded-io")]
+    /// Returned when I/O of an `embedded-io` stream failed.
+    EmbeddedIo(::embedded_io::ErrorKind),
     /// Returned when the response cannot be recognized.
     UnsupportedProtocol,
+    /// Returned when the server keeps dropping the connections made soon after another,
+    /// like the `connection-throttle` of Spigot and BungeeCord.
+    /// See also [`PingConfig::throttle_delay`](PingConfig::throttle_delay).
+    Throttled,
 }
 
 impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
+            #[cfg(feature = "std")]
             Self::Io(io) => io.fmt(f),
+            #[cfg(feature = "embedded-io")]
+            Self::EmbeddedIo(kind) => kind.fmt(f),
             Self::UnsupportedProtocol => write!(f, "unsupported protocol"),
+            Self::Throttled => write!(f, "throttled by the server"),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl core::error::Error for Error {}
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for Error {
     fn from(error: std::io::Error) -> Self {
         Self::Io(error)
@@ -68,25 +223,147 @@ impl From<std::io::Error> for Error {
 }
 
 /// The ping result type.
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+/// The IP family to use when a hostname resolves to both IPv4 and IPv6 addresses.
+pub enum IpPreference {
+    #[default]
+    /// Use the addresses in the order the resolver returned them.
+    Any,
+    /// Try IPv4 addresses first, then IPv6 addresses.
+    PreferV4,
+    /// Try IPv6 addresses first, then IPv4 addresses.
+    PreferV6,
+    /// Use IPv4 addresses only.
+    V4Only,
+    /// Use IPv6 addresses only.
+    V6Only,
+}
+
+impl IpPreference {
+    /// Filter and reorder the resolved addresses according to the preference.
+    /// The relative order of the addresses in the same family is preserved.
+    pub fn apply(self, addresses: &mut Vec<SocketAddr>) {
+        match self {
+            Self::Any => {}
+            Self::PreferV4 => addresses.sort_by_key(|address| !address.is_ipv4()),
+            Self::PreferV6 => addresses.sort_by_key(|address| !address.is_ipv6()),
+            Self::V4Only => addresses.retain(SocketAddr::is_ipv4),
+            Self::V6Only => addresses.retain(SocketAddr::is_ipv6),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The options of the handshake and the response, used by `ping_with_config` functions.
+pub struct PingConfig {
+    /// The protocol version to advertise in the handshake.
+    /// Defaults to -1, which means the client is determining what version to use.
+    pub protocol_version: i32,
+    /// The hostname to send in the handshake instead of the one to ping, e.g. for virtual hosts behind a proxy.
+    /// Defaults to `None`.
+    pub virtual_host: Option<String>,
+    /// `true` if the legacy ping is attempted when the modern one fails. Defaults to `true`.
+    pub legacy_fallback: bool,
+    /// The next state to request in the handshake. Defaults to 1 (status).
+    ///
+    /// The servers answer the ping request only in the status state,
+    /// so other values are useful only to probe how the server reacts.
+    pub next_state: i32,
+    /// The marker of the Forge client to append to the hostname in the handshake. Defaults to `None`.
+    ///
+    /// Forge servers may omit some of the mod data from the response unless the client is marked as modded.
+    pub forge_marker: Option<ForgeMarker>,
+    /// The PROXY protocol header to send before the handshake, for the servers behind a proxy like HAProxy.
+    /// Defaults to `None`.
+    pub proxy_header: Option<ProxyHeader>,
+    /// The limits of the response, including the maximum packet size.
+    pub limits: Limits,
+    /// The delay before reconnecting when the server drops a connection right after answering another,
+    /// as the connection throttles do to the clients reconnecting too soon.
+    /// Defaults to 4 seconds, the default throttle of Spigot and BungeeCord.
+    ///
+    /// It applies to the functions making several connections, like [`sync::ping_with_protocol_retry`](crate::sync::ping_with_protocol_retry).
+    /// `None` fails with [`Throttled`](Error::Throttled) without reconnecting.
+    pub throttle_delay: Option<Duration>,
+}
+
+// the default `connection-throttle` of Spigot and BungeeCord
+pub(crate) const THROTTLE_DELAY: Duration = Duration::from_secs(4);
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        Self {
+            protocol_version: -1,
+            virtual_host: None,
+            legacy_fallback: true,
+            next_state: 1,
+            forge_marker: None,
+            proxy_header: None,
+            limits: Limits::default(),
+            throttle_delay: Some(THROTTLE_DELAY),
+        }
+    }
+}
+
+impl From<Limits> for PingConfig {
+    fn from(limits: Limits) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+}
 
-fn build_latest_request(hostname: &str, port: u16) -> Result<Vec<u8>> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A marker the Forge clients append to the hostname in the handshake.
+pub enum ForgeMarker {
+    /// `\0FML\0`, sent by the Forge clients up to 1.12.2.
+    Fml,
+    /// `\0FML2\0`, sent by the Forge clients from 1.13.
+    Fml2,
+}
+
+impl ForgeMarker {
+    fn suffix(self) -> &'static str {
+        match self {
+            ForgeMarker::Fml => "\0FML\0",
+            ForgeMarker::Fml2 => "\0FML2\0",
+        }
+    }
+}
+
+fn build_latest_request(hostname: &str, port: u16, config: &PingConfig) -> Result<Vec<u8>> {
+    let mut hostname = config
+        .virtual_host
+        .as_deref()
+        .unwrap_or(hostname)
+        .to_string();
+    if let Some(marker) = config.forge_marker {
+        hostname.push_str(marker.suffix());
+    }
     // buffer for the 1st packet's data part
     let mut buffer = vec![
         0x00, // 1st packet id: 0 for handshake as VarInt
-        0xff, 0xff, 0xff, 0xff,
-        0x0f, // protocol version: -1 (determining what version to use) as VarInt
     ];
+    // protocol version as VarInt, -1 by default (determining what version to use)
+    write_varint(&mut buffer, config.protocol_version);
     // Some server implementations require hostname and port to be properly set (Notchian does not)
     write_varint(&mut buffer, hostname.len() as i32); // length of hostname as VarInt
     buffer.extend_from_slice(hostname.as_bytes());
     buffer.extend_from_slice(&[
         (port >> 8) as u8,
         (port & 0b1111_1111) as u8, // server port as unsigned short
-        0x01,                       // next state: 1 (status) as VarInt
     ]);
-    // buffer for the 1st and 2nd packet
-    let mut full_buffer = vec![];
+    // next state as VarInt, 1 (status) by default
+    write_varint(&mut buffer, config.next_state);
+    // buffer for the PROXY header, the 1st and 2nd packet
+    let mut full_buffer = match &config.proxy_header {
+        Some(header) => header.encode(),
+        None => vec![],
+    };
     write_varint(&mut full_buffer, buffer.len() as i32); // length of 1st packet id + data as VarInt
     full_buffer.append(&mut buffer);
     full_buffer.extend_from_slice(&[
@@ -96,11 +373,12 @@ fn build_latest_request(hostname: &str, port: u16) -> Result<Vec<u8>> {
     Ok(full_buffer)
 }
 
-fn decode_latest_response(buffer: &[u8]) -> Result<RawLatest> {
-    serde_json::from_slice(buffer).map_err(|_| Error::UnsupportedProtocol)
+fn decode_latest_response(buffer: &[u8], limits: &Limits) -> Result<RawLatest> {
+    limits::with_limits(limits, || serde_json::from_slice(buffer))
+        .map_err(|_| Error::UnsupportedProtocol)
 }
 
-const LEGACY_REQUEST: [u8; 35] = [
+const LEGACY_REQUEST_HEAD: [u8; 27] = [
     0xfe, // 1st packet id: 0xfe for server list ping
     0x01, // payload: always 1
     0xfa, // 2nd packet id: 0xfa for plugin message
@@ -108,12 +386,55 @@ const LEGACY_REQUEST: [u8; 35] = [
     0x00, 0x4d, 0x00, 0x43, 0x00, 0x7c, 0x00, 0x50, 0x00, 0x69, 0x00, 0x6e, 0x00, 0x67, 0x00, 0x48,
     0x00, 0x6f, 0x00, 0x73, 0x00, 0x74,
     // MC|PingHost as UTF16-BE
-    7,    // length of the rest of the data: 7 + length of hostname
-    0x4a, // protocol version: 0x4a for the last version
-    0x00, 0x00, // length of hostname: 0 as short
-    0x00, 0x00, 0x00, 0x00, // port: 0 as int
 ];
 
+// the legacy request of 1.6, carrying the hostname and the port as the 1.6 client does,
+// since some proxies reject the request without them
+fn build_legacy_request(hostname: &str, port: u16, config: &PingConfig) -> Vec<u8> {
+    let hostname = config.virtual_host.as_deref().unwrap_or(hostname);
+    // the length of the rest of the data is a short too
+    let hostname: Vec<u16> = hostname
+        .encode_utf16()
+        .take((u16::MAX as usize - 7) / 2)
+        .collect();
+    let mut request = LEGACY_REQUEST_HEAD.to_vec();
+    // length of the rest of the data: 7 + length of hostname in bytes
+    request.extend_from_slice(&(7 + hostname.len() as u16 * 2).to_be_bytes());
+    request.push(0x4a); // protocol version: 0x4a for the last version
+    request.extend_from_slice(&(hostname.len() as u16).to_be_bytes()); // length of hostname as short
+    request.extend(hostname.iter().flat_map(|unit| unit.to_be_bytes())); // hostname as UTF16-BE
+    request.extend_from_slice(&(port as i32).to_be_bytes()); // port as int
+    request
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A version of the Server List Ping protocol.
+///
+/// See also [the minecraft protocol wiki](https://wiki.vg/Server_List_Ping) for the differences.
+pub enum Strategy {
+    /// The current protocol, used since 1.7.
+    Modern,
+    /// The legacy protocol of 1.6.
+    Legacy16,
+    /// The legacy protocol of 1.4 and 1.5.
+    Legacy14,
+    /// The legacy protocol of beta 1.8 to 1.3.
+    ///
+    /// The response has an empty version and the protocol of -1, since the server does not send them.
+    Beta,
+}
+
+// the length in bytes of the legacy response after its header,
+// which is the packet id 0xff and the length of the UTF-16 string in code units
+fn legacy_body_length(header: [u8; 3], limits: &Limits) -> Result<usize> {
+    let [packet_id, high, low] = header;
+    let length = u16::from_be_bytes([high, low]) as usize * 2;
+    if packet_id != 0xff || header.len() + length > limits.max_response_size {
+        return Err(Error::UnsupportedProtocol);
+    }
+    Ok(length)
+}
+
 fn decode_legacy(buffer: &[u8]) -> Result<String> {
     if buffer.len() <= 3 || buffer[0] != 0xff {
         return Err(Error::UnsupportedProtocol);
@@ -125,7 +446,46 @@ fn decode_legacy(buffer: &[u8]) -> Result<String> {
     String::from_utf16(&utf16be).map_err(|_| Error::UnsupportedProtocol)
 }
 
+/// Parse a response of the legacy protocol (beta 1.8 to 1.6), the whole packet starting with `0xff`, without any stream.
+///
+/// The response of the servers before 1.4, `motd§online§max`, has an empty version and the protocol of -1 as in [`Strategy::Beta`](Strategy::Beta).
+///
+/// The packet is rejected unless its length matches the length in its header.
+/// It never panics on any input, so it can be fuzzed directly.
+///
+/// # Examples
+///
+/// ```
+/// use craftping::parse_legacy_response;
+///
+/// let status = ["\u{a7}1", "78", "1.6.4", "A Minecraft Server", "0", "20"].join("\0");
+/// let mut packet = vec![0xff];
+/// packet.extend_from_slice(&(status.encode_utf16().count() as u16).to_be_bytes());
+/// packet.extend(status.encode_utf16().flat_map(u16::to_be_bytes));
+///
+/// let response = parse_legacy_response(&packet).unwrap();
+/// assert_eq!(response.version, "1.6.4");
+/// assert_eq!(response.max_players, 20);
+/// assert!(parse_legacy_response(&packet[..20]).is_err());
+/// ```
+pub fn parse_legacy_response(packet: &[u8]) -> Result<Response> {
+    // the packet must end exactly where the length in its header tells
+    let header = match packet {
+        [packet_id, high, low, ..] => [*packet_id, *high, *low],
+        _ => return Err(Error::UnsupportedProtocol),
+    };
+    if header.len() + legacy_body_length(header, &Limits::default())? != packet.len() {
+        return Err(Error::UnsupportedProtocol);
+    }
+    let response = decode_legacy(packet)?;
+    parse_legacy(&response, packet.to_vec())
+}
+
 fn parse_legacy(s: &str, raw: Vec<u8>) -> Result<Response> {
+    // the servers before 1.4 answer with `motd§online§max` instead
+    if !s.starts_with("\u{00a7}\u{0031}\0") {
+        return parse_beta(s, raw);
+    }
     let mut fields = s.split('\0');
     let magic = fields.next().map(|s| s == "\u{00a7}\u{0031}");
     let protocol = fields.next().and_then(|s| s.parse().ok());
@@ -156,12 +516,70 @@ fn parse_legacy(s: &str, raw: Vec<u8>) -> Result<Response> {
             forge_data: None,
             mod_info: None,
             sample: None,
+            extras: Default::default(),
+            raw,
+        }),
+        _ => Err(Error::UnsupportedProtocol),
+    }
+}
+
+fn parse_beta(s: &str, raw: Vec<u8>) -> Result<Response> {
+    // the motd can contain the section sign, but the player counts cannot
+    let mut fields = s.rsplitn(3, '\u{00a7}');
+    let max_players = fields.next().and_then(|s| s.parse().ok());
+    let players = fields.next().and_then(|s| s.parse().ok());
+    let motd = fields.next();
+    match (motd, players, max_players) {
+        (Some(motd), Some(players), Some(max_players)) => Ok(Response {
+            protocol: -1,
+            enforces_secure_chat: None,
+            previews_chat: None,
+            version: String::new(),
+            description: Chat {
+                text: motd.to_string(),
+                ..Default::default()
+            },
+            online_players: players,
+            max_players,
+            favicon: None,
+            forge_data: None,
+            mod_info: None,
+            sample: None,
+            extras: Default::default(),
             raw,
         }),
         _ => Err(Error::UnsupportedProtocol),
     }
 }
 
+// the ping packet sent after the status response, whose pong echoes the payload back
+#[cfg(any(feature = "sync", feature = "async-tokio", feature = "async-futures"))]
+fn build_ping_request(payload: i64) -> Vec<u8> {
+    let mut request = vec![
+        9,    // length of packet id + data as VarInt
+        0x01, // packet id: 1 for ping as VarInt
+    ];
+    request.extend_from_slice(&payload.to_be_bytes());
+    request
+}
+
+// the current time in milliseconds, as the vanilla client uses for the ping payload
+#[cfg(any(feature = "sync", feature = "async-tokio", feature = "async-futures"))]
+fn ping_payload() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as i64)
+}
+
+// a read timeout is reported as WouldBlock on unix, and as TimedOut on windows
+#[cfg(any(feature = "sync", feature = "async-tokio", feature = "icmp"))]
+fn read_timed_out<T>(result: std::io::Result<T>) -> std::io::Result<T> {
+    result.map_err(|error| match error.kind() {
+        std::io::ErrorKind::WouldBlock => std::io::ErrorKind::TimedOut.into(),
+        _ => error,
+    })
+}
+
 // used in read_varint implemenetation
 const LAST_SEVEN_BITS: i32 = 0b0111_1111;
 const NEXT_BYTE_EXISTS: u8 = 0b1000_0000;
@@ -184,3 +602,53 @@ fn write_varint(sink: &mut Vec<u8>, mut value: i32) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_legacy_prefixes() {
+        let status = ["\u{a7}1", "78", "1.6.4", "A Minecraft Server", "0", "20"].join("\0");
+        let mut packet = vec![0xff, 0x00, status.encode_utf16().count() as u8];
+        packet.extend(status.encode_utf16().flat_map(u16::to_be_bytes));
+
+        assert_eq!(parse_legacy_response(&packet).unwrap().raw(), packet);
+        // every prefix cut before the max players is rejected
+        let max_players = packet.len() - 4;
+        for length in 0..max_players {
+            assert!(parse_legacy_response(&packet[..length]).is_err());
+        }
+        assert!(parse_legacy_response(&[0xff, 0x00, 0x01, 0xd8, 0x00]).is_err());
+        // the length in the header must match the packet
+        let mut trailing = packet.clone();
+        trailing.extend_from_slice(&[0x00, 0x30]);
+        assert!(parse_legacy_response(&trailing).is_err());
+        assert!(parse_legacy_response(&packet[..packet.len() - 2]).is_err());
+
+        let status = "A \u{a7}cServer\u{a7}3\u{a7}20";
+        let mut packet = vec![0xff, 0x00, status.encode_utf16().count() as u8];
+        packet.extend(status.encode_utf16().flat_map(u16::to_be_bytes));
+        let response = parse_legacy_response(&packet).unwrap();
+        assert_eq!(response.description.text, "A \u{a7}cServer");
+        assert_eq!((response.online_players, response.max_players), (3, 20));
+        assert_eq!(response.protocol, -1);
+    }
+
+    #[test]
+    fn build_legacy_requests() {
+        let request = build_legacy_request("a.b", 25565, &PingConfig::default());
+        assert_eq!(request[..27], LEGACY_REQUEST_HEAD);
+        assert_eq!(
+            request[27..],
+            [0x00, 13, 0x4a, 0x00, 3, 0, b'a', 0, b'.', 0, b'b', 0, 0, 0x63, 0xdd]
+        );
+
+        let config = PingConfig {
+            virtual_host: Some(String::new()),
+            ..Default::default()
+        };
+        let request = build_legacy_request("a.b", 0, &config);
+        assert_eq!(request[27..], [0x00, 7, 0x4a, 0x00, 0, 0, 0, 0, 0]);
+    }
+}
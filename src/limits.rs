@@ -0,0 +1,310 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{Error as _, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{Chat, ForgeMod, ModInfoItem, Player};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The resource limits enforced while receiving and parsing a response.
+///
+/// A response exceeding any of the limits is rejected with [`UnsupportedProtocol`](crate::Error::UnsupportedProtocol)
+/// before the memory for the offending field is allocated.
+pub struct Limits {
+    /// The maximum size of the whole response in bytes. Defaults to 2097151, the maximum packet size of the vanilla client.
+    pub max_response_size: usize,
+    /// The maximum size of the decoded favicon in bytes. Defaults to 65536.
+    pub max_favicon_size: usize,
+    /// The maximum number of the sample players. Defaults to 1024.
+    pub max_sample_entries: usize,
+    /// The maximum number of the entries in a mod list. Defaults to 4096.
+    pub max_mod_entries: usize,
+    /// The maximum nesting depth of the [`Chat`](Chat) extras. Defaults to 32.
+    pub max_chat_depth: usize,
+}
+
+impl Limits {
+    const DEFAULT: Self = Self {
+        max_response_size: 2097151,
+        max_favicon_size: 65536,
+        max_sample_entries: 1024,
+        max_mod_entries: 4096,
+        max_chat_depth: 32,
+    };
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(feature = "std")]
+mod state {
+    use std::cell::Cell;
+
+    use super::Limits;
+
+    thread_local! {
+        // the limits of the response currently being deserialized
+        static CURRENT: Cell<Limits> = const { Cell::new(Limits::DEFAULT) };
+        // the nesting depth of the chat currently being deserialized
+        static CHAT_DEPTH: Cell<usize> = const { Cell::new(0) };
+    }
+
+    pub(super) fn replace(limits: Limits) -> Limits {
+        CURRENT.with(|current| current.replace(limits))
+    }
+
+    pub(super) fn current() -> Limits {
+        CURRENT.with(Cell::get)
+    }
+
+    pub(super) fn chat_depth() -> usize {
+        CHAT_DEPTH.with(Cell::get)
+    }
+
+    pub(super) fn set_chat_depth(depth: usize) {
+        CHAT_DEPTH.with(|current| current.set(depth))
+    }
+}
+
+// without threads, the state is shared by the whole program,
+// so the responses should not be deserialized concurrently with the different limits
+#[cfg(not(feature = "std"))]
+mod state {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::Limits;
+
+    static MAX_RESPONSE_SIZE: AtomicUsize = AtomicUsize::new(Limits::DEFAULT.max_response_size);
+    static MAX_FAVICON_SIZE: AtomicUsize = AtomicUsize::new(Limits::DEFAULT.max_favicon_size);
+    static MAX_SAMPLE_ENTRIES: AtomicUsize = AtomicUsize::new(Limits::DEFAULT.max_sample_entries);
+    static MAX_MOD_ENTRIES: AtomicUsize = AtomicUsize::new(Limits::DEFAULT.max_mod_entries);
+    static MAX_CHAT_DEPTH: AtomicUsize = AtomicUsize::new(Limits::DEFAULT.max_chat_depth);
+    static CHAT_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+    pub(super) fn replace(limits: Limits) -> Limits {
+        let previous = current();
+        MAX_RESPONSE_SIZE.store(limits.max_response_size, Ordering::Relaxed);
+        MAX_FAVICON_SIZE.store(limits.max_favicon_size, Ordering::Relaxed);
+        MAX_SAMPLE_ENTRIES.store(limits.max_sample_entries, Ordering::Relaxed);
+        MAX_MOD_ENTRIES.store(limits.max_mod_entries, Ordering::Relaxed);
+        MAX_CHAT_DEPTH.store(limits.max_chat_depth, Ordering::Relaxed);
+        previous
+    }
+
+    pub(super) fn current() -> Limits {
+        Limits {
+            max_response_size: MAX_RESPONSE_SIZE.load(Ordering::Relaxed),
+            max_favicon_size: MAX_FAVICON_SIZE.load(Ordering::Relaxed),
+            max_sample_entries: MAX_SAMPLE_ENTRIES.load(Ordering::Relaxed),
+            max_mod_entries: MAX_MOD_ENTRIES.load(Ordering::Relaxed),
+            max_chat_depth: MAX_CHAT_DEPTH.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(super) fn chat_depth() -> usize {
+        CHAT_DEPTH.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_chat_depth(depth: usize) {
+        CHAT_DEPTH.store(depth, Ordering::Relaxed)
+    }
+}
+
+/// Run `f` with the limits applied to every deserialization inside it.
+pub(crate) fn with_limits<R>(limits: &Limits, f: impl FnOnce() -> R) -> R {
+    let _restore = Restore {
+        chat_depth: state::chat_depth(),
+        limits: state::replace(*limits),
+    };
+    f()
+}
+
+// restores the state on drop, even if the deserialization panics
+struct Restore {
+    limits: Limits,
+    chat_depth: usize,
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        state::replace(self.limits);
+        state::set_chat_depth(self.chat_depth);
+    }
+}
+
+fn current() -> Limits {
+    state::current()
+}
+
+struct LimitedVec<T> {
+    limit: usize,
+    item: PhantomData<T>,
+}
+
+impl<T> LimitedVec<T> {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            item: PhantomData,
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for LimitedVec<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a sequence of at most {} elements", self.limit)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let capacity = seq.size_hint().unwrap_or(0).min(self.limit);
+        let mut vec = Vec::with_capacity(capacity);
+        while let Some(item) = seq.next_element()? {
+            if vec.len() == self.limit {
+                return Err(A::Error::invalid_length(vec.len() + 1, &self));
+            }
+            vec.push(item);
+        }
+        Ok(vec)
+    }
+}
+
+struct LimitedString {
+    limit: usize,
+}
+
+impl<'de> Visitor<'de> for LimitedString {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a string of at most {} bytes", self.limit)
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        if v.len() > self.limit {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        Ok(v.to_string())
+    }
+}
+
+struct Optional<V>(V);
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Optional<V> {
+    type Value = Option<V::Value>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.expecting(f)?;
+        write!(f, " or null")
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self.0).map(Some)
+    }
+}
+
+pub(crate) fn favicon<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    // "data:image/png;base64," followed by the base64-encoded image
+    let limit = 22 + current().max_favicon_size.div_ceil(3) * 4;
+    deserializer.deserialize_option(Optional(LimitedString { limit }))
+}
+
+pub(crate) fn sample<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<Player>>, D::Error> {
+    let limit = current().max_sample_entries;
+    deserializer.deserialize_option(Optional(LimitedVec::new(limit)))
+}
+
+pub(crate) fn mod_info_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ModInfoItem>, D::Error> {
+    deserializer.deserialize_seq(LimitedVec::new(current().max_mod_entries))
+}
+
+pub(crate) fn forge_mods<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ForgeMod>, D::Error> {
+    deserializer.deserialize_seq(LimitedVec::new(current().max_mod_entries))
+}
+
+pub(crate) fn chat_extra<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Chat>, D::Error> {
+    let depth = state::chat_depth() + 1;
+    if depth > current().max_chat_depth {
+        return Err(D::Error::custom("chat is nested too deeply"));
+    }
+    state::set_chat_depth(depth);
+    let result = Vec::deserialize(deserializer);
+    state::set_chat_depth(depth - 1);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::decode_latest_response;
+
+    #[test]
+    fn reject_exceeding_limits() {
+        let limits = Limits {
+            max_sample_entries: 1,
+            max_chat_depth: 1,
+            ..Default::default()
+        };
+        let response = |sample: &str, description: &str| {
+            format!(
+                r#"{{
+                    "version": {{ "name": "1.20.4", "protocol": 765 }},
+                    "players": {{ "max": 20, "online": 2, "sample": {} }},
+                    "description": {}
+                }}"#,
+                sample, description
+            )
+        };
+        let player = r#"{ "name": "Notch", "id": "069a79f4-44e9-4726-a5be-fca90e38aaf5" }"#;
+        let one = format!("[{}]", player);
+        let two = format!("[{}, {}]", player, player);
+        let flat = r#"{ "text": "", "extra": [{ "text": "A" }] }"#;
+        let nested = r#"{ "text": "", "extra": [{ "text": "", "extra": [{ "text": "A" }] }] }"#;
+
+        assert!(decode_latest_response(response(&one, flat).as_bytes(), &limits).is_ok());
+        assert!(decode_latest_response(response(&two, flat).as_bytes(), &limits).is_err());
+        assert!(decode_latest_response(response(&one, nested).as_bytes(), &limits).is_err());
+        assert!(
+            decode_latest_response(response("null", nested).as_bytes(), &Limits::default()).is_ok()
+        );
+    }
+
+    #[test]
+    fn restore_limits_on_panic() {
+        let limits = Limits {
+            max_sample_entries: 1,
+            ..Default::default()
+        };
+        let result = std::panic::catch_unwind(|| with_limits(&limits, || panic!("parser bug")));
+        assert!(result.is_err());
+        assert_eq!(current(), Limits::default());
+    }
+}
@@ -0,0 +1,641 @@
+//! Provides a config-driven [`Monitor`](Monitor) which periodically pings servers.
+//!
+//! The [`Monitor`](Monitor) polls every configured server on its own interval,
+//! hands every result to the exporters and every change of the servers, like going offline, to the notifiers.
+//! The changes are computed by [`EventTracker`](crate::watch::EventTracker), as [`Watcher::events`](crate::watch::Watcher::events) does.
+//! [`MonitorConfig`](MonitorConfig) implements [`Deserialize`](serde::Deserialize),
+//! so it can be loaded from any format supported by serde.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use craftping::monitor::{Monitor, MonitorConfig};
+//!
+//! let config: MonitorConfig = serde_json::from_str(r#"{
+//!     "servers": [{ "hostname": "my.server.com", "interval_secs": 30 }],
+//!     "notifiers": [{ "type": "stderr" }],
+//!     "exporters": [{ "type": "ndjson", "path": "status.ndjson" }]
+//! }"#).unwrap();
+//! Monitor::new(config).unwrap().run().unwrap();
+//! ```
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+pub use crate::sink::Report;
+use crate::{
+    sink::NdjsonSink,
+    sync::ping_with_latency,
+    transport::{TcpTransport, Transport},
+    watch::{Event, EventTracker, Schedule},
+    CancelToken, IpPreference, Response, Result,
+};
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+/// The configuration of a [`Monitor`](Monitor).
+pub struct MonitorConfig {
+    /// The servers to poll.
+    pub servers: Vec<ServerConfig>,
+    #[serde(default)]
+    /// The notifiers which receive the changes of the servers.
+    pub notifiers: Vec<NotifierConfig>,
+    #[serde(default)]
+    /// The exporters which receive every poll result.
+    pub exporters: Vec<ExporterConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+/// The configuration of a monitored server.
+pub struct ServerConfig {
+    #[serde(default)]
+    /// The name used in reports and notifications.
+    /// `None` to use `hostname:port`.
+    pub name: Option<String>,
+    /// The hostname of the server.
+    pub hostname: String,
+    #[serde(default = "default_port")]
+    /// The port of the server. Defaults to 25565.
+    pub port: u16,
+    #[serde(default = "default_interval_secs")]
+    /// The interval between polls in seconds. Defaults to 60.
+    pub interval_secs: u64,
+    #[serde(default = "default_timeout_secs")]
+    /// The connect, read and write timeout in seconds. Defaults to 5.
+    /// It should not be zero.
+    pub timeout_secs: u64,
+    #[serde(default = "default_dns_cache_secs")]
+    /// The longest time the resolved addresses are reused in seconds, within the TTL of the records. Defaults to 300.
+    /// `0` to resolve the hostname on every poll.
+    ///
+    /// See also [`TcpTransport::dns_cache_ttl`](TcpTransport::dns_cache_ttl).
+    pub dns_cache_secs: u64,
+    #[serde(default)]
+    /// The IP family to connect with. Defaults to [`IpPreference::Any`](IpPreference::Any).
+    pub ip_preference: IpPreference,
+    #[serde(default)]
+    /// How early the connection is opened before the poll is due, in seconds. Defaults to 0.
+    ///
+    /// Opening the connection ahead lets the poll start exactly when it is due,
+    /// without waiting for the name resolution and the connection.
+    /// The [`latency_ms`](Report::latency_ms) of the report is measured over the prewarmed connection.
+    /// It should be shorter than the time the server keeps an idle connection open.
+    pub prewarm_secs: u64,
+}
+
+impl ServerConfig {
+    /// The name used in reports and notifications.
+    pub fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("{}:{}", self.hostname, self.port),
+        }
+    }
+}
+
+fn default_port() -> u16 {
+    25565
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_timeout_secs() -> u64 {
+    5
+}
+
+fn default_dns_cache_secs() -> u64 {
+    300
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// The configuration of a built-in [`Notifier`](Notifier).
+pub enum NotifierConfig {
+    /// Prints a line to the standard error each time a server comes online or goes offline.
+    Stderr,
+    /// Runs a command each time a server comes online or goes offline.
+    ///
+    /// The environment variables `CRAFTPING_SERVER` and `CRAFTPING_STATUS` (`online` or `offline`)
+    /// are set for the command.
+    Command {
+        /// The program to run.
+        program: String,
+        #[serde(default)]
+        /// The arguments passed to the program.
+        args: Vec<String>,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// The configuration of a built-in [`Exporter`](Exporter).
+pub enum ExporterConfig {
+    /// Appends each [`Report`](Report) as a JSON line to the file.
+    Ndjson {
+        /// The path of the file.
+        path: PathBuf,
+    },
+    /// Prints each [`Report`](Report) as a JSON line to the standard output.
+    Stdout,
+    /// Appends each [`Report`](Report) to the file in the Elasticsearch bulk API format.
+    ///
+    /// See also [`write_elasticsearch_bulk`](write_elasticsearch_bulk).
+    ElasticsearchBulk {
+        /// The path of the file.
+        path: PathBuf,
+        /// The index the reports are indexed into.
+        index: String,
+    },
+}
+
+#[derive(Debug)]
+/// A change of a server, passed to the notifiers.
+///
+/// The first poll of a server is always reported as [`CameOnline`](Event::CameOnline) or [`WentOffline`](Event::WentOffline).
+pub struct Notification<'a> {
+    /// The server which changed.
+    pub server: &'a ServerConfig,
+    /// The change of the server.
+    pub event: &'a Event,
+    /// The result of the poll which caused the change.
+    pub result: &'a Result<Response>,
+}
+
+/// Receives the changes of the monitored servers.
+pub trait Notifier {
+    /// Called once for each change.
+    fn notify(&mut self, notification: &Notification) -> Result<()>;
+}
+
+/// Receives the result of every poll.
+pub trait Exporter {
+    /// Called once for each poll.
+    fn export(&mut self, report: &Report) -> Result<()>;
+}
+
+struct StderrNotifier;
+
+impl Notifier for StderrNotifier {
+    fn notify(&mut self, notification: &Notification) -> Result<()> {
+        let server = notification.server.display_name();
+        match (notification.event, notification.result) {
+            (Event::CameOnline, _) => eprintln!("{} is online", server),
+            (Event::WentOffline, Err(error)) => eprintln!("{} is offline: {}", server, error),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+struct CommandNotifier {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&mut self, notification: &Notification) -> Result<()> {
+        let status = match notification.event {
+            Event::CameOnline => "online",
+            Event::WentOffline => "offline",
+            _ => return Ok(()),
+        };
+        Command::new(&self.program)
+            .args(&self.args)
+            .env("CRAFTPING_SERVER", notification.server.display_name())
+            .env("CRAFTPING_STATUS", status)
+            .status()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Exporter for NdjsonSink<W> {
+    fn export(&mut self, report: &Report) -> Result<()> {
+        self.write_report(report)
+    }
+}
+
+struct ElasticsearchBulkExporter<W> {
+    writer: W,
+    index: String,
+}
+
+impl<W: Write> Exporter for ElasticsearchBulkExporter<W> {
+    fn export(&mut self, report: &Report) -> Result<()> {
+        write_elasticsearch_bulk(&mut self.writer, &self.index, [report])?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Write the reports in the Elasticsearch bulk API (NDJSON) format, to be indexed into `index`.
+///
+/// Each report is preceded by its `index` action line,
+/// and has an additional `@timestamp` field with its timestamp in RFC 3339 format.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn run(reports: Vec<craftping::monitor::Report>) -> craftping::Result<()> {
+/// use craftping::monitor::write_elasticsearch_bulk;
+///
+/// let mut body = vec![];
+/// write_elasticsearch_bulk(&mut body, "minecraft-status", &reports)?;
+/// // POST the body to /_bulk with `Content-Type: application/x-ndjson`
+/// # Ok(())
+/// # }
+/// ```
+pub fn write_elasticsearch_bulk<'a>(
+    mut writer: impl Write,
+    index: &str,
+    reports: impl IntoIterator<Item = &'a Report>,
+) -> Result<()> {
+    let action = serde_json::json!({ "index": { "_index": index } });
+    for report in reports {
+        let mut document = serde_json::to_value(report).map_err(io::Error::from)?;
+        document["@timestamp"] = rfc3339(report.timestamp).into();
+        serde_json::to_writer(&mut writer, &action).map_err(io::Error::from)?;
+        writer.write_all(b"\n")?;
+        serde_json::to_writer(&mut writer, &document).map_err(io::Error::from)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// format seconds since the unix epoch as an RFC 3339 UTC timestamp
+fn rfc3339(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+    // civil_from_days from http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+struct Target<T: Transport> {
+    config: ServerConfig,
+    transport: T,
+    schedule: Schedule,
+    tracker: EventTracker,
+    prewarmed: Option<Result<T::Stream>>,
+}
+
+impl<T: Transport> Target<T> {
+    fn prewarm_at(&self) -> Instant {
+        let prewarm = Duration::from_secs(self.config.prewarm_secs);
+        let next_poll = self.schedule.next_ping();
+        next_poll.checked_sub(prewarm).unwrap_or(next_poll)
+    }
+
+    // the time this target needs attention: either to prewarm or to poll
+    fn next_wake(&self) -> Instant {
+        if self.config.prewarm_secs > 0 && self.prewarmed.is_none() {
+            self.prewarm_at()
+        } else {
+            self.schedule.next_ping()
+        }
+    }
+
+    fn prewarm(&mut self) {
+        let config = &self.config;
+        self.prewarmed = Some(self.transport.connect(&config.hostname, config.port));
+    }
+
+    fn ping(&mut self) -> Result<(Response, Duration)> {
+        let hostname = &self.config.hostname;
+        let port = self.config.port;
+        let mut stream = match self.prewarmed.take() {
+            Some(stream) => stream?,
+            None => self.transport.connect(hostname, port)?,
+        };
+        ping_with_latency(&mut stream, hostname, port)
+    }
+}
+
+/// The runtime which polls the configured servers.
+///
+/// Each server is connected with its own [`Transport`](Transport),
+/// which is [`TcpTransport`](TcpTransport) unless created with [`with_transport`](Monitor::with_transport).
+///
+/// See also [`MonitorConfig`](MonitorConfig).
+pub struct Monitor<T: Transport = TcpTransport> {
+    targets: Vec<Target<T>>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    exporters: Vec<Box<dyn Exporter>>,
+}
+
+impl Monitor {
+    /// Create a monitor from the configuration.
+    ///
+    /// This fails if any of the exporter files cannot be opened.
+    pub fn new(config: MonitorConfig) -> Result<Self> {
+        Self::with_transport(config, |server| {
+            TcpTransport::new(Duration::from_secs(server.timeout_secs))
+                .ip_preference(server.ip_preference)
+                .dns_cache_ttl(Duration::from_secs(server.dns_cache_secs))
+        })
+    }
+
+    /// Forget the cached addresses of every server,
+    /// so that the hostnames are resolved again on their next poll.
+    ///
+    /// See also [`TcpTransport::invalidate_dns_cache`](TcpTransport::invalidate_dns_cache).
+    pub fn invalidate_dns_cache(&mut self) {
+        for target in &mut self.targets {
+            target.transport.invalidate_dns_cache();
+        }
+    }
+}
+
+impl<T: Transport> Monitor<T> {
+    /// Create a monitor from the configuration, connecting each server with the transport `transport` returns.
+    ///
+    /// The `timeout_secs`, `dns_cache_secs` and `ip_preference` of the servers are only
+    /// applied by the transport itself.
+    ///
+    /// This fails if any of the exporter files cannot be opened.
+    pub fn with_transport(
+        config: MonitorConfig,
+        mut transport: impl FnMut(&ServerConfig) -> T,
+    ) -> Result<Self> {
+        let targets = config
+            .servers
+            .into_iter()
+            .map(|config| Target {
+                transport: transport(&config),
+                schedule: Schedule::new(Duration::from_secs(config.interval_secs)),
+                config,
+                tracker: EventTracker::new(),
+                prewarmed: None,
+            })
+            .collect();
+        let notifiers = config
+            .notifiers
+            .into_iter()
+            .map(|notifier| -> Box<dyn Notifier> {
+                match notifier {
+                    NotifierConfig::Stderr => Box::new(StderrNotifier),
+                    NotifierConfig::Command { program, args } => {
+                        Box::new(CommandNotifier { program, args })
+                    }
+                }
+            })
+            .collect();
+        let exporters = config
+            .exporters
+            .into_iter()
+            .map(|exporter| -> Result<Box<dyn Exporter>> {
+                Ok(match exporter {
+                    ExporterConfig::Ndjson { path } => {
+                        let file = OpenOptions::new().create(true).append(true).open(path)?;
+                        Box::new(NdjsonSink::new(BufWriter::<File>::new(file)))
+                    }
+                    ExporterConfig::Stdout => Box::new(NdjsonSink::new(io::stdout())),
+                    ExporterConfig::ElasticsearchBulk { path, index } => {
+                        let file = OpenOptions::new().create(true).append(true).open(path)?;
+                        Box::new(ElasticsearchBulkExporter {
+                            writer: BufWriter::new(file),
+                            index,
+                        })
+                    }
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            targets,
+            notifiers,
+            exporters,
+        })
+    }
+
+    /// Add a custom notifier in addition to the configured ones.
+    pub fn add_notifier(&mut self, notifier: impl Notifier + 'static) {
+        self.notifiers.push(Box::new(notifier));
+    }
+
+    /// Add a custom exporter in addition to the configured ones.
+    pub fn add_exporter(&mut self, exporter: impl Exporter + 'static) {
+        self.exporters.push(Box::new(exporter));
+    }
+
+    /// Poll the servers forever.
+    ///
+    /// This returns only when a notifier or an exporter fails.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            let wait = self.tick()?;
+            thread::sleep(wait);
+        }
+    }
+
+    /// Poll the servers until the token is cancelled.
+    ///
+    /// The poll in flight is finished and exported before this returns,
+    /// and no more polls are started after the cancellation.
+    /// This also returns when a notifier or an exporter fails.
+    pub fn run_until(&mut self, token: &CancelToken) -> Result<()> {
+        loop {
+            let wait = self.tick_until(Some(token))?;
+            if token.wait_timeout(wait) {
+                break Ok(());
+            }
+        }
+    }
+
+    /// Poll every server whose poll is due, and return the time until the next poll is due.
+    ///
+    /// Use this instead of [`run`](Monitor::run) to drive the monitor from your own loop.
+    pub fn tick(&mut self) -> Result<Duration> {
+        self.tick_until(None)
+    }
+
+    fn tick_until(&mut self, token: Option<&CancelToken>) -> Result<Duration> {
+        for index in 0..self.targets.len() {
+            if token.is_some_and(CancelToken::is_cancelled) {
+                break;
+            }
+            let target = &mut self.targets[index];
+            if target.schedule.next_ping() <= Instant::now() {
+                self.poll(index)?;
+            } else if target.next_wake() <= Instant::now() {
+                target.prewarm();
+            }
+        }
+        let now = Instant::now();
+        Ok(self
+            .targets
+            .iter()
+            .map(|target| target.next_wake().saturating_duration_since(now))
+            .min()
+            .unwrap_or(Duration::from_secs(default_interval_secs())))
+    }
+
+    fn poll(&mut self, index: usize) -> Result<()> {
+        let target = &mut self.targets[index];
+        target.schedule.start();
+        let mut latency = None;
+        let result = target.ping().map(|(response, rtt)| {
+            latency = Some(rtt);
+            response
+        });
+
+        for event in target.tracker.update(&result) {
+            let notification = Notification {
+                server: &target.config,
+                event: &event,
+                result: &result,
+            };
+            for notifier in &mut self.notifiers {
+                notifier.notify(&notification)?;
+            }
+        }
+
+        let mut report = Report::new(target.config.display_name(), &result);
+        report.latency_ms = latency.map(|latency| latency.as_secs_f64() * 1000.0);
+        for exporter in &mut self.exporters {
+            exporter.export(&report)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Error;
+
+    use std::{cell::RefCell, net::TcpStream, rc::Rc};
+
+    #[test]
+    fn deserialize_config() {
+        let config: MonitorConfig = serde_json::from_str(
+            r#"{
+                "servers": [{ "hostname": "localhost" }],
+                "notifiers": [{ "type": "command", "program": "true" }]
+            }"#,
+        )
+        .unwrap();
+        let server = &config.servers[0];
+        assert_eq!(server.port, 25565);
+        assert_eq!(server.interval_secs, 60);
+        assert_eq!(server.dns_cache_secs, 300);
+        assert_eq!(server.prewarm_secs, 0);
+        assert_eq!(server.display_name(), "localhost:25565");
+        assert!(matches!(
+            &config.notifiers[0],
+            NotifierConfig::Command { program, args } if program == "true" && args.is_empty()
+        ));
+        assert!(config.exporters.is_empty());
+    }
+
+    #[test]
+    fn write_bulk() {
+        let report = Report {
+            server: "localhost:25565".to_string(),
+            timestamp: 1700000000,
+            response: None,
+            error: Some("connection refused".to_string()),
+            latency_ms: None,
+        };
+        let mut body = vec![];
+        write_elasticsearch_bulk(&mut body, "status", [&report]).unwrap();
+        let body = String::from_utf8(body).unwrap();
+        let lines: Vec<_> = body.lines().collect();
+        assert_eq!(lines[0], r#"{"index":{"_index":"status"}}"#);
+        let document: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(document["@timestamp"], "2023-11-14T22:13:20Z");
+        assert_eq!(document["error"], "connection refused");
+    }
+
+    #[test]
+    fn notify_transition() {
+        struct Recorder(Rc<RefCell<Vec<Event>>>);
+
+        impl Notifier for Recorder {
+            fn notify(&mut self, notification: &Notification) -> Result<()> {
+                self.0.borrow_mut().push(notification.event.clone());
+                Ok(())
+            }
+        }
+
+        let config: MonitorConfig = serde_json::from_str(
+            r#"{ "servers": [{ "hostname": "localhost", "interval_secs": 0 }] }"#,
+        )
+        .unwrap();
+        let mut monitor = Monitor::with_transport(config, |_| {
+            |_: &str, _: u16| -> Result<TcpStream> { Err(Error::UnsupportedProtocol) }
+        })
+        .unwrap();
+        let events = Rc::new(RefCell::new(vec![]));
+        monitor.add_notifier(Recorder(events.clone()));
+        monitor.tick().unwrap();
+        monitor.tick().unwrap();
+        assert_eq!(*events.borrow(), [Event::WentOffline]);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn measure_latency_over_prewarmed_connection() {
+        use crate::{
+            testing::{MockServer, Reply},
+            Chat,
+        };
+
+        struct Latencies(Rc<RefCell<Vec<Option<f64>>>>);
+
+        impl Exporter for Latencies {
+            fn export(&mut self, report: &Report) -> Result<()> {
+                self.0.borrow_mut().push(report.latency_ms);
+                Ok(())
+            }
+        }
+
+        let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+        let server = MockServer::start(Reply::status(response)).unwrap();
+        let config: MonitorConfig = serde_json::from_value(serde_json::json!({
+            "servers": [{ "hostname": "localhost", "port": server.address().port(), "prewarm_secs": 1 }]
+        }))
+        .unwrap();
+        // counts the connections, to tell the prewarmed one is reused
+        let connects = Rc::new(RefCell::new(0));
+        let mut monitor = Monitor::with_transport(config, |_| {
+            let connects = connects.clone();
+            move |_: &str, port: u16| -> Result<TcpStream> {
+                *connects.borrow_mut() += 1;
+                Ok(TcpStream::connect(("localhost", port))?)
+            }
+        })
+        .unwrap();
+        let latencies = Rc::new(RefCell::new(vec![]));
+        monitor.add_exporter(Latencies(latencies.clone()));
+        monitor.targets[0].prewarm();
+        assert_eq!(*connects.borrow(), 1);
+        monitor.tick().unwrap();
+        assert_eq!(*connects.borrow(), 1);
+        assert!(monitor.targets[0].prewarmed.is_none());
+        assert!(matches!(latencies.borrow()[..], [Some(latency)] if latency >= 0.0));
+    }
+}
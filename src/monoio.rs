@@ -0,0 +1,137 @@
+//! Provides asynchronous [`ping`](ping) function for the streams of the monoio runtime.
+//!
+//! Like the [`uring`](crate::uring) module, the functions here pass the owned buffers to the stream,
+//! as required by the `AsyncReadRent` and `AsyncWriteRent` traits of monoio.
+use std::io;
+
+use ::monoio::io::{AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt};
+
+use crate::{completion::OwnedIo, *};
+
+/// Send a ping request to the server and return a future response.
+///
+/// See also [`Response`](Response).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::monoio::ping;
+/// use monoio::net::TcpStream;
+///
+/// #[monoio::main]
+/// async fn main() {
+///     let mut stream = TcpStream::connect("127.0.0.1:25565").await.unwrap();
+///     let response = ping(&mut stream, "127.0.0.1", 25565).await.unwrap();
+///     println!("Players online: {}", response.online_players);
+/// }
+/// ```
+pub async fn ping<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: AsyncReadRent + AsyncWriteRent,
+{
+    ping_with_config(stream, hostname, port, &PingConfig::default()).await
+}
+
+/// Send a ping request to the server with the options and return a future response.
+///
+/// See also [`PingConfig`](PingConfig).
+pub async fn ping_with_config<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response>
+where
+    Stream: AsyncReadRent + AsyncWriteRent,
+{
+    completion::ping_with_config(&mut RentStream(stream), hostname, port, config).await
+}
+
+/// Send a ping request of the current protocol (1.7 and later) to the server and return a future response.
+///
+/// Unlike [`ping`](ping), it does not fall back to the legacy protocol.
+pub async fn ping_latest<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: AsyncReadRent + AsyncWriteRent,
+{
+    completion::request_latest(
+        &mut RentStream(stream),
+        hostname,
+        port,
+        &PingConfig::default(),
+    )
+    .await
+}
+
+/// Send a ping request of the legacy protocol (1.6) to the server and return a future response.
+///
+/// `hostname` and `port` are sent to the server as the 1.6 client does. Some proxies reject the request without them.
+pub async fn ping_legacy<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: AsyncReadRent + AsyncWriteRent,
+{
+    completion::request_legacy(
+        &mut RentStream(stream),
+        build_legacy_request(hostname, port, &PingConfig::default()),
+        &Limits::default(),
+    )
+    .await
+}
+
+struct RentStream<'a, S>(&'a mut S);
+
+impl<S> OwnedIo for RentStream<'_, S>
+where
+    S: AsyncReadRent + AsyncWriteRent,
+{
+    async fn read(&mut self, buffer: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+        self.0.read(buffer).await
+    }
+
+    async fn write_all(&mut self, buffer: Vec<u8>) -> (io::Result<()>, Vec<u8>) {
+        let (written, buffer) = self.0.write_all(buffer).await;
+        (written.map(|_| ()), buffer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ping_split_response() {
+        let json = r#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":0},"description":"Hello"}"#;
+        let mut body = vec![0x00];
+        write_varint(&mut body, json.len() as i32);
+        body.extend_from_slice(json.as_bytes());
+        let mut packet = vec![];
+        write_varint(&mut packet, body.len() as i32);
+        packet.extend_from_slice(&body);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            use std::io::{Read, Write};
+
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 16];
+            let _ = stream.read(&mut request).unwrap();
+            // the response arrives in two reads
+            let (head, tail) = packet.split_at(10);
+            stream.write_all(head).unwrap();
+            stream.flush().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            stream.write_all(tail).unwrap();
+        });
+
+        let mut runtime = ::monoio::RuntimeBuilder::<::monoio::LegacyDriver>::new()
+            .build()
+            .unwrap();
+        let response = runtime.block_on(async {
+            let mut stream = ::monoio::net::TcpStream::connect(address).await.unwrap();
+            ping(&mut stream, "127.0.0.1", address.port()).await
+        });
+        server.join().unwrap();
+        assert_eq!(response.unwrap().description.text, "Hello");
+    }
+}
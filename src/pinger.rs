@@ -0,0 +1,1469 @@
+//! Provides [`Pinger`](Pinger), which connects to a server and pings it with the timeouts.
+//!
+//! The `ping` functions accept any stream, leaving the connection and its timeouts to the caller.
+//! [`Pinger`](Pinger) owns the connection instead, so that a ping never hangs on an unresponsive server.
+//! It can also connect through a SOCKS5 or HTTP CONNECT [`Proxy`](crate::transport::Proxy),
+//! and reuse the resolved addresses across the pings with a [`DnsCache`](DnsCache).
+//! The hostnames are resolved with a [`Resolver`](Resolver), which is [`SystemResolver`](SystemResolver) by default.
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    capture::{CaptureStream, Exchange},
+    resolve::{self, DnsCache, Resolver, SystemResolver},
+    transport::{self, Proxy},
+    Error, IpPreference, PingConfig, ProxyHeader, Response, Result, ServerAddress,
+};
+
+// the delay between the connection attempts recommended by RFC 8305
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone)]
+/// A builder to connect to a server and ping it.
+///
+/// Every timeout is unset by default, which waits forever.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::pinger::Pinger;
+/// use std::time::Duration;
+///
+/// let response = Pinger::new("my.server.com", 25565)
+///     .connect_timeout(Duration::from_secs(3))
+///     .read_timeout(Duration::from_secs(5))
+///     .ping()
+///     .unwrap();
+/// println!("Players online: {}", response.online_players);
+/// ```
+pub struct Pinger {
+    hostname: String,
+    port: u16,
+    srv: bool,
+    resolver: SharedResolver,
+    ip_preference: IpPreference,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    proxy_header: Option<ProxyHeader>,
+    proxy: Option<Proxy>,
+    attempt_delay: Duration,
+    socket: SocketOptions,
+    dns_cache: DnsCache,
+    capture: Option<CaptureHook>,
+    #[cfg(feature = "tls")]
+    tls: Option<std::sync::Arc<rustls::ClientConfig>>,
+}
+
+impl Pinger {
+    /// Create a pinger to the server.
+    pub fn new(hostname: impl Into<String>, port: u16) -> Self {
+        Self {
+            hostname: hostname.into(),
+            port,
+            srv: false,
+            resolver: SharedResolver::new(SystemResolver::default()),
+            ip_preference: IpPreference::Any,
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            proxy_header: None,
+            proxy: None,
+            attempt_delay: CONNECTION_ATTEMPT_DELAY,
+            socket: SocketOptions::default(),
+            dns_cache: DnsCache::new(Duration::ZERO),
+            capture: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Create a pinger to the address, looking up its SRV records first if it has no port, as the vanilla client does.
+    ///
+    /// The targets of the records are tried in order until one is connected,
+    /// and the handshake carries the hostname of the address rather than the target.
+    /// The records are looked up with the [`resolver`](Pinger::resolver) even if the pinger has a [`proxy`](Pinger::proxy).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::{pinger::Pinger, ServerAddress};
+    ///
+    /// let address: ServerAddress = "my.server.com".parse().unwrap();
+    /// let response = Pinger::from_address(address).ping().unwrap();
+    /// ```
+    pub fn from_address(address: impl Into<ServerAddress>) -> Self {
+        let address = address.into();
+        let mut pinger = Self::new(address.hostname(), address.port());
+        pinger.srv = !address.has_port();
+        pinger
+    }
+
+    /// The hostname of the server.
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// The port of the server.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Set the timeout of connecting to each resolved address.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout of each read from the server.
+    ///
+    /// It is an inactivity timeout, failing the ping when no bytes arrive for the duration,
+    /// even in the middle of the response.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout of each write to the server.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the delay before starting to connect to the next resolved address while the previous attempt is pending.
+    /// Defaults to 250 milliseconds, as recommended by RFC 8305.
+    ///
+    /// See also [`ping`](Pinger::ping).
+    pub fn connection_attempt_delay(mut self, delay: Duration) -> Self {
+        self.attempt_delay = delay;
+        self
+    }
+
+    /// Set the resolver to resolve the hostnames and look up the SRV records with.
+    /// Defaults to [`SystemResolver`](SystemResolver).
+    ///
+    /// The resolver is cloned for each ping, so that the clones of the pinger resolve concurrently.
+    pub fn resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: Resolver + Clone + Send + Sync + 'static,
+    {
+        self.resolver = SharedResolver::new(resolver);
+        self
+    }
+
+    /// Set the IP family to connect with. Defaults to [`IpPreference::Any`](IpPreference::Any).
+    ///
+    /// The addresses of the preferred family are tried first, and the racing alternates the families from there.
+    pub fn ip_preference(mut self, ip_preference: IpPreference) -> Self {
+        self.ip_preference = ip_preference;
+        self
+    }
+
+    /// Reuse the resolved addresses and the SRV records of the cache, and cache the new ones.
+    /// Without a cache, the hostname is resolved on every ping.
+    pub fn dns_cache(mut self, cache: DnsCache) -> Self {
+        self.dns_cache = cache;
+        self
+    }
+
+    /// Set the options of the sockets, replacing the ones set by the shorthands like [`bind`](Pinger::bind).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::pinger::{Pinger, SocketOptions};
+    /// use std::time::Duration;
+    ///
+    /// let options = SocketOptions::new().ttl(64).linger(Some(Duration::ZERO));
+    /// let response = Pinger::new("my.server.com", 25565)
+    ///     .socket_options(options)
+    ///     .ping()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket = options;
+        self
+    }
+
+    /// Bind the connections to the local IP address, e.g. to choose the network of a multi-homed host.
+    ///
+    /// Only the resolved addresses of the same family as the local address are connected.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn bind(mut self, local: IpAddr) -> Self {
+        self.socket = self.socket.bind(local);
+        self
+    }
+
+    /// Bind the connections to the network interface with `SO_BINDTODEVICE`, e.g. `wg0` to ping through a VPN.
+    ///
+    /// Linux requires `CAP_NET_RAW` for it before 5.7.
+    #[cfg(all(
+        feature = "socket",
+        any(target_os = "android", target_os = "fuchsia", target_os = "linux")
+    ))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(all(
+            feature = "socket",
+            any(target_os = "android", target_os = "fuchsia", target_os = "linux")
+        )))
+    )]
+    pub fn bind_device(mut self, interface: impl Into<String>) -> Self {
+        self.socket = self.socket.bind_device(interface);
+        self
+    }
+
+    /// Set `TCP_NODELAY` of the connections, to disable the Nagle's algorithm if `true`.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.socket = self.socket.nodelay(nodelay);
+        self
+    }
+
+    /// Set `SO_LINGER` of the connections.
+    ///
+    /// `Some(Duration::ZERO)` resets the connections on close instead of leaving them in `TIME_WAIT`,
+    /// which keeps the ports of the hosts pinging many servers available.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn linger(mut self, linger: Option<Duration>) -> Self {
+        self.socket = self.socket.linger(linger);
+        self
+    }
+
+    /// Set the time-to-live of the IPv4 packets, or the hop limit of the IPv6 packets.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.socket = self.socket.ttl(ttl);
+        self
+    }
+
+    /// Enable the TCP keepalive of the connections, probing the server after it is idle for the duration.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.socket = self.socket.keepalive(idle);
+        self
+    }
+
+    /// Send the PROXY protocol header before the handshake.
+    ///
+    /// See also [`PingConfig::proxy_header`](PingConfig::proxy_header).
+    pub fn proxy_header(mut self, header: ProxyHeader) -> Self {
+        self.proxy_header = Some(header);
+        self
+    }
+
+    /// Connect to the server through the proxy.
+    ///
+    /// The connect timeout applies to connecting to the proxy,
+    /// and the read and write timeouts to the negotiation with the proxy as well.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Record the bytes of each ping, and pass them to the hook with the result of the ping, for debugging.
+    ///
+    /// The bytes are the ones of the ping itself, excluding the proxy and the TLS handshakes.
+    /// The hook is called for every ping which reached the server, whether it succeeded or not.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::pinger::Pinger;
+    ///
+    /// let pinger = Pinger::new("my.server.com", 25565).capture(|exchange, result| {
+    ///     if let Err(error) = result {
+    ///         eprintln!("{}\n{}", error, exchange);
+    ///     }
+    /// });
+    /// let response = pinger.ping();
+    /// ```
+    pub fn capture<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Exchange, &Result<Response>) + Send + Sync + 'static,
+    {
+        self.capture = Some(CaptureHook(Arc::new(hook)));
+        self
+    }
+
+    /// Wrap the connection in TLS with the config, for the servers behind a TLS-terminating bridge.
+    ///
+    /// The certificate of the server is verified against the hostname, and the TLS handshake
+    /// is subject to the read and write timeouts.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::pinger::Pinger;
+    /// use rustls::{ClientConfig, RootCertStore};
+    /// use std::sync::Arc;
+    ///
+    /// # fn run(roots: RootCertStore) {
+    /// let config = ClientConfig::builder()
+    ///     .with_root_certificates(roots)
+    ///     .with_no_client_auth();
+    /// let response = Pinger::new("my.server.com", 443)
+    ///     .tls(Arc::new(config))
+    ///     .ping()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    pub fn tls(mut self, config: std::sync::Arc<rustls::ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    fn server_name(&self) -> io::Result<rustls::pki_types::ServerName<'static>> {
+        rustls::pki_types::ServerName::try_from(self.hostname.clone())
+            .map_err(|_| ErrorKind::InvalidInput.into())
+    }
+
+    // forget the cached addresses if none of them could be connected, in case the server has moved
+    fn forget_unless_connected<T>(
+        &self,
+        target: &ServerAddress,
+        connected: io::Result<T>,
+    ) -> io::Result<T> {
+        if connected.is_err() {
+            self.dns_cache.forget(self.address(target).0);
+        }
+        connected
+    }
+
+    // the address to connect to for the target, which is the proxy if any
+    fn address<'a>(&'a self, target: &'a ServerAddress) -> (&'a str, u16) {
+        match &self.proxy {
+            Some(proxy) => (&proxy.hostname, proxy.port),
+            None => (target.hostname(), target.port()),
+        }
+    }
+
+    // the servers to try in order, which are the targets of the SRV records or the server itself
+    fn targets(&self) -> Vec<ServerAddress> {
+        if !self.srv {
+            return vec![ServerAddress::new(self.hostname.clone(), self.port)];
+        }
+        let mut resolver = self.resolver.get();
+        resolve::lookup_all_with(&mut self.dns_cache.resolver(&mut *resolver), &self.hostname)
+            .into_iter()
+            .map(|(hostname, port)| ServerAddress::new(hostname, port))
+            .collect()
+    }
+
+    // the addresses to connect to for the target, resolved or cached
+    fn resolve(&self, target: &ServerAddress) -> Result<Vec<SocketAddr>> {
+        let (hostname, port) = self.address(target);
+        let resolved = match hostname.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => {
+                let mut resolver = self.resolver.get();
+                self.dns_cache
+                    .resolver(&mut *resolver)
+                    .lookup_ip(hostname)?
+            }
+        };
+        let mut addresses: Vec<_> = resolved
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .filter(|address| self.socket.accepts(address))
+            .collect();
+        self.ip_preference.apply(&mut addresses);
+        if addresses.is_empty() {
+            return Err(io::Error::from(ErrorKind::NotFound).into());
+        }
+        Ok(addresses)
+    }
+
+    // the addresses of every target, for pinging them all
+    fn resolve_all(&self) -> Result<Vec<(ServerAddress, SocketAddr)>> {
+        let mut resolved = vec![];
+        let mut last_error = None;
+        for target in self.targets() {
+            match self.resolve(&target) {
+                Ok(addresses) => resolved.extend(
+                    addresses
+                        .into_iter()
+                        .map(|address| (target.clone(), address)),
+                ),
+                Err(error) => last_error = Some(error),
+            }
+        }
+        match last_error {
+            Some(error) if resolved.is_empty() => Err(error),
+            _ => Ok(resolved),
+        }
+    }
+
+    fn identity(
+        &self,
+        target: ServerAddress,
+        peer: SocketAddr,
+        local: SocketAddr,
+    ) -> ServerIdentity {
+        let address = match self.srv {
+            true => ServerAddress::without_port(self.hostname.clone()),
+            false => ServerAddress::new(self.hostname.clone(), self.port),
+        };
+        ServerIdentity {
+            address,
+            target,
+            peer,
+            local,
+        }
+    }
+
+    fn config(&self) -> PingConfig {
+        PingConfig {
+            proxy_header: self.proxy_header,
+            ..Default::default()
+        }
+    }
+
+    /// Connect to the server and ping it.
+    ///
+    /// The resolved addresses are raced as in Happy Eyeballs (RFC 8305), alternating the IPv6 and IPv4 addresses
+    /// and starting the next attempt every [`connection_attempt_delay`](Pinger::connection_attempt_delay)
+    /// until one is connected, so a broken address family does not stall the ping.
+    ///
+    /// See also [`sync::ping`](crate::sync::ping).
+    #[cfg(feature = "sync")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+    pub fn ping(&self) -> Result<Response> {
+        self.ping_with_identity().map(|(response, _)| response)
+    }
+
+    /// Connect to the server and ping it as [`ping`](Pinger::ping) does,
+    /// returning the [`ServerIdentity`](ServerIdentity) of the connection with the response.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::{pinger::Pinger, ServerAddress};
+    ///
+    /// let address: ServerAddress = "my.server.com".parse().unwrap();
+    /// let (response, identity) = Pinger::from_address(address).ping_with_identity().unwrap();
+    /// println!("{} via {} ({})", identity.address, identity.target, identity.peer);
+    /// ```
+    #[cfg(feature = "sync")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+    pub fn ping_with_identity(&self) -> Result<(Response, ServerIdentity)> {
+        let mut last_error = Error::Io(ErrorKind::NotFound.into());
+        for target in self.targets() {
+            let addresses = match self.resolve(&target) {
+                Ok(addresses) => addresses,
+                Err(error) => {
+                    last_error = error;
+                    continue;
+                }
+            };
+            let connected = connect(
+                addresses,
+                self.connect_timeout,
+                self.attempt_delay,
+                &self.socket,
+            );
+            match self.forget_unless_connected(&target, connected) {
+                Ok(stream) => {
+                    let identity =
+                        self.identity(target.clone(), stream.peer_addr()?, stream.local_addr()?);
+                    return Ok((self.ping_stream(stream, &target)?, identity));
+                }
+                // fail over to the next target
+                Err(error) => last_error = error.into(),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Connect to every resolved address of the server and ping them all concurrently,
+    /// returning the result of each address in the resolved order.
+    /// The addresses of every target of the SRV records are pinged, if they are looked up.
+    ///
+    /// It is useful for the round-robin clusters behind one hostname,
+    /// where [`ping`](Pinger::ping) reaches only one of the servers.
+    /// The pinger must not have a [`proxy`](Pinger::proxy), which would resolve the hostname by itself,
+    /// otherwise it fails with [`InvalidInput`](ErrorKind::InvalidInput).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::pinger::Pinger;
+    /// use std::time::Duration;
+    ///
+    /// let pinger = Pinger::new("my.server.com", 25565).connect_timeout(Duration::from_secs(3));
+    /// for (address, result) in pinger.ping_all().unwrap() {
+    ///     match result {
+    ///         Ok(response) => println!("{}: {} player(s) online", address, response.online_players),
+    ///         Err(error) => println!("{}: {}", address, error),
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "sync")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+    pub fn ping_all(&self) -> Result<Vec<(SocketAddr, Result<Response>)>> {
+        if self.proxy.is_some() {
+            return Err(io::Error::from(ErrorKind::InvalidInput).into());
+        }
+        let addresses = self.resolve_all()?;
+        let ping = |target: &ServerAddress, address| {
+            let stream = connect(
+                vec![address],
+                self.connect_timeout,
+                self.attempt_delay,
+                &self.socket,
+            )?;
+            self.ping_stream(stream, target)
+        };
+        // the targets without threads, like wasm32-wasip2, ping the addresses one by one
+        if cfg!(target_family = "wasm") {
+            return Ok(addresses
+                .iter()
+                .map(|(target, address)| (*address, ping(target, *address)))
+                .collect());
+        }
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let pings: Vec<_> = addresses
+                .iter()
+                .map(|(target, address)| scope.spawn(move || ping(target, *address)))
+                .collect();
+            pings
+                .into_iter()
+                .map(|ping| {
+                    ping.join()
+                        .unwrap_or_else(|_| Err(io::Error::other("ping panicked").into()))
+                })
+                .collect()
+        });
+        Ok(addresses
+            .into_iter()
+            .map(|(_, address)| address)
+            .zip(results)
+            .collect())
+    }
+
+    // ping the target through the connected stream, after the proxy and the TLS handshakes
+    #[cfg(feature = "sync")]
+    fn ping_stream(
+        &self,
+        mut stream: std::net::TcpStream,
+        target: &ServerAddress,
+    ) -> Result<Response> {
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+        if let Some(proxy) = &self.proxy {
+            transport::tunnel(&mut stream, proxy, target.hostname(), target.port())?;
+        }
+        #[cfg(feature = "tls")]
+        if let Some(config) = &self.tls {
+            let connection = rustls::ClientConnection::new(config.clone(), self.server_name()?)
+                .map_err(io::Error::other)?;
+            let stream = rustls::StreamOwned::new(connection, stream);
+            return self.ping_captured(stream, target.port());
+        }
+        self.ping_captured(stream, target.port())
+    }
+
+    // ping with the config, passing the exchange to the capture hook if any
+    #[cfg(feature = "sync")]
+    fn ping_captured<Stream>(&self, mut stream: Stream, port: u16) -> Result<Response>
+    where
+        Stream: io::Read + io::Write,
+    {
+        let Some(hook) = &self.capture else {
+            return crate::sync::ping_with_config(
+                &mut stream,
+                &self.hostname,
+                port,
+                &self.config(),
+            );
+        };
+        let mut stream = CaptureStream::new(stream);
+        let result =
+            crate::sync::ping_with_config(&mut stream, &self.hostname, port, &self.config());
+        (hook.0)(stream.exchange(), &result);
+        result
+    }
+
+    /// Connect to the server and ping it asynchronously, with the tokio runtime.
+    ///
+    /// The resolved addresses are raced as in [`ping`](Pinger::ping).
+    /// The read and write timeouts apply to each read and write, as in [`ping`](Pinger::ping).
+    ///
+    /// See also [`tokio::ping`](crate::tokio::ping).
+    #[cfg(feature = "async-tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
+    pub async fn ping_tokio(&self) -> Result<Response> {
+        self.ping_with_identity_tokio()
+            .await
+            .map(|(response, _)| response)
+    }
+
+    /// Connect to the server and ping it asynchronously as [`ping_tokio`](Pinger::ping_tokio) does,
+    /// returning the [`ServerIdentity`](ServerIdentity) of the connection with the response.
+    #[cfg(feature = "async-tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
+    pub async fn ping_with_identity_tokio(&self) -> Result<(Response, ServerIdentity)> {
+        let mut last_error = Error::Io(ErrorKind::NotFound.into());
+        for target in self.blocking(Pinger::targets).await? {
+            let resolving = target.clone();
+            let addresses = match self
+                .blocking(move |pinger| pinger.resolve(&resolving))
+                .await
+            {
+                Ok(Ok(addresses)) => addresses,
+                Ok(Err(error)) | Err(error) => {
+                    last_error = error;
+                    continue;
+                }
+            };
+            let connected = connect_tokio(
+                addresses,
+                self.connect_timeout,
+                self.attempt_delay,
+                &self.socket,
+            )
+            .await;
+            match self.forget_unless_connected(&target, connected) {
+                Ok(stream) => {
+                    let identity =
+                        self.identity(target.clone(), stream.peer_addr()?, stream.local_addr()?);
+                    return Ok((self.ping_stream_tokio(stream, &target).await?, identity));
+                }
+                // fail over to the next target
+                Err(error) => last_error = error.into(),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Connect to every resolved address of the server and ping them all concurrently, with the tokio runtime.
+    ///
+    /// See also [`ping_all`](Pinger::ping_all).
+    #[cfg(feature = "async-tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
+    pub async fn ping_all_tokio(&self) -> Result<Vec<(SocketAddr, Result<Response>)>> {
+        if self.proxy.is_some() {
+            return Err(io::Error::from(ErrorKind::InvalidInput).into());
+        }
+        let addresses = self.blocking(Pinger::resolve_all).await??;
+        let mut pings = ::tokio::task::JoinSet::new();
+        for (index, (target, address)) in addresses.iter().cloned().enumerate() {
+            let pinger = self.clone();
+            pings.spawn(async move {
+                let connected = connect_tokio(
+                    vec![address],
+                    pinger.connect_timeout,
+                    pinger.attempt_delay,
+                    &pinger.socket,
+                )
+                .await;
+                let result = match connected {
+                    Ok(stream) => pinger.ping_stream_tokio(stream, &target).await,
+                    Err(error) => Err(error.into()),
+                };
+                (index, result)
+            });
+        }
+        let mut results: Vec<_> = addresses.iter().map(|_| None).collect();
+        while let Some(finished) = pings.join_next().await {
+            // the tasks are never aborted, so only a panic fails them
+            let (index, result) = finished.map_err(io::Error::other)?;
+            results[index] = Some(result);
+        }
+        Ok(addresses
+            .into_iter()
+            .map(|(_, address)| address)
+            .zip(results.into_iter().flatten())
+            .collect())
+    }
+
+    // run the blocking resolution on the blocking threads of the runtime
+    #[cfg(feature = "async-tokio")]
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Pinger) -> T + Send + 'static,
+    {
+        let pinger = self.clone();
+        Ok(::tokio::task::spawn_blocking(move || f(&pinger))
+            .await
+            .map_err(io::Error::other)?)
+    }
+
+    // ping the target through the connected stream, after the proxy and the TLS handshakes
+    #[cfg(feature = "async-tokio")]
+    async fn ping_stream_tokio(
+        &self,
+        stream: ::tokio::net::TcpStream,
+        target: &ServerAddress,
+    ) -> Result<Response> {
+        let mut stream =
+            crate::tokio::TimeoutStream::new(stream, self.read_timeout, self.write_timeout);
+        if let Some(proxy) = &self.proxy {
+            transport::tunnel_tokio(&mut stream, proxy, target.hostname(), target.port()).await?;
+        }
+        #[cfg(feature = "tls")]
+        if let Some(config) = &self.tls {
+            let stream = tokio_rustls::TlsConnector::from(config.clone())
+                .connect(self.server_name()?, stream)
+                .await?;
+            return self.ping_captured_tokio(stream, target.port()).await;
+        }
+        self.ping_captured_tokio(stream, target.port()).await
+    }
+
+    // ping with the config asynchronously, as `ping_captured` does
+    #[cfg(feature = "async-tokio")]
+    async fn ping_captured_tokio<Stream>(&self, mut stream: Stream, port: u16) -> Result<Response>
+    where
+        Stream: ::tokio::io::AsyncRead + ::tokio::io::AsyncWrite + Unpin,
+    {
+        let Some(hook) = &self.capture else {
+            return crate::tokio::ping_with_config(
+                &mut stream,
+                &self.hostname,
+                port,
+                &self.config(),
+            )
+            .await;
+        };
+        let mut stream = CaptureStream::new(stream);
+        let result =
+            crate::tokio::ping_with_config(&mut stream, &self.hostname, port, &self.config()).await;
+        (hook.0)(stream.exchange(), &result);
+        result
+    }
+
+    /// Connect to the server over the Tor network and ping it asynchronously, with the tokio runtime.
+    ///
+    /// The server can be a `.onion` address, and the hostname is resolved by the exit relay otherwise.
+    /// The connect timeout applies to building the circuit and opening the stream,
+    /// and the read and write timeouts as in [`ping_tokio`](Pinger::ping_tokio).
+    /// The [`proxy`](Pinger::proxy) is not used, as the connection is already anonymized.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use arti_client::{TorClient, TorClientConfig};
+    /// use craftping::pinger::Pinger;
+    ///
+    /// # async fn run() {
+    /// let client = TorClient::create_bootstrapped(TorClientConfig::default()).await.unwrap();
+    /// let response = Pinger::new("example.onion", 25565).ping_tor(&client).await.unwrap();
+    /// # }
+    /// ```
+    #[cfg(feature = "arti")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "arti")))]
+    pub async fn ping_tor<R>(&self, client: &arti_client::TorClient<R>) -> Result<Response>
+    where
+        R: tor_rtcompat::Runtime,
+    {
+        let connect = client.connect((self.hostname.as_str(), self.port));
+        let connected = match self.connect_timeout {
+            Some(duration) => ::tokio::time::timeout(duration, connect)
+                .await
+                .map_err(|_| io::Error::from(ErrorKind::TimedOut))?,
+            None => connect.await,
+        };
+        let stream = connected.map_err(io::Error::other)?;
+        let stream =
+            crate::tokio::TimeoutStream::new(stream, self.read_timeout, self.write_timeout);
+        self.ping_captured_tokio(stream, self.port).await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Where a ping of [`Pinger`](Pinger) went, for logging and debugging the servers with several records.
+///
+/// See also [`ping_with_identity`](Pinger::ping_with_identity).
+pub struct ServerIdentity {
+    /// The address the pinger was created with.
+    pub address: ServerAddress,
+    /// The target connected to, which is the one of the SRV record if looked up, or the address itself otherwise.
+    pub target: ServerAddress,
+    /// The resolved address connected to, which is the one of the [`proxy`](Pinger::proxy) if any.
+    pub peer: SocketAddr,
+    /// The local address of the connection.
+    pub local: SocketAddr,
+}
+
+#[derive(Clone)]
+// receives the exchange of each ping, which the clones of a pinger share
+struct CaptureHook(Arc<CaptureFn>);
+
+type CaptureFn = dyn Fn(&Exchange, &Result<Response>) + Send + Sync;
+
+impl fmt::Debug for CaptureHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureHook").finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone)]
+// creates a resolver for each resolution, which the clones of a pinger share
+struct SharedResolver(Arc<dyn Fn() -> Box<dyn Resolver + Send> + Send + Sync>);
+
+impl SharedResolver {
+    fn new<R>(resolver: R) -> Self
+    where
+        R: Resolver + Clone + Send + Sync + 'static,
+    {
+        Self(Arc::new(move || Box::new(resolver.clone())))
+    }
+
+    fn get(&self) -> Box<dyn Resolver + Send> {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for SharedResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedResolver").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// The options of the sockets set before connecting, shared by [`Pinger`](Pinger)
+/// and [`Scanner`](crate::scan::Scanner::socket_options).
+///
+/// Every option is left as the system default unless set.
+pub struct SocketOptions {
+    #[cfg(feature = "socket")]
+    local: Option<IpAddr>,
+    #[cfg(feature = "socket")]
+    nodelay: Option<bool>,
+    #[cfg(feature = "socket")]
+    linger: Option<Option<Duration>>,
+    #[cfg(feature = "socket")]
+    ttl: Option<u32>,
+    #[cfg(feature = "socket")]
+    keepalive: Option<Duration>,
+    #[cfg(feature = "socket")]
+    reuse_address: Option<bool>,
+    #[cfg(all(
+        feature = "socket",
+        unix,
+        not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+    ))]
+    reuse_port: Option<bool>,
+    #[cfg(all(
+        feature = "socket",
+        any(target_os = "android", target_os = "fuchsia", target_os = "linux")
+    ))]
+    device: Option<String>,
+}
+
+impl SocketOptions {
+    /// Create the options leaving everything as the system default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind the connections to the local IP address, e.g. to choose the network of a multi-homed host.
+    ///
+    /// Only the resolved addresses of the same family as the local address are connected.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn bind(mut self, local: IpAddr) -> Self {
+        self.local = Some(local);
+        self
+    }
+
+    /// Bind the connections to the network interface with `SO_BINDTODEVICE`, e.g. `wg0` to ping through a VPN.
+    ///
+    /// Linux requires `CAP_NET_RAW` for it before 5.7.
+    #[cfg(all(
+        feature = "socket",
+        any(target_os = "android", target_os = "fuchsia", target_os = "linux")
+    ))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(all(
+            feature = "socket",
+            any(target_os = "android", target_os = "fuchsia", target_os = "linux")
+        )))
+    )]
+    pub fn bind_device(mut self, interface: impl Into<String>) -> Self {
+        self.device = Some(interface.into());
+        self
+    }
+
+    /// Set `TCP_NODELAY` of the connections, to disable the Nagle's algorithm if `true`.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = Some(nodelay);
+        self
+    }
+
+    /// Set `SO_LINGER` of the connections.
+    ///
+    /// `Some(Duration::ZERO)` resets the connections on close instead of leaving them in `TIME_WAIT`,
+    /// which keeps the ports of the hosts pinging many servers available.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn linger(mut self, linger: Option<Duration>) -> Self {
+        self.linger = Some(linger);
+        self
+    }
+
+    /// Set the time-to-live of the IPv4 packets, or the hop limit of the IPv6 packets.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Enable the TCP keepalive of the connections, probing the server after it is idle for the duration.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Set `SO_REUSEADDR` of the connections, to let the connections bound with [`bind`](SocketOptions::bind)
+    /// take the local ports still in `TIME_WAIT` if `true`.
+    ///
+    /// It keeps a sustained scan from running out of the ephemeral ports,
+    /// along with [`linger`](SocketOptions::linger) which leaves no connection in `TIME_WAIT`.
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn reuse_address(mut self, reuse: bool) -> Self {
+        self.reuse_address = Some(reuse);
+        self
+    }
+
+    /// Set `SO_REUSEPORT` of the connections, to let them share the local ports with each other if `true`.
+    ///
+    /// See also [`reuse_address`](SocketOptions::reuse_address).
+    #[cfg(all(
+        feature = "socket",
+        unix,
+        not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+    ))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(all(
+            feature = "socket",
+            unix,
+            not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+        )))
+    )]
+    pub fn reuse_port(mut self, reuse: bool) -> Self {
+        self.reuse_port = Some(reuse);
+        self
+    }
+
+    // whether the address can be connected from the bound local address
+    fn accepts(&self, address: &SocketAddr) -> bool {
+        #[cfg(feature = "socket")]
+        if let Some(local) = self.local {
+            return local.is_ipv4() == address.is_ipv4();
+        }
+        let _ = address;
+        true
+    }
+
+    #[cfg(feature = "sync")]
+    fn connect(
+        &self,
+        address: SocketAddr,
+        timeout: Option<Duration>,
+    ) -> io::Result<std::net::TcpStream> {
+        #[cfg(feature = "socket")]
+        {
+            let socket = self.socket(address)?;
+            match timeout {
+                Some(timeout) => socket.connect_timeout(&address.into(), timeout)?,
+                None => socket.connect(&address.into())?,
+            }
+            Ok(socket.into())
+        }
+        #[cfg(not(feature = "socket"))]
+        match timeout {
+            Some(timeout) => std::net::TcpStream::connect_timeout(&address, timeout),
+            None => std::net::TcpStream::connect(address),
+        }
+    }
+
+    #[cfg(feature = "async-tokio")]
+    pub(crate) async fn connect_tokio(
+        &self,
+        address: SocketAddr,
+    ) -> io::Result<::tokio::net::TcpStream> {
+        #[cfg(feature = "socket")]
+        {
+            let socket = self.socket(address)?;
+            socket.set_nonblocking(true)?;
+            ::tokio::net::TcpSocket::from_std_stream(socket.into())
+                .connect(address)
+                .await
+        }
+        #[cfg(not(feature = "socket"))]
+        ::tokio::net::TcpStream::connect(address).await
+    }
+
+    // an unconnected socket with the options set
+    #[cfg(feature = "socket")]
+    fn socket(&self, address: SocketAddr) -> io::Result<socket2::Socket> {
+        use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+
+        let socket = Socket::new(
+            Domain::for_address(address),
+            Type::STREAM,
+            Some(Protocol::TCP),
+        )?;
+        // the reuse applies to the binding, so it is set before
+        if let Some(reuse) = self.reuse_address {
+            socket.set_reuse_address(reuse)?;
+        }
+        #[cfg(all(
+            unix,
+            not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+        ))]
+        if let Some(reuse) = self.reuse_port {
+            socket.set_reuse_port(reuse)?;
+        }
+        if let Some(local) = self.local {
+            socket.bind(&SocketAddr::new(local, 0).into())?;
+        }
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if let Some(device) = &self.device {
+            socket.bind_device(Some(device.as_bytes()))?;
+        }
+        if let Some(nodelay) = self.nodelay {
+            socket.set_tcp_nodelay(nodelay)?;
+        }
+        if let Some(linger) = self.linger {
+            socket.set_linger(linger)?;
+        }
+        match (self.ttl, address) {
+            (Some(ttl), SocketAddr::V4(_)) => socket.set_ttl_v4(ttl)?,
+            (Some(hops), SocketAddr::V6(_)) => socket.set_unicast_hops_v6(hops)?,
+            (None, _) => {}
+        }
+        if let Some(idle) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+        Ok(socket)
+    }
+}
+
+// alternate the address families, starting with the family of the first address (RFC 8305 section 4)
+#[cfg(any(feature = "sync", feature = "async-tokio"))]
+fn interleave(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = addresses.first().is_some_and(SocketAddr::is_ipv6);
+    let (preferred, other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv6() == first_v6);
+    let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+    let mut interleaved = vec![];
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return interleaved,
+            (first, second) => interleaved.extend(first.into_iter().chain(second)),
+        }
+    }
+}
+
+// connect to the first address accepting the connection, starting the next attempt
+// when the previous one fails or the delay elapses (RFC 8305 section 5)
+#[cfg(feature = "sync")]
+fn connect(
+    addresses: Vec<SocketAddr>,
+    timeout: Option<Duration>,
+    delay: Duration,
+    options: &SocketOptions,
+) -> io::Result<std::net::TcpStream> {
+    use std::sync::mpsc;
+
+    let options = options.clone();
+    let connect = move |address: SocketAddr| options.connect(address, timeout);
+    // the targets without threads, like wasm32-wasip2, try the addresses one by one
+    if addresses.len() == 1 || cfg!(target_family = "wasm") {
+        let mut last_error = ErrorKind::NotFound.into();
+        for address in addresses {
+            match connect(address) {
+                Ok(stream) => return Ok(stream),
+                Err(error) => last_error = error,
+            }
+        }
+        return Err(last_error);
+    }
+    let (sender, receiver) = mpsc::channel();
+    let mut addresses = interleave(addresses).into_iter();
+    let mut next = addresses.next();
+    let mut pending = 0;
+    let mut last_error = ErrorKind::NotFound.into();
+    loop {
+        if let Some(address) = next.take() {
+            let sender = sender.clone();
+            let connect = connect.clone();
+            // the losing attempts finish in the background, and their connections are dropped
+            std::thread::spawn(move || sender.send(connect(address)));
+            pending += 1;
+        }
+        if pending == 0 {
+            return Err(last_error);
+        }
+        let finished = if addresses.len() > 0 {
+            receiver.recv_timeout(delay).ok()
+        } else {
+            receiver.recv().ok()
+        };
+        match finished {
+            Some(Ok(stream)) => return Ok(stream),
+            Some(Err(error)) => {
+                pending -= 1;
+                last_error = error;
+                next = addresses.next();
+            }
+            None => next = addresses.next(),
+        }
+    }
+}
+
+// connect to the first address accepting the connection asynchronously, as `connect` does
+#[cfg(feature = "async-tokio")]
+async fn connect_tokio(
+    addresses: Vec<SocketAddr>,
+    timeout: Option<Duration>,
+    delay: Duration,
+    options: &SocketOptions,
+) -> io::Result<::tokio::net::TcpStream> {
+    use ::tokio::{task::JoinSet, time};
+
+    let connect = |address: SocketAddr| {
+        let options = options.clone();
+        async move {
+            match timeout {
+                Some(timeout) => time::timeout(timeout, options.connect_tokio(address))
+                    .await
+                    .unwrap_or_else(|_| Err(ErrorKind::TimedOut.into())),
+                None => options.connect_tokio(address).await,
+            }
+        }
+    };
+    if let [address] = addresses[..] {
+        return connect(address).await;
+    }
+    // the attempts still in flight are aborted when the set is dropped
+    let mut attempts = JoinSet::new();
+    let mut addresses = interleave(addresses).into_iter();
+    let mut next = addresses.next();
+    let mut last_error = ErrorKind::NotFound.into();
+    loop {
+        if let Some(address) = next.take() {
+            attempts.spawn(connect(address));
+        }
+        let finished = if addresses.len() > 0 {
+            time::timeout(delay, attempts.join_next()).await.ok()
+        } else {
+            Some(attempts.join_next().await)
+        };
+        match finished {
+            Some(Some(Ok(Ok(stream)))) => return Ok(stream),
+            Some(Some(Ok(Err(error)))) => {
+                last_error = error;
+                next = addresses.next();
+            }
+            Some(Some(Err(error))) => {
+                last_error = io::Error::other(error);
+                next = addresses.next();
+            }
+            Some(None) => return Err(last_error),
+            None => next = addresses.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn race_addresses() {
+        let v4 = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let v6 = |port| SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, port));
+        assert_eq!(
+            interleave(vec![v6(1), v6(2), v6(3), v4(4), v4(5)]),
+            [v6(1), v4(4), v6(2), v4(5), v6(3)]
+        );
+
+        let listener = std::net::TcpListener::bind(v4(0)).unwrap();
+        let open = listener.local_addr().unwrap();
+        // nothing listens on the port just released
+        let closed = std::net::TcpListener::bind(v4(0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let options = SocketOptions::default();
+        let delay = Duration::from_secs(5);
+        let stream = connect(vec![closed, open], None, delay, &options).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), open);
+        assert!(connect(vec![closed, closed], None, delay, &options).is_err());
+    }
+
+    #[cfg(all(feature = "socket", feature = "testing"))]
+    #[test]
+    fn set_socket_options() {
+        use crate::{
+            testing::{MockServer, Reply},
+            Chat,
+        };
+
+        let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+        let server = MockServer::start(Reply::status(response)).unwrap();
+        let pinger = Pinger::new("localhost", server.address().port())
+            .bind(std::net::Ipv4Addr::LOCALHOST.into())
+            .nodelay(true)
+            .linger(Some(Duration::ZERO))
+            .ttl(64)
+            .keepalive(Duration::from_secs(60))
+            .read_timeout(Duration::from_millis(500));
+
+        assert_eq!(pinger.ping().unwrap().version, "1.20.4");
+        let pinger = pinger.bind(std::net::Ipv6Addr::UNSPECIFIED.into());
+        assert!(pinger.ping().is_err());
+
+        let options = SocketOptions::new()
+            .bind(std::net::Ipv4Addr::LOCALHOST.into())
+            .reuse_address(true);
+        let socket = options.socket(server.address()).unwrap();
+        assert!(socket.reuse_address().unwrap());
+        #[cfg(target_os = "linux")]
+        assert!(options
+            .reuse_port(true)
+            .socket(server.address())
+            .unwrap()
+            .reuse_port()
+            .unwrap());
+        let pinger = Pinger::new("localhost", server.address().port())
+            .socket_options(SocketOptions::new().reuse_address(true))
+            .read_timeout(Duration::from_millis(500));
+        assert_eq!(pinger.ping().unwrap().version, "1.20.4");
+    }
+
+    #[cfg(all(feature = "sync", feature = "testing"))]
+    #[test]
+    fn capture_exchange() {
+        use crate::{
+            testing::{MockServer, Reply},
+            Chat,
+        };
+        use std::sync::Mutex;
+
+        let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+        let server = MockServer::start(Reply::status(response)).unwrap();
+        let captured = Arc::new(Mutex::new(None));
+        let pinger = Pinger::new("localhost", server.address().port()).capture({
+            let captured = captured.clone();
+            move |exchange, result| {
+                assert!(result.is_ok());
+                *captured.lock().unwrap() = Some(exchange.clone());
+            }
+        });
+        pinger.ping().unwrap();
+        let exchange = captured.lock().unwrap().take().unwrap();
+        // the handshake starts with its length and the packet id 0
+        assert_eq!(exchange.sent[1], 0x00);
+        assert!(String::from_utf8_lossy(&exchange.received).contains("1.20.4"));
+    }
+
+    #[cfg(all(feature = "sync", feature = "testing"))]
+    // resolves every name to the local host with the TTL, and serves the SRV records, counting the lookups
+    #[derive(Clone, Default)]
+    struct Local {
+        ttl: Option<Duration>,
+        records: Vec<crate::resolve::SrvRecord>,
+        lookups: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[cfg(all(feature = "sync", feature = "testing"))]
+    impl Resolver for Local {
+        fn lookup_ip(&mut self, hostname: &str) -> Result<Vec<IpAddr>> {
+            Ok(self.lookup_ip_with_ttl(hostname)?.0)
+        }
+
+        fn lookup_ip_with_ttl(
+            &mut self,
+            _hostname: &str,
+        ) -> Result<(Vec<IpAddr>, Option<Duration>)> {
+            self.lookups
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok((vec![std::net::Ipv4Addr::LOCALHOST.into()], self.ttl))
+        }
+
+        fn lookup_srv(&mut self, name: &str) -> Result<Vec<crate::resolve::SrvRecord>> {
+            assert_eq!(name, "_minecraft._tcp.play.invalid");
+            Ok(self.records.clone())
+        }
+    }
+
+    #[cfg(all(feature = "sync", feature = "testing"))]
+    impl Local {
+        fn lookups(&self) -> usize {
+            self.lookups.load(std::sync::atomic::Ordering::Relaxed)
+        }
+
+        fn record(mut self, priority: u16, port: u16) -> Self {
+            self.records.push(crate::resolve::SrvRecord {
+                priority,
+                weight: 0,
+                port,
+                target: format!("target{}.invalid", priority),
+                ttl: None,
+            });
+            self
+        }
+    }
+
+    #[cfg(all(feature = "sync", feature = "testing"))]
+    #[test]
+    fn reuse_cached_addresses() {
+        use crate::{
+            testing::{MockServer, Reply},
+            Chat,
+        };
+
+        let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+        let server = MockServer::start(Reply::status(response)).unwrap();
+        let port = server.address().port();
+        let resolver = Local::default();
+        let pinger = Pinger::new("server.invalid", port)
+            .resolver(resolver.clone())
+            .dns_cache(DnsCache::new(Duration::from_secs(60)))
+            .read_timeout(Duration::from_millis(500));
+        assert_eq!(pinger.ping().unwrap().version, "1.20.4");
+        assert_eq!(pinger.ping().unwrap().version, "1.20.4");
+        assert_eq!(resolver.lookups(), 1);
+
+        // the addresses which cannot be connected are resolved again
+        drop(server);
+        assert!(pinger.ping().is_err());
+        assert!(pinger.ping().is_err());
+        assert_eq!(resolver.lookups(), 2);
+
+        // the addresses are not kept longer than their TTL
+        let expiring = Local {
+            ttl: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let pinger = pinger
+            .resolver(expiring.clone())
+            .dns_cache(DnsCache::new(Duration::from_secs(60)));
+        assert!(pinger.ping().is_err());
+        assert!(pinger.ping().is_err());
+        assert_eq!(expiring.lookups(), 2);
+    }
+
+    #[cfg(all(feature = "sync", feature = "testing"))]
+    #[test]
+    fn identify_failed_over_target() {
+        use crate::{
+            testing::{MockServer, Reply},
+            Chat,
+        };
+
+        let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+        let server = MockServer::start(Reply::status(response)).unwrap();
+        let closed = std::net::TcpListener::bind(("127.0.0.1", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let resolver = Local::default()
+            .record(10, closed.port())
+            .record(20, server.address().port());
+        let address: ServerAddress = "play.invalid".parse().unwrap();
+        let pinger = Pinger::from_address(address.clone())
+            .resolver(resolver)
+            .read_timeout(Duration::from_millis(500));
+        let (response, identity) = pinger.ping_with_identity().unwrap();
+        assert_eq!(response.version, "1.20.4");
+        assert_eq!(identity.address, address);
+        assert_eq!(
+            identity.target,
+            ServerAddress::new("target20.invalid", server.address().port())
+        );
+        assert_eq!(identity.peer, server.address());
+        assert!(identity.local.ip().is_loopback());
+    }
+
+    #[cfg(all(feature = "sync", feature = "testing"))]
+    #[test]
+    fn ping_every_address() {
+        use crate::{
+            testing::{MockServer, Reply},
+            Chat,
+        };
+
+        let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+        let server = MockServer::start(Reply::status(response)).unwrap();
+        // nothing listens on the port just released
+        let closed = std::net::TcpListener::bind(("127.0.0.1", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let resolver = Local::default()
+            .record(10, closed.port())
+            .record(20, server.address().port());
+        let address: ServerAddress = "play.invalid".parse().unwrap();
+        let pinger = Pinger::from_address(address)
+            .resolver(resolver)
+            .read_timeout(Duration::from_millis(500));
+        let results = pinger.ping_all().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, closed);
+        assert!(results[0].1.is_err());
+        assert_eq!(results[1].0, server.address());
+        assert_eq!(results[1].1.as_ref().unwrap().version, "1.20.4");
+        assert!(pinger
+            .proxy(Proxy::socks5("127.0.0.1", 1080))
+            .ping_all()
+            .is_err());
+    }
+
+    #[cfg(all(feature = "sync", feature = "testing"))]
+    #[test]
+    fn fail_over_srv_targets() {
+        use crate::{
+            testing::{MockServer, Reply},
+            Chat,
+        };
+
+        let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+        let server = MockServer::start(Reply::status(response)).unwrap();
+        // nothing listens on the port just released
+        let closed = std::net::TcpListener::bind(("127.0.0.1", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let resolver = Local::default()
+            .record(20, server.address().port())
+            .record(10, closed.port());
+        let address: ServerAddress = "play.invalid".parse().unwrap();
+        let pinger = Pinger::from_address(address)
+            .resolver(resolver)
+            .read_timeout(Duration::from_millis(500));
+        assert_eq!(pinger.ping().unwrap().version, "1.20.4");
+        assert!(pinger.ip_preference(IpPreference::V6Only).ping().is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use alloc::{format, vec::Vec};
+use core::net::{IpAddr, SocketAddr};
+
+// the signature which starts every header of the version 2
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+// the version 2 and the PROXY command
+const V2_COMMAND: u8 = 0x21;
+// TCP over IPv4 and IPv6
+const V2_TCP4: u8 = 0x11;
+const V2_TCP6: u8 = 0x21;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A version of the PROXY protocol.
+pub enum ProxyVersion {
+    /// The human-readable version 1.
+    V1,
+    /// The binary version 2.
+    V2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A header of the HAProxy PROXY protocol, sent before the handshake.
+///
+/// The servers behind HAProxy or TCPShield may drop the connections without the header,
+/// and read the address of the client from it.
+///
+/// If the source and the destination are of the different address families,
+/// the IPv4 address is sent as an IPv4-mapped IPv6 address.
+///
+/// See also [the specification](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt).
+///
+/// # Examples
+///
+/// ```
+/// use craftping::{PingConfig, ProxyHeader, ProxyVersion};
+///
+/// let config = PingConfig {
+///     proxy_header: Some(ProxyHeader::new(
+///         ProxyVersion::V2,
+///         "203.0.113.7:51234".parse().unwrap(),
+///         "198.51.100.1:25565".parse().unwrap(),
+///     )),
+///     ..Default::default()
+/// };
+/// ```
+pub struct ProxyHeader {
+    /// The version of the protocol.
+    pub version: ProxyVersion,
+    /// The address of the client, as the server should see it.
+    pub source: SocketAddr,
+    /// The address of the server the client connected to.
+    pub destination: SocketAddr,
+}
+
+impl ProxyHeader {
+    /// Create a header of the version, from the source to the destination.
+    pub fn new(version: ProxyVersion, source: SocketAddr, destination: SocketAddr) -> Self {
+        Self {
+            version,
+            source,
+            destination,
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let (source, destination) = match (self.source.ip(), self.destination.ip()) {
+            (IpAddr::V4(source), IpAddr::V6(destination)) => {
+                (IpAddr::V6(source.to_ipv6_mapped()), IpAddr::V6(destination))
+            }
+            (IpAddr::V6(source), IpAddr::V4(destination)) => {
+                (IpAddr::V6(source), IpAddr::V6(destination.to_ipv6_mapped()))
+            }
+            addresses => addresses,
+        };
+        let (source_port, destination_port) = (self.source.port(), self.destination.port());
+        match self.version {
+            ProxyVersion::V1 => {
+                let family = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+                format!(
+                    "PROXY {} {} {} {} {}\r\n",
+                    family, source, destination, source_port, destination_port
+                )
+                .into_bytes()
+            }
+            ProxyVersion::V2 => {
+                let mut header = V2_SIGNATURE.to_vec();
+                header.push(V2_COMMAND);
+                match (source, destination) {
+                    (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                        header.push(V2_TCP4);
+                        header.extend_from_slice(&12u16.to_be_bytes());
+                        header.extend_from_slice(&source.octets());
+                        header.extend_from_slice(&destination.octets());
+                    }
+                    (IpAddr::V6(source), IpAddr::V6(destination)) => {
+                        header.push(V2_TCP6);
+                        header.extend_from_slice(&36u16.to_be_bytes());
+                        header.extend_from_slice(&source.octets());
+                        header.extend_from_slice(&destination.octets());
+                    }
+                    _ => unreachable!("the addresses are of the same family"),
+                }
+                header.extend_from_slice(&source_port.to_be_bytes());
+                header.extend_from_slice(&destination_port.to_be_bytes());
+                header
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_header() {
+        let source = "203.0.113.7:51234".parse().unwrap();
+        let destination = "198.51.100.1:25565".parse().unwrap();
+        let v1 = ProxyHeader::new(ProxyVersion::V1, source, destination).encode();
+        assert_eq!(v1, b"PROXY TCP4 203.0.113.7 198.51.100.1 51234 25565\r\n");
+
+        let v2 = ProxyHeader::new(ProxyVersion::V2, source, destination).encode();
+        assert_eq!(
+            v2[..16],
+            [b"\r\n\r\n\0\r\nQUIT\n".as_slice(), &[0x21, 0x11, 0, 12]].concat()
+        );
+        assert_eq!(
+            v2[16..],
+            [203, 0, 113, 7, 198, 51, 100, 1, 0xc8, 0x22, 0x63, 0xdd]
+        );
+
+        let mixed = ProxyHeader::new(ProxyVersion::V1, source, "[::1]:25565".parse().unwrap());
+        assert!(mixed
+            .encode()
+            .starts_with(b"PROXY TCP6 ::ffff:203.0.113.7 ::1 "));
+    }
+}
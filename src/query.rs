@@ -0,0 +1,449 @@
+//! Provides the Query protocol functions, like [`basic_stat`](basic_stat).
+//!
+//! The Query protocol is a UDP protocol based on GameSpy4,
+//! served by the servers with `enable-query=true` in `server.properties`, on the port `query.port`.
+//! It exposes the information the Server List Ping does not, e.g. the map name.
+//!
+//! See also [the minecraft protocol wiki](https://wiki.vg/Query).
+use std::{
+    collections::HashMap,
+    net::UdpSocket,
+    sync::atomic::{AtomicI32, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+const HANDSHAKE: u8 = 0x09;
+const STAT: u8 = 0x00;
+// only the lower 4 bits of each byte of the session id are used by the server
+const SESSION_ID_MASK: i32 = 0x0f0f0f0f;
+// the padding before the K/V section and the player section of the full stat response
+const KV_PADDING: &[u8] = b"splitnum\0\x80\0";
+const PLAYER_PADDING: &[u8] = b"\x01player_\0\0";
+// the largest UDP payload
+const MAX_PACKET_SIZE: usize = 65507;
+
+// distinguishes the sessions started from this process
+static SESSION_ID: AtomicI32 = AtomicI32::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How the Query requests are retransmitted, since the UDP packets get lost routinely.
+///
+/// Each attempt starts over from the handshake, so that a challenge token expired in the meantime is refreshed.
+/// The servers do not answer a request with a stale token at all, which is indistinguishable from a lost packet.
+pub struct Retransmission {
+    /// The number of the attempts, including the first one. Defaults to 3.
+    pub attempts: u32,
+    /// The time to wait for the responses of the first attempt. Defaults to 1 second.
+    pub timeout: Duration,
+    /// The factor to multiply the timeout by on each retransmission. Defaults to 2.
+    pub backoff: u32,
+}
+
+impl Default for Retransmission {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            timeout: Duration::from_secs(1),
+            backoff: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+/// The response of the basic stat request.
+pub struct BasicStat {
+    /// The MOTD of the server, with the legacy formatting codes.
+    pub motd: String,
+    /// The game type. It is always `SMP` for vanilla servers.
+    pub game_type: String,
+    /// The name of the default world.
+    pub map: String,
+    /// The number of the players currently connected.
+    pub online_players: usize,
+    /// The maximum number of the connected players.
+    pub max_players: usize,
+    /// The port the server accepts the players on.
+    pub host_port: u16,
+    /// The IP address the server accepts the players on, as configured in `server-ip`.
+    pub host_ip: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+/// The response of the full stat request.
+pub struct FullStat {
+    /// The MOTD of the server, with the legacy formatting codes.
+    pub motd: String,
+    /// The game type. It is always `SMP` for vanilla servers.
+    pub game_type: String,
+    /// The game id. It is always `MINECRAFT` for vanilla servers.
+    pub game_id: String,
+    /// The version name of the server.
+    pub version: String,
+    /// The server software reported with the plugins, e.g. `Paper on 1.20.4`.
+    /// `None` for vanilla servers, which report no plugins.
+    pub server_mod: Option<String>,
+    /// The plugins installed on the server.
+    pub plugins: Vec<Plugin>,
+    /// The name of the default world.
+    pub map: String,
+    /// The number of the players currently connected.
+    pub online_players: usize,
+    /// The maximum number of the connected players.
+    pub max_players: usize,
+    /// The port the server accepts the players on.
+    pub host_port: u16,
+    /// The IP address the server accepts the players on, as configured in `server-ip`.
+    pub host_ip: String,
+    /// The names of every connected player.
+    pub players: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+/// A plugin reported in the full stat response.
+pub struct Plugin {
+    /// The name of the plugin.
+    pub name: String,
+    /// The version of the plugin. It can be empty if the server does not report it.
+    pub version: String,
+}
+
+/// Send a basic stat request to the server and wait for the response.
+///
+/// The socket should be connected to the query port of the server.
+/// The request is retransmitted with the default [`Retransmission`](Retransmission),
+/// and fails with [`TimedOut`](std::io::ErrorKind::TimedOut) if the server never responds, e.g. without the query enabled.
+/// See also [`basic_stat_with_retransmission`](basic_stat_with_retransmission).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::query::basic_stat;
+/// use std::net::UdpSocket;
+///
+/// let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+/// socket.connect(("my.server.com", 25565)).unwrap();
+/// let stat = basic_stat(&socket).unwrap();
+/// println!("Map: {}", stat.map);
+/// ```
+pub fn basic_stat(socket: &UdpSocket) -> Result<BasicStat> {
+    basic_stat_with_retransmission(socket, &Retransmission::default())
+}
+
+/// Send a basic stat request to the server, retransmitting it as configured, and wait for the response.
+///
+/// The read timeout of the socket is overridden during the request, and restored afterwards.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::query::{basic_stat_with_retransmission, Retransmission};
+/// use std::{net::UdpSocket, time::Duration};
+///
+/// let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+/// socket.connect(("my.server.com", 25565)).unwrap();
+/// let retransmission = Retransmission {
+///     attempts: 5,
+///     timeout: Duration::from_millis(500),
+///     ..Default::default()
+/// };
+/// let stat = basic_stat_with_retransmission(&socket, &retransmission).unwrap();
+/// println!("Map: {}", stat.map);
+/// ```
+pub fn basic_stat_with_retransmission(
+    socket: &UdpSocket,
+    retransmission: &Retransmission,
+) -> Result<BasicStat> {
+    let payload = stat(socket, retransmission, false)?;
+    parse_basic_stat(&payload)
+}
+
+/// Send a full stat request to the server and wait for the response.
+///
+/// Unlike [`basic_stat`](basic_stat), the response includes the plugins and the names of every connected player.
+/// See also [`basic_stat`](basic_stat) for the socket and the retransmission.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::query::full_stat;
+/// use std::net::UdpSocket;
+///
+/// let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+/// socket.connect(("my.server.com", 25565)).unwrap();
+/// let stat = full_stat(&socket).unwrap();
+/// println!("Players: {}", stat.players.join(", "));
+/// ```
+pub fn full_stat(socket: &UdpSocket) -> Result<FullStat> {
+    full_stat_with_retransmission(socket, &Retransmission::default())
+}
+
+/// Send a full stat request to the server, retransmitting it as configured, and wait for the response.
+///
+/// See also [`basic_stat_with_retransmission`](basic_stat_with_retransmission).
+pub fn full_stat_with_retransmission(
+    socket: &UdpSocket,
+    retransmission: &Retransmission,
+) -> Result<FullStat> {
+    let payload = stat(socket, retransmission, true)?;
+    parse_full_stat(&payload)
+}
+
+fn next_session_id() -> i32 {
+    SESSION_ID.fetch_add(1, Ordering::Relaxed) & SESSION_ID_MASK
+}
+
+// run the handshake and the stat request, starting over on each timeout,
+// and restore the read timeout of the socket afterwards
+fn stat(socket: &UdpSocket, retransmission: &Retransmission, full: bool) -> Result<Vec<u8>> {
+    let read_timeout = socket.read_timeout()?;
+    let session_id = next_session_id();
+    let mut timeout = retransmission.timeout;
+    let mut result = Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+    for _ in 0..retransmission.attempts.max(1) {
+        let deadline = Instant::now().checked_add(timeout);
+        result = handshake(socket, session_id, deadline).and_then(|token| {
+            let mut body = token.to_be_bytes().to_vec();
+            if full {
+                // the padding tells the full stat request from the basic one
+                body.extend_from_slice(&[0; 4]);
+            }
+            exchange(socket, STAT, session_id, &body, deadline)
+        });
+        match &result {
+            Err(Error::Io(error)) if error.kind() == std::io::ErrorKind::TimedOut => {
+                timeout = timeout.saturating_mul(retransmission.backoff);
+            }
+            _ => break,
+        }
+    }
+    socket.set_read_timeout(read_timeout)?;
+    result
+}
+
+// return the challenge token for the session
+fn handshake(socket: &UdpSocket, session_id: i32, deadline: Option<Instant>) -> Result<i32> {
+    let payload = exchange(socket, HANDSHAKE, session_id, &[], deadline)?;
+    let (token, _) = read_string(&payload).ok_or(Error::UnsupportedProtocol)?;
+    token.trim().parse().map_err(|_| Error::UnsupportedProtocol)
+}
+
+// send a request, and return the payload of the response after the type and the session id,
+// or time out at the deadline, which is never if it overflows
+fn exchange(
+    socket: &UdpSocket,
+    kind: u8,
+    session_id: i32,
+    body: &[u8],
+    deadline: Option<Instant>,
+) -> Result<Vec<u8>> {
+    let mut request = MAGIC.to_vec();
+    request.push(kind);
+    request.extend_from_slice(&session_id.to_be_bytes());
+    request.extend_from_slice(body);
+    socket.send(&request)?;
+
+    let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+    loop {
+        // the stray responses must not extend the wait
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if remaining.is_some_and(|remaining| remaining.is_zero()) {
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+        }
+        socket.set_read_timeout(remaining)?;
+        let length = crate::read_timed_out(socket.recv(&mut buffer))?;
+        // skip the stray responses of the previous sessions
+        if length >= 5 && buffer[0] == kind && buffer[1..5] == session_id.to_be_bytes() {
+            buffer.truncate(length);
+            buffer.drain(..5);
+            return Ok(buffer);
+        }
+    }
+}
+
+// read a null-terminated string, and return it with the rest of the payload
+fn read_string(payload: &[u8]) -> Option<(String, &[u8])> {
+    let end = payload.iter().position(|&b| b == 0)?;
+    let string = String::from_utf8_lossy(&payload[..end]).into_owned();
+    Some((string, &payload[end + 1..]))
+}
+
+fn parse_basic_stat(payload: &[u8]) -> Result<BasicStat> {
+    let parse = || {
+        let (motd, rest) = read_string(payload)?;
+        let (game_type, rest) = read_string(rest)?;
+        let (map, rest) = read_string(rest)?;
+        let (online_players, rest) = read_string(rest)?;
+        let (max_players, rest) = read_string(rest)?;
+        // the only little-endian field of the protocol
+        let host_port = u16::from_le_bytes([*rest.first()?, *rest.get(1)?]);
+        let (host_ip, _) = read_string(&rest[2..])?;
+        Some(BasicStat {
+            motd,
+            game_type,
+            map,
+            online_players: online_players.parse().ok()?,
+            max_players: max_players.parse().ok()?,
+            host_port,
+            host_ip,
+        })
+    };
+    parse().ok_or(Error::UnsupportedProtocol)
+}
+
+fn parse_full_stat(payload: &[u8]) -> Result<FullStat> {
+    let parse = || {
+        let mut rest = payload.strip_prefix(KV_PADDING)?;
+        let mut values = HashMap::new();
+        loop {
+            let (key, next) = read_string(rest)?;
+            rest = next;
+            if key.is_empty() {
+                break;
+            }
+            let (value, next) = read_string(rest)?;
+            rest = next;
+            values.insert(key, value);
+        }
+
+        let mut rest = rest.strip_prefix(PLAYER_PADDING)?;
+        let mut players = vec![];
+        loop {
+            let (name, next) = read_string(rest)?;
+            rest = next;
+            if name.is_empty() {
+                break;
+            }
+            players.push(name);
+        }
+
+        let (server_mod, plugins) = parse_plugins(values.get("plugins").map_or("", String::as_str));
+        let mut take = |key: &str| values.remove(key).unwrap_or_default();
+        Some(FullStat {
+            motd: take("hostname"),
+            game_type: take("gametype"),
+            game_id: take("game_id"),
+            version: take("version"),
+            server_mod,
+            plugins,
+            map: take("map"),
+            online_players: take("numplayers").parse().ok()?,
+            max_players: take("maxplayers").parse().ok()?,
+            host_port: take("hostport").parse().ok()?,
+            host_ip: take("hostip"),
+            players,
+        })
+    };
+    parse().ok_or(Error::UnsupportedProtocol)
+}
+
+// the plugins are reported as `<server mod>: <name> <version>; <name> <version>; ...`
+fn parse_plugins(plugins: &str) -> (Option<String>, Vec<Plugin>) {
+    let (server_mod, list) = match plugins.split_once(": ") {
+        Some((server_mod, list)) => (server_mod, list),
+        None => (plugins, ""),
+    };
+    let server_mod = Some(server_mod.trim().to_string()).filter(|s| !s.is_empty());
+    let plugins = list
+        .split(';')
+        .map(str::trim)
+        .filter(|plugin| !plugin.is_empty())
+        .map(|plugin| {
+            let (name, version) = plugin.rsplit_once(' ').unwrap_or((plugin, ""));
+            Plugin {
+                name: name.to_string(),
+                version: version.to_string(),
+            }
+        })
+        .collect();
+    (server_mod, plugins)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_basic_stat_response() {
+        let payload = b"A Minecraft Server\0SMP\0world\x002\x0020\0\xdd\x63127.0.0.1\0";
+        let stat = parse_basic_stat(payload).unwrap();
+        assert_eq!(stat.motd, "A Minecraft Server");
+        assert_eq!(stat.map, "world");
+        assert_eq!(stat.online_players, 2);
+        assert_eq!(stat.max_players, 20);
+        assert_eq!(stat.host_port, 25565);
+        assert_eq!(stat.host_ip, "127.0.0.1");
+        assert!(parse_basic_stat(b"A Minecraft Server\0SMP\0").is_err());
+    }
+
+    #[test]
+    fn parse_full_stat_response() {
+        let mut payload = KV_PADDING.to_vec();
+        payload.extend_from_slice(
+            b"hostname\0A Minecraft Server\0gametype\0SMP\0game_id\0MINECRAFT\0",
+        );
+        payload.extend_from_slice(
+            b"version\x001.20.4\0plugins\0Paper on 1.20.4: WorldEdit 7.2.0; Essentials 2.20\0",
+        );
+        payload.extend_from_slice(
+            b"map\0world\0numplayers\x002\0maxplayers\x0020\0hostport\x0025565\0hostip\x00127.0.0.1\0\0",
+        );
+        payload.extend_from_slice(PLAYER_PADDING);
+        payload.extend_from_slice(b"Notch\0jeb_\0\0");
+
+        let stat = parse_full_stat(&payload).unwrap();
+        assert_eq!(stat.motd, "A Minecraft Server");
+        assert_eq!(stat.server_mod.as_deref(), Some("Paper on 1.20.4"));
+        assert_eq!(stat.plugins.len(), 2);
+        assert_eq!(stat.plugins[1].name, "Essentials");
+        assert_eq!(stat.plugins[1].version, "2.20");
+        assert_eq!(stat.host_port, 25565);
+        assert_eq!(stat.players, ["Notch", "jeb_"]);
+    }
+
+    #[test]
+    fn retransmit_and_refresh_token() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(server.local_addr().unwrap()).unwrap();
+        let thread = std::thread::spawn(move || {
+            let mut buffer = [0; 64];
+            let mut token = 0i32;
+            // the first handshake is lost, and the first stat request carries the token expired since
+            for _ in 0..5 {
+                let (length, client) = server.recv_from(&mut buffer).unwrap();
+                let request = &buffer[..length];
+                let mut response = vec![request[2]];
+                response.extend_from_slice(&request[3..7]);
+                match request[2] {
+                    HANDSHAKE if token == 0 => {
+                        token += 1;
+                        continue;
+                    }
+                    HANDSHAKE => {
+                        token += 1;
+                        response.extend_from_slice(format!("{}\0", token).as_bytes());
+                    }
+                    _ if request[7..11] != token.to_be_bytes() || token < 3 => continue,
+                    _ => response.extend_from_slice(
+                        b"A Minecraft Server\0SMP\0world\x002\x0020\0\xdd\x63127.0.0.1\0",
+                    ),
+                }
+                server.send_to(&response, client).unwrap();
+            }
+        });
+        let retransmission = Retransmission {
+            attempts: 3,
+            timeout: Duration::from_millis(100),
+            backoff: 2,
+        };
+        let stat = basic_stat_with_retransmission(&socket, &retransmission).unwrap();
+        assert_eq!(stat.map, "world");
+        assert_eq!(socket.read_timeout().unwrap(), None);
+        thread.join().unwrap();
+    }
+}
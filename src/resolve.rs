@@ -0,0 +1,689 @@
+//! Provides [`lookup`](lookup) function to resolve the address of a server like the vanilla client.
+//!
+//! The vanilla client looks up the `_minecraft._tcp` SRV record of the hostname before connecting,
+//! so a server can be reached with only its hostname even if it runs on another host or port.
+//! Pinging the hostname without the lookup reaches the wrong host or port for such servers.
+//!
+//! The names are resolved with a [`Resolver`](Resolver), which is [`SystemResolver`](SystemResolver) by default.
+//! Applications with their own DNS (DNS over HTTPS, internal resolvers, caches) can implement [`Resolver`](Resolver)
+//! and pass it to [`lookup_with`](lookup_with) or [`TcpTransport::resolver`](crate::transport::TcpTransport::resolver).
+//! The results can be reused across the connections with a [`DnsCache`](DnsCache).
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Error, Result};
+
+/// The port used when the server has no SRV record.
+pub const DEFAULT_PORT: u16 = 25565;
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const DNS_PORT: u16 = 53;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u16 = 3;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+// the largest response without EDNS
+const MAX_RESPONSE_SIZE: usize = 512;
+
+/// Resolves the names for the lookups and the connections.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{resolve::{Resolver, SrvRecord}, transport::TcpTransport};
+/// use std::{net::IpAddr, time::Duration};
+///
+/// // resolves every name to the local host
+/// #[derive(Debug, Clone)]
+/// struct Localhost;
+///
+/// impl Resolver for Localhost {
+///     fn lookup_ip(&mut self, _hostname: &str) -> craftping::Result<Vec<IpAddr>> {
+///         Ok(vec!["127.0.0.1".parse().unwrap()])
+///     }
+///
+///     fn lookup_srv(&mut self, _name: &str) -> craftping::Result<Vec<SrvRecord>> {
+///         Ok(vec![])
+///     }
+/// }
+///
+/// let transport = TcpTransport::new(Duration::from_secs(5)).resolver(Localhost);
+/// ```
+pub trait Resolver {
+    /// Resolve the IP addresses of the hostname.
+    fn lookup_ip(&mut self, hostname: &str) -> Result<Vec<IpAddr>>;
+
+    /// Resolve the IP addresses of the hostname, along with how long they stay valid.
+    ///
+    /// The TTL is `None` if the resolver does not know it, which is the default.
+    /// [`DnsCache`](DnsCache) keeps the addresses for the TTL.
+    fn lookup_ip_with_ttl(&mut self, hostname: &str) -> Result<(Vec<IpAddr>, Option<Duration>)> {
+        Ok((self.lookup_ip(hostname)?, None))
+    }
+
+    /// Look up the SRV records of the name, e.g. `_minecraft._tcp.example.com`.
+    /// Returns an empty list if the name has no records.
+    fn lookup_srv(&mut self, name: &str) -> Result<Vec<SrvRecord>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An SRV record.
+pub struct SrvRecord {
+    /// The priority of the target. The lower one should be tried first.
+    pub priority: u16,
+    /// The relative weight of the targets with the same priority.
+    pub weight: u16,
+    /// The port of the target.
+    pub port: u16,
+    /// The hostname of the target.
+    pub target: String,
+    /// The time to live of the record, or `None` if the resolver does not know it.
+    pub ttl: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+/// The default [`Resolver`](Resolver).
+///
+/// The IP addresses are resolved with the resolver of the operating system.
+/// The SRV records are looked up from the nameservers in `/etc/resolv.conf`,
+/// since the operating system does not expose the lookup.
+pub struct SystemResolver {
+    timeout: Duration,
+}
+
+impl SystemResolver {
+    /// Create a resolver which waits for the SRV lookups up to the timeout.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl Default for SystemResolver {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT)
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn lookup_ip(&mut self, hostname: &str) -> Result<Vec<IpAddr>> {
+        Ok((hostname, 0)
+            .to_socket_addrs()?
+            .map(|address| address.ip())
+            .collect())
+    }
+
+    fn lookup_srv(&mut self, name: &str) -> Result<Vec<SrvRecord>> {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.subsec_nanos() as u16);
+        let query = build_query(id, name)?;
+
+        let mut last_error = Error::Io(ErrorKind::NotFound.into());
+        for nameserver in nameservers()? {
+            match exchange(nameserver, &query, self.timeout) {
+                Ok(response) => return parse_srv_response(id, &response),
+                Err(error) => last_error = error,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+// the cached results with their expiry, shared by the clones
+// the values with the time they expire, which is `None` if they never expire
+type Cached<K, V> = Arc<Mutex<HashMap<K, (V, Option<Instant>)>>>;
+
+#[derive(Debug, Clone)]
+/// A cache of the resolved addresses and the SRV records, shared by its clones.
+///
+/// The connections to the same hosts, e.g. by a [`Watcher`](crate::watch::Watcher), reuse the results until their TTL passes,
+/// instead of asking the resolver every time.
+/// The TTL of the records is used if the [`Resolver`](Resolver) reports it, as [`HickoryResolver`](HickoryResolver)
+/// and the SRV lookups of [`SystemResolver`](SystemResolver) do, up to the maximum TTL of the cache.
+/// The results without a TTL, like the addresses from the resolver of the operating system, are kept for the maximum TTL.
+/// The cached addresses of a host are forgotten whenever none of them can be connected, so a moved server is resolved again.
+///
+/// A cache can be shared by [`Pinger`](crate::pinger::Pinger::dns_cache) and [`TcpTransport`](crate::transport::TcpTransport::dns_cache).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{pinger::Pinger, resolve::DnsCache};
+/// use std::time::Duration;
+///
+/// let cache = DnsCache::new(Duration::from_secs(300));
+/// for hostname in ["a.server.com", "b.server.com", "a.server.com"] {
+///     let response = Pinger::new(hostname, 25565).dns_cache(cache.clone()).ping();
+/// }
+/// ```
+pub struct DnsCache {
+    max_ttl: Duration,
+    addresses: Cached<String, Vec<IpAddr>>,
+    records: Cached<String, Vec<SrvRecord>>,
+}
+
+impl DnsCache {
+    /// Create an empty cache keeping the results up to the maximum TTL.
+    /// A zero TTL disables the cache.
+    pub fn new(max_ttl: Duration) -> Self {
+        Self {
+            max_ttl,
+            addresses: Default::default(),
+            records: Default::default(),
+        }
+    }
+
+    /// The longest time the results are kept.
+    pub fn max_ttl(&self) -> Duration {
+        self.max_ttl
+    }
+
+    /// Forget every cached result, so that the names are resolved again.
+    pub fn clear(&self) {
+        lock(&self.addresses).clear();
+        lock(&self.records).clear();
+    }
+
+    // forget the addresses of the host, which could not be connected
+    pub(crate) fn forget(&self, hostname: &str) {
+        lock(&self.addresses).remove(hostname);
+    }
+
+    // the resolver answering from this cache before asking the resolver
+    pub(crate) fn resolver<'a, R: Resolver + ?Sized>(
+        &'a self,
+        resolver: &'a mut R,
+    ) -> CachedResolver<'a, R> {
+        CachedResolver {
+            cache: self,
+            resolver,
+        }
+    }
+
+    // how long the result is kept, which is zero if it should not be cached
+    fn ttl(&self, ttl: Option<Duration>) -> Duration {
+        ttl.map_or(self.max_ttl, |ttl| ttl.min(self.max_ttl))
+    }
+}
+
+// the cache stays usable even if a thread panicked while holding it
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// the unexpired result of the name, along with the rest of its TTL
+fn get<V: Clone>(cached: &Cached<String, V>, name: &str) -> Option<(V, Duration)> {
+    let mut cached = lock(cached);
+    let now = Instant::now();
+    match cached.get(name) {
+        Some((value, None)) => Some((value.clone(), Duration::MAX)),
+        Some((value, Some(expires))) if *expires > now => Some((value.clone(), *expires - now)),
+        Some(_) => {
+            cached.remove(name);
+            None
+        }
+        None => None,
+    }
+}
+
+fn insert<V>(cached: &Cached<String, V>, name: &str, value: V, ttl: Duration) {
+    if !ttl.is_zero() {
+        // a TTL too long to represent never expires
+        let expires = Instant::now().checked_add(ttl);
+        lock(cached).insert(name.to_string(), (value, expires));
+    }
+}
+
+// a resolver answering from the cache, and caching the answers of the resolver it wraps
+pub(crate) struct CachedResolver<'a, R: ?Sized> {
+    cache: &'a DnsCache,
+    resolver: &'a mut R,
+}
+
+impl<R: Resolver + ?Sized> Resolver for CachedResolver<'_, R> {
+    fn lookup_ip(&mut self, hostname: &str) -> Result<Vec<IpAddr>> {
+        Ok(self.lookup_ip_with_ttl(hostname)?.0)
+    }
+
+    fn lookup_ip_with_ttl(&mut self, hostname: &str) -> Result<(Vec<IpAddr>, Option<Duration>)> {
+        if let Some((addresses, ttl)) = get(&self.cache.addresses, hostname) {
+            return Ok((addresses, Some(ttl)));
+        }
+        let (addresses, ttl) = self.resolver.lookup_ip_with_ttl(hostname)?;
+        if !addresses.is_empty() {
+            let ttl = self.cache.ttl(ttl);
+            insert(&self.cache.addresses, hostname, addresses.clone(), ttl);
+        }
+        Ok((addresses, ttl))
+    }
+
+    fn lookup_srv(&mut self, name: &str) -> Result<Vec<SrvRecord>> {
+        if let Some((records, _)) = get(&self.cache.records, name) {
+            return Ok(records);
+        }
+        let records = self.resolver.lookup_srv(name)?;
+        // the set of the records expires with the first of them
+        let ttl = records.iter().filter_map(|record| record.ttl).min();
+        insert(
+            &self.cache.records,
+            name,
+            records.clone(),
+            self.cache.ttl(ttl),
+        );
+        Ok(records)
+    }
+}
+
+/// Resolve the hostname and the port to connect to, like the vanilla client.
+///
+/// The target of the `_minecraft._tcp` SRV record of the hostname is returned if it exists,
+/// otherwise the hostname itself and [`DEFAULT_PORT`](DEFAULT_PORT).
+/// As in the vanilla client, a failed lookup falls back to the hostname too,
+/// and IP addresses are returned without a lookup.
+/// Use [`lookup_srv`](lookup_srv) to handle the failures,
+/// or [`lookup_all`](lookup_all) to fail over to the other targets.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{resolve::lookup, sync::ping};
+/// use std::net::TcpStream;
+///
+/// let (hostname, port) = lookup("my.server.com");
+/// let mut stream = TcpStream::connect((hostname.as_str(), port)).unwrap();
+/// // the handshake should carry the address the user entered
+/// let response = ping(&mut stream, "my.server.com", port).unwrap();
+/// ```
+pub fn lookup(hostname: &str) -> (String, u16) {
+    lookup_with(&mut SystemResolver::default(), hostname)
+}
+
+/// Resolve the hostname and the port to connect to with the resolver.
+///
+/// See also [`lookup`](lookup).
+pub fn lookup_with<R: Resolver + ?Sized>(resolver: &mut R, hostname: &str) -> (String, u16) {
+    let mut targets = lookup_all_with(resolver, hostname);
+    targets.swap_remove(0)
+}
+
+/// Resolve every target to connect to, in the order they should be tried.
+///
+/// The targets of the `_minecraft._tcp` SRV records are ordered by their priority,
+/// and the targets with the same priority are shuffled by their weight as in RFC 2782.
+/// The list falls back to the hostname itself and [`DEFAULT_PORT`](DEFAULT_PORT) as [`lookup`](lookup) does,
+/// so it is never empty.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{resolve::lookup_all, sync::ping};
+/// use std::net::TcpStream;
+///
+/// let stream = lookup_all("my.server.com")
+///     .into_iter()
+///     .find_map(|(hostname, port)| TcpStream::connect((hostname.as_str(), port)).ok());
+/// ```
+pub fn lookup_all(hostname: &str) -> Vec<(String, u16)> {
+    lookup_all_with(&mut SystemResolver::default(), hostname)
+}
+
+/// Resolve every target to connect to with the resolver.
+///
+/// See also [`lookup_all`](lookup_all).
+pub fn lookup_all_with<R: Resolver + ?Sized>(
+    resolver: &mut R,
+    hostname: &str,
+) -> Vec<(String, u16)> {
+    let targets = if hostname.parse::<IpAddr>().is_ok() {
+        vec![]
+    } else {
+        lookup_srv_with(resolver, hostname).unwrap_or_default()
+    };
+    if targets.is_empty() {
+        return vec![(hostname.to_string(), DEFAULT_PORT)];
+    }
+    targets
+}
+
+/// Look up the `_minecraft._tcp` SRV records of the hostname, and return their targets and ports.
+///
+/// Returns an empty list if the record does not exist.
+/// The targets are in the order they should be tried: by the lowest priority,
+/// and then shuffled by the weight as described in RFC 2782.
+/// The targets of `.`, which mean that the service is not available, are left out.
+pub fn lookup_srv(hostname: &str, timeout: Duration) -> Result<Vec<(String, u16)>> {
+    lookup_srv_with(&mut SystemResolver::new(timeout), hostname)
+}
+
+/// Look up the `_minecraft._tcp` SRV records of the hostname with the resolver.
+///
+/// See also [`lookup_srv`](lookup_srv).
+pub fn lookup_srv_with<R: Resolver + ?Sized>(
+    resolver: &mut R,
+    hostname: &str,
+) -> Result<Vec<(String, u16)>> {
+    let name = format!("_minecraft._tcp.{}", hostname.trim_end_matches('.'));
+    let records = resolver.lookup_srv(&name)?;
+    Ok(order_srv(records, random_up_to)
+        .into_iter()
+        .filter(|record| !record.target.is_empty())
+        .map(|record| (record.target, record.port))
+        .collect())
+}
+
+// the selection order of RFC 2782: by the priority,
+// then a record chosen at random in proportion to its weight, among the rest with the same priority
+fn order_srv(mut records: Vec<SrvRecord>, mut random: impl FnMut(u32) -> u32) -> Vec<SrvRecord> {
+    // the records of zero weight come first, so that they have a small chance to be chosen
+    records.sort_by_key(|record| (record.priority, record.weight != 0));
+    let mut ordered = Vec::with_capacity(records.len());
+    while let Some(first) = records.first() {
+        let priority = first.priority;
+        let end = records
+            .iter()
+            .position(|record| record.priority != priority)
+            .unwrap_or(records.len());
+        let mut group: Vec<_> = records.drain(..end).collect();
+        while !group.is_empty() {
+            let total = group.iter().map(|record| record.weight as u32).sum();
+            let chosen = random(total);
+            let mut sum = 0;
+            let index = group
+                .iter()
+                .position(|record| {
+                    sum += record.weight as u32;
+                    sum >= chosen
+                })
+                .unwrap_or(0);
+            ordered.push(group.remove(index));
+        }
+    }
+    ordered
+}
+
+// a random number from 0 to max inclusive, enough to spread the load over the targets
+fn random_up_to(max: u32) -> u32 {
+    let random = RandomState::new().build_hasher().finish();
+    (random % (max as u64 + 1)) as u32
+}
+
+#[cfg(feature = "hickory")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hickory")))]
+#[derive(Clone)]
+/// A [`Resolver`](Resolver) backed by hickory-dns.
+///
+/// Unlike [`SystemResolver`](SystemResolver), the records are cached for their TTL.
+/// Clones of the resolver share the same cache.
+pub struct HickoryResolver {
+    resolver: std::sync::Arc<hickory_resolver::Resolver>,
+}
+
+#[cfg(feature = "hickory")]
+impl HickoryResolver {
+    /// Create a resolver with the system configuration, e.g. `/etc/resolv.conf` on unix.
+    pub fn from_system_conf() -> Result<Self> {
+        Ok(hickory_resolver::Resolver::from_system_conf()?.into())
+    }
+}
+
+#[cfg(feature = "hickory")]
+impl From<hickory_resolver::Resolver> for HickoryResolver {
+    fn from(resolver: hickory_resolver::Resolver) -> Self {
+        Self {
+            resolver: std::sync::Arc::new(resolver),
+        }
+    }
+}
+
+#[cfg(feature = "hickory")]
+impl std::fmt::Debug for HickoryResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HickoryResolver").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "hickory")]
+impl Resolver for HickoryResolver {
+    fn lookup_ip(&mut self, hostname: &str) -> Result<Vec<IpAddr>> {
+        Ok(self.lookup_ip_with_ttl(hostname)?.0)
+    }
+
+    fn lookup_ip_with_ttl(&mut self, hostname: &str) -> Result<(Vec<IpAddr>, Option<Duration>)> {
+        let lookup = self
+            .resolver
+            .lookup_ip(hostname)
+            .map_err(std::io::Error::from)?;
+        let ttl = lookup
+            .valid_until()
+            .saturating_duration_since(Instant::now());
+        Ok((lookup.iter().collect(), Some(ttl)))
+    }
+
+    fn lookup_srv(&mut self, name: &str) -> Result<Vec<SrvRecord>> {
+        use hickory_resolver::error::ResolveErrorKind;
+
+        let lookup = match self.resolver.srv_lookup(name) {
+            Ok(lookup) => lookup,
+            Err(error) if matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                return Ok(vec![])
+            }
+            Err(error) => return Err(std::io::Error::from(error).into()),
+        };
+        let ttl = lookup
+            .as_lookup()
+            .valid_until()
+            .saturating_duration_since(Instant::now());
+        Ok(lookup
+            .iter()
+            .map(|srv| SrvRecord {
+                priority: srv.priority(),
+                weight: srv.weight(),
+                port: srv.port(),
+                target: srv.target().to_utf8().trim_end_matches('.').to_string(),
+                ttl: Some(ttl),
+            })
+            .collect())
+    }
+}
+
+fn nameservers() -> Result<Vec<IpAddr>> {
+    let conf = fs::read_to_string(RESOLV_CONF)?;
+    let nameservers: Vec<IpAddr> = conf
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        // drop the zone index of IPv6 addresses, e.g. fe80::1%eth0
+        .filter_map(|address| address.trim().split('%').next()?.parse().ok())
+        .collect();
+    if nameservers.is_empty() {
+        return Err(Error::Io(ErrorKind::NotFound.into()));
+    }
+    Ok(nameservers)
+}
+
+fn exchange(nameserver: IpAddr, query: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+    let local: SocketAddr = match nameserver {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect((nameserver, DNS_PORT))?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.send(query)?;
+
+    let mut buffer = vec![0u8; MAX_RESPONSE_SIZE];
+    let length = crate::read_timed_out(socket.recv(&mut buffer))?;
+    buffer.truncate(length);
+    Ok(buffer)
+}
+
+fn build_query(id: u16, name: &str) -> Result<Vec<u8>> {
+    let mut query = id.to_be_bytes().to_vec();
+    // recursion desired, one question
+    query.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::Io(ErrorKind::InvalidInput.into()));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+fn parse_srv_response(id: u16, response: &[u8]) -> Result<Vec<SrvRecord>> {
+    let parse = || {
+        let read_u16 = |offset: usize| -> Option<u16> {
+            Some(u16::from_be_bytes([
+                *response.get(offset)?,
+                *response.get(offset + 1)?,
+            ]))
+        };
+        if read_u16(0)? != id {
+            return None;
+        }
+        let flags = read_u16(2)?;
+        if flags & 0x000f == RCODE_NXDOMAIN {
+            return Some(vec![]);
+        }
+        if flags & 0x8000 == 0 || flags & 0x000f != 0 {
+            return None;
+        }
+        let questions = read_u16(4)?;
+        let answers = read_u16(6)?;
+
+        let mut offset = 12;
+        for _ in 0..questions {
+            offset = read_name(response, offset)?.1 + 4;
+        }
+        let mut records = vec![];
+        for _ in 0..answers {
+            offset = read_name(response, offset)?.1;
+            let kind = read_u16(offset)?;
+            let ttl = (read_u16(offset + 4)? as u64) << 16 | read_u16(offset + 6)? as u64;
+            let length = read_u16(offset + 8)? as usize;
+            let data = offset + 10;
+            offset = data + length;
+            if kind == TYPE_SRV {
+                records.push(SrvRecord {
+                    priority: read_u16(data)?,
+                    weight: read_u16(data + 2)?,
+                    port: read_u16(data + 4)?,
+                    target: read_name(response, data + 6)?.0,
+                    ttl: Some(Duration::from_secs(ttl)),
+                });
+            }
+        }
+        Some(records)
+    };
+    parse().ok_or(Error::UnsupportedProtocol)
+}
+
+// read a possibly compressed name, and return it with the offset right after it
+fn read_name(message: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = vec![];
+    let mut end = None;
+    // bound the iterations, since the pointers of a malformed message can form a loop
+    for _ in 0..message.len() {
+        let length = *message.get(offset)? as usize;
+        if length & 0xc0 == 0xc0 {
+            let pointer = (length & 0x3f) << 8 | *message.get(offset + 1)? as usize;
+            end.get_or_insert(offset + 2);
+            offset = pointer;
+        } else if length == 0 {
+            return Some((labels.join("."), end.unwrap_or(offset + 1)));
+        } else {
+            let label = message.get(offset + 1..offset + 1 + length)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            offset += 1 + length;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_srv_records() {
+        let id = 0x1234;
+        let mut response = build_query(id, "_minecraft._tcp.example.com").unwrap();
+        // a response with two answers
+        response[2..4].copy_from_slice(&[0x81, 0x80]);
+        response[6..8].copy_from_slice(&[0x00, 0x02]);
+        for (priority, weight, port, target) in [
+            (10u16, 0u16, 25566u16, &b"\x04play\xc0\x1c"[..]),
+            (5, 0, 25570, b"\x02mc\x07example\x03net\x00"),
+        ] {
+            // a pointer to the question name
+            response.extend_from_slice(&[0xc0, 0x0c]);
+            response.extend_from_slice(&TYPE_SRV.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&300u32.to_be_bytes());
+            response.extend_from_slice(&(6 + target.len() as u16).to_be_bytes());
+            response.extend_from_slice(&priority.to_be_bytes());
+            response.extend_from_slice(&weight.to_be_bytes());
+            response.extend_from_slice(&port.to_be_bytes());
+            response.extend_from_slice(target);
+        }
+
+        let records = parse_srv_response(id, &response).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].target, "play.example.com");
+        assert_eq!(records[0].port, 25566);
+        assert_eq!(records[1].target, "mc.example.net");
+        assert_eq!(records[1].ttl, Some(Duration::from_secs(300)));
+        assert!(parse_srv_response(id + 1, &response).is_err());
+    }
+
+    #[test]
+    fn order_by_priority_and_weight() {
+        let record = |priority, weight, target: &str| SrvRecord {
+            priority,
+            weight,
+            port: 25565,
+            target: target.to_string(),
+            ttl: None,
+        };
+        let records = vec![
+            record(20, 0, "backup"),
+            record(10, 1, "light"),
+            record(10, 0, "idle"),
+            record(10, 3, "heavy"),
+        ];
+        let targets = |random: &dyn Fn(u32) -> u32| -> Vec<String> {
+            order_srv(records.clone(), random)
+                .into_iter()
+                .map(|record| record.target)
+                .collect()
+        };
+        // the running sums are idle: 0, light: 1, heavy: 4
+        assert_eq!(targets(&|_| 0), ["idle", "light", "heavy", "backup"]);
+        assert_eq!(
+            targets(&|total| total),
+            ["heavy", "light", "idle", "backup"]
+        );
+        assert_eq!(
+            targets(&|total| total.min(1)),
+            ["light", "heavy", "idle", "backup"]
+        );
+    }
+
+    #[test]
+    fn cache_forever() {
+        let cache = DnsCache::new(Duration::MAX);
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        insert(&cache.addresses, "a", vec![ip], cache.ttl(None));
+        assert_eq!(get(&cache.addresses, "a"), Some((vec![ip], Duration::MAX)));
+    }
+}
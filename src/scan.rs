@@ -0,0 +1,400 @@
+//! Provides a [`Scanner`](Scanner) which pings many addresses, like the IP ranges, concurrently.
+//!
+//! The [`Scanner`](Scanner) connects to every target with short timeouts, running a bounded number of pings at once,
+//! and streams the results through [`Scan`](Scan) as they arrive.
+//! A failed target is reported in its own [`ScanResult`](ScanResult), without aborting the rest of the scan.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use craftping::scan::Scanner;
+//!
+//! # async fn run() {
+//! let mut scan = Scanner::new().scan_cidr(["192.168.0.0/24".parse().unwrap()]);
+//! while let Some(found) = scan.next().await {
+//!     if let Ok(response) = found.result {
+//!         println!("{}: {} player(s) online", found.address, response.online_players);
+//!     }
+//! }
+//! # }
+//! ```
+use std::{
+    fmt::Display,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use ::tokio::{
+    sync::{mpsc, Semaphore},
+    task::spawn,
+    time::timeout,
+};
+
+use crate::{
+    pinger::SocketOptions,
+    sink::StatusSink,
+    tokio::{RateLimit, RateLimiter, TimeoutStream},
+    *,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// A range of IP addresses in the CIDR notation, e.g. `192.168.0.0/24`.
+///
+/// The address without a prefix length, e.g. `192.168.0.1`, is a range of the single address.
+pub struct Cidr {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Create a range of the addresses sharing the first `prefix` bits with the address.
+    ///
+    /// Return `None` if the prefix is longer than the address.
+    pub fn new(address: IpAddr, prefix: u8) -> Option<Self> {
+        let address = match address {
+            IpAddr::V4(address) if prefix <= 32 => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(address) & mask))
+            }
+            IpAddr::V6(address) if prefix <= 128 => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(address) & mask))
+            }
+            _ => return None,
+        };
+        Some(Self { address, prefix })
+    }
+
+    /// The first address of the range.
+    pub fn network(&self) -> IpAddr {
+        self.address
+    }
+
+    /// The length of the prefix in bits.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Iterate over every address of the range, in ascending order.
+    pub fn addresses(&self) -> impl Iterator<Item = IpAddr> {
+        let (start, bits) = match self.address {
+            IpAddr::V4(address) => (u32::from(address) as u128, 32),
+            IpAddr::V6(address) => (u128::from(address), 128),
+        };
+        let last = start
+            | u128::MAX
+                .checked_shr(128 - bits + self.prefix as u32)
+                .unwrap_or(0);
+        let is_ipv4 = self.address.is_ipv4();
+        (start..=last).map(move |address| match is_ipv4 {
+            true => IpAddr::V4(Ipv4Addr::from(address as u32)),
+            false => IpAddr::V6(Ipv6Addr::from(address)),
+        })
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = ParseCidrError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let address: IpAddr = address.parse().map_err(|_| ParseCidrError)?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| ParseCidrError)?,
+            None if address.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(address, prefix).ok_or(ParseCidrError)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The error returned when a [`Cidr`](Cidr) cannot be parsed.
+pub struct ParseCidrError;
+
+impl Display for ParseCidrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid CIDR notation")
+    }
+}
+
+impl std::error::Error for ParseCidrError {}
+
+#[derive(Debug)]
+/// The result of pinging a target of the scan.
+pub struct ScanResult {
+    /// The address of the target.
+    pub address: SocketAddr,
+    /// The response of the target, or the error while connecting or pinging.
+    pub result: Result<Response>,
+}
+
+#[derive(Debug, Clone)]
+/// A builder to scan many addresses concurrently.
+///
+/// By default, it scans the port 25565, 256 targets at once, with 1 second connect timeout and 3 seconds read and write timeouts,
+/// without a rate limit.
+pub struct Scanner {
+    ports: Vec<u16>,
+    concurrency: usize,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    rate_limit: Option<RateLimit>,
+    token: Option<CancelToken>,
+    socket: SocketOptions,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scanner {
+    /// Create a scanner with the default options.
+    pub fn new() -> Self {
+        Self {
+            ports: vec![25565],
+            concurrency: 256,
+            connect_timeout: Duration::from_secs(1),
+            read_timeout: Duration::from_secs(3),
+            rate_limit: None,
+            token: None,
+            socket: SocketOptions::default(),
+        }
+    }
+
+    /// Set the ports to scan on every address of the ranges, used by [`scan_cidr`](Scanner::scan_cidr).
+    pub fn ports(mut self, ports: impl Into<Vec<u16>>) -> Self {
+        self.ports = ports.into();
+        self
+    }
+
+    /// Set the maximum number of the targets pinged at once.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set the timeout of connecting to each target.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Set the timeout of each read and write to each target.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Set the rate of starting pings. Unlimited by default.
+    ///
+    /// The targets are pinged in the order given, so interleaving the networks
+    /// lets the scan go on while the limit per network is reached.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Set the options of the sockets, as [`Pinger::socket_options`](crate::pinger::Pinger::socket_options) does.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::{pinger::SocketOptions, scan::Scanner};
+    /// use std::time::Duration;
+    ///
+    /// # async fn run() {
+    /// // reset the connections on close and reuse the local ports, not to run out of them
+    /// let options = SocketOptions::new()
+    ///     .linger(Some(Duration::ZERO))
+    ///     .reuse_address(true);
+    /// let scan = Scanner::new()
+    ///     .socket_options(options)
+    ///     .scan_cidr(["192.168.0.0/24".parse().unwrap()]);
+    /// # }
+    /// ```
+    #[cfg(feature = "socket")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "socket")))]
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket = options;
+        self
+    }
+
+    /// Stop the scans when the token is cancelled.
+    ///
+    /// No more targets are pinged after the cancellation, while the pings in flight are finished and yielded,
+    /// so the scan ends as soon as they are done.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::{scan::Scanner, CancelToken};
+    ///
+    /// # async fn run() {
+    /// let token = CancelToken::new();
+    /// let mut scan = Scanner::new()
+    ///     .cancel_token(token.clone())
+    ///     .scan_cidr(["10.0.0.0/8".parse().unwrap()]);
+    /// while let Some(found) = scan.next().await {
+    ///     if found.result.is_ok() {
+    ///         // the first server is enough
+    ///         token.cancel();
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Start to scan every port of every address in the ranges.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn scan_cidr<I>(&self, ranges: I) -> Scan
+    where
+        I: IntoIterator<Item = Cidr>,
+        I::IntoIter: Send + 'static,
+    {
+        let ports = self.ports.clone();
+        self.scan(
+            ranges
+                .into_iter()
+                .flat_map(move |range| {
+                    let ports = ports.clone();
+                    range.addresses().flat_map(move |address| {
+                        ports.clone().into_iter().map(move |port| (address, port))
+                    })
+                })
+                .map(SocketAddr::from),
+        )
+    }
+
+    /// Start to scan the targets.
+    ///
+    /// The targets are consumed lazily, as the pings are finished.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn scan<I>(&self, targets: I) -> Scan
+    where
+        I: IntoIterator<Item = SocketAddr>,
+        I::IntoIter: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(self.concurrency);
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let scanner = self.clone();
+        let targets = targets.into_iter();
+        let mut limiter = self.rate_limit.map(RateLimiter::new);
+        spawn(async move {
+            for address in targets {
+                // the semaphore is never closed
+                let Ok(permit) = semaphore.clone().acquire_owned().await else {
+                    break;
+                };
+                if let Some(limiter) = &mut limiter {
+                    limiter.acquire(Some(address.ip())).await;
+                }
+                // stop when the scan is dropped or cancelled
+                if sender.is_closed()
+                    || scanner
+                        .token
+                        .as_ref()
+                        .is_some_and(CancelToken::is_cancelled)
+                {
+                    break;
+                }
+                let sender = sender.clone();
+                let scanner = scanner.clone();
+                spawn(async move {
+                    let result = scanner.ping(address).await;
+                    let _ = sender.send(ScanResult { address, result }).await;
+                    drop(permit);
+                });
+            }
+        });
+        Scan { receiver }
+    }
+
+    async fn ping(&self, address: SocketAddr) -> Result<Response> {
+        let stream = timeout(self.connect_timeout, self.socket.connect_tokio(address))
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))?;
+        let mut stream =
+            TimeoutStream::new(stream, Some(self.read_timeout), Some(self.read_timeout));
+        let hostname = address.ip().to_string();
+        crate::tokio::ping(&mut stream, &hostname, address.port()).await
+    }
+}
+
+#[derive(Debug)]
+/// A running scan, which yields the results as they arrive.
+///
+/// The results are not in the order of the targets. Dropping it stops the scan.
+pub struct Scan {
+    receiver: mpsc::Receiver<ScanResult>,
+}
+
+impl Scan {
+    /// Wait for the next result, or return `None` if every target is scanned.
+    pub async fn next(&mut self) -> Option<ScanResult> {
+        self.receiver.recv().await
+    }
+
+    /// Wait for every result and record it into the sink, named by the address of the target,
+    /// and then flush the sink.
+    ///
+    /// The scan stops if the sink fails.
+    pub async fn record_into(mut self, mut sink: impl StatusSink) -> Result<()> {
+        while let Some(found) = self.next().await {
+            sink.record(&found.address.to_string(), &found.result)?;
+        }
+        sink.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_cidr() {
+        let cidr: Cidr = "192.168.1.77/30".parse().unwrap();
+        assert_eq!("192.168.1.76/30", cidr.to_string());
+        let addresses: Vec<_> = cidr.addresses().map(|ip| ip.to_string()).collect();
+        assert_eq!(
+            vec![
+                "192.168.1.76",
+                "192.168.1.77",
+                "192.168.1.78",
+                "192.168.1.79"
+            ],
+            addresses
+        );
+
+        let single: Cidr = "::1".parse().unwrap();
+        assert_eq!(1, single.addresses().count());
+        let all: Cidr = "10.1.2.3/0".parse().unwrap();
+        assert_eq!(IpAddr::from([0, 0, 0, 0]), all.network());
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+    }
+}
@@ -0,0 +1,563 @@
+//! Provides the server side of the Server List Ping, to answer the ping requests with a [`Response`](Response).
+//!
+//! It is useful for the proxies and the placeholder servers, e.g. showing a maintenance message
+//! while the real server is down.
+//! The legacy ping requests (0xFE) of the old clients are answered as well, in the format each client expects.
+//! The clients of 1.5 and older are told apart only by sending nothing more after the request,
+//! so set a read timeout on the stream to answer them.
+//! The favicon of the response can be read from the PNG file with [`read_favicon`](read_favicon).
+//! The server can also be shown in the LAN worlds section of the clients nearby with [`announce_lan`](announce_lan).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use craftping::{server::respond, Chat, Response};
+//! use std::net::TcpListener;
+//!
+//! let response = Response::new("1.20.4", 765, Chat::from_legacy("§cUnder maintenance"));
+//! let listener = TcpListener::bind(("0.0.0.0", 25565)).unwrap();
+//! for stream in listener.incoming() {
+//!     if let Ok(mut stream) = stream {
+//!         let _ = respond(&mut stream, &response);
+//!     }
+//! }
+//! ```
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{sync::read_varint, write_varint, Error, Limits, Response, Result, Strategy};
+
+// the handshake of the longest hostname (255 characters in UTF-8) fits in it
+const MAX_HANDSHAKE_LENGTH: i32 = 1100;
+// the signature and the header chunk which every PNG file starts with
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const PNG_HEADER: &[u8] = b"\0\0\0\x0dIHDR";
+// the only size of the favicons the clients show
+const FAVICON_SIZE: u32 = 64;
+
+/// The multicast address the clients listen on for the LAN worlds.
+pub const LAN_ADDRESS: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(224, 0, 2, 60), 4445);
+
+/// The interval the vanilla game announces a LAN world at.
+pub const LAN_INTERVAL: Duration = Duration::from_millis(1500);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The handshake sent by the client before the status request.
+pub struct Handshake {
+    /// The version of the ping protocol the client used.
+    pub strategy: Strategy,
+    /// The protocol version the client advertised, or -1 if the client is determining what version to use.
+    /// It is -1 for the legacy requests of 1.5 and older, which do not advertise one.
+    pub protocol_version: i32,
+    /// The hostname the client connected to, including the marker of the Forge clients if any.
+    /// It is empty for the legacy requests of 1.5 and older.
+    pub hostname: String,
+    /// The port the client connected to. It is 0 for the legacy requests of 1.5 and older.
+    pub port: u16,
+    /// The state the client requested, which is 1 (status) for the ping requests.
+    pub next_state: i32,
+}
+
+/// Read a ping request from the client, and answer it with the response.
+///
+/// The latency measurement which follows is answered as well, if the client sends one.
+/// The handshake of the client is returned.
+/// If the client requested a state other than status (e.g. to log in), it fails with [`UnsupportedProtocol`](Error::UnsupportedProtocol).
+pub fn respond<Stream>(stream: &mut Stream, response: &Response) -> Result<Handshake>
+where
+    Stream: Read + Write,
+{
+    let handshake = read_handshake(stream)?;
+    answer(stream, &handshake, response)?;
+    Ok(handshake)
+}
+
+/// Read a ping request from the client, and answer it with the response built from the handshake.
+///
+/// It is useful to answer differently by the hostname, like a virtual host.
+/// See also [`respond`](respond).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{server::respond_with, Chat, Response};
+/// use std::net::TcpListener;
+///
+/// let listener = TcpListener::bind(("0.0.0.0", 25565)).unwrap();
+/// let (mut stream, _) = listener.accept().unwrap();
+/// respond_with(&mut stream, |handshake| {
+///     let motd = format!("You connected to {}", handshake.hostname);
+///     Response::new("1.20.4", 765, Chat::from_legacy(&motd))
+/// })
+/// .unwrap();
+/// ```
+pub fn respond_with<Stream, F>(stream: &mut Stream, response: F) -> Result<Handshake>
+where
+    Stream: Read + Write,
+    F: FnOnce(&Handshake) -> Response,
+{
+    let handshake = read_handshake(stream)?;
+    answer(stream, &handshake, &response(&handshake))?;
+    Ok(handshake)
+}
+
+/// Read the handshake and the status request from the client.
+///
+/// The response should be sent with [`write_status`](write_status) afterwards.
+/// If the client requested a state other than status, it fails with [`UnsupportedProtocol`](Error::UnsupportedProtocol).
+pub fn read_handshake<Stream>(stream: &mut Stream) -> Result<Handshake>
+where
+    Stream: Read,
+{
+    let mut first = [0u8];
+    stream.read_exact(&mut first)?;
+    if first[0] == 0xfe {
+        return read_legacy_handshake(stream);
+    }
+
+    let length = read_varint(&mut first.chain(&mut *stream))?;
+    if !(0..=MAX_HANDSHAKE_LENGTH).contains(&length) {
+        return Err(Error::UnsupportedProtocol);
+    }
+    let mut packet = vec![0u8; length as usize];
+    stream.read_exact(&mut packet)?;
+    let handshake = parse_handshake(&mut packet.as_slice())?;
+    if handshake.next_state != 1 {
+        return Err(Error::UnsupportedProtocol);
+    }
+
+    // the status request has no fields
+    let length = read_varint(stream)?;
+    let packet_id = read_varint(stream)?;
+    if length != 1 || packet_id != 0x00 {
+        return Err(Error::UnsupportedProtocol);
+    }
+    Ok(handshake)
+}
+
+// read the rest of a legacy request following 0xFE
+fn read_legacy_handshake<Stream>(stream: &mut Stream) -> Result<Handshake>
+where
+    Stream: Read,
+{
+    let mut handshake = Handshake {
+        strategy: Strategy::Beta,
+        protocol_version: -1,
+        hostname: String::new(),
+        port: 0,
+        next_state: 1,
+    };
+    // the older clients send nothing more and wait for the response
+    if read_byte_or_wait(stream)? != Some(0x01) {
+        return Ok(handshake);
+    }
+    handshake.strategy = Strategy::Legacy14;
+    if read_byte_or_wait(stream)? != Some(0xfa) {
+        return Ok(handshake);
+    }
+
+    // the plugin message of 1.6: MC|PingHost, the protocol version, the hostname and the port
+    handshake.strategy = Strategy::Legacy16;
+    let channel_length = read_u16(stream)?;
+    let _channel = read_utf16(stream, channel_length)?;
+    let data_length = read_u16(stream)?;
+    // the protocol version, the length of the hostname and the port
+    if !(7..=MAX_HANDSHAKE_LENGTH as u16).contains(&data_length) {
+        return Err(Error::UnsupportedProtocol);
+    }
+    let mut protocol_version = [0u8];
+    stream.read_exact(&mut protocol_version)?;
+    let protocol_version = protocol_version[0];
+    let mut data = vec![0u8; data_length as usize - 1];
+    stream.read_exact(&mut data)?;
+    let mut data = data.as_slice();
+    let hostname_length = read_u16(&mut data)?;
+    let hostname = read_utf16(&mut data, hostname_length)?;
+    let mut port = [0u8; 4];
+    data.read_exact(&mut port)?;
+    handshake.protocol_version = protocol_version as i32;
+    handshake.hostname = hostname;
+    handshake.port = i32::from_be_bytes(port) as u16;
+    Ok(handshake)
+}
+
+// read a byte, or return None if the client is waiting for the response
+fn read_byte_or_wait<Stream>(stream: &mut Stream) -> Result<Option<u8>>
+where
+    Stream: Read,
+{
+    let mut byte = [0u8];
+    match stream.read_exact(&mut byte) {
+        Ok(()) => Ok(Some(byte[0])),
+        Err(error)
+            if matches!(
+                error.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::UnexpectedEof
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn read_u16<Stream>(stream: &mut Stream) -> Result<u16>
+where
+    Stream: Read,
+{
+    let mut short = [0u8; 2];
+    stream.read_exact(&mut short)?;
+    Ok(u16::from_be_bytes(short))
+}
+
+// read a string of the length in UTF-16 code units
+fn read_utf16<Stream>(stream: &mut Stream, length: u16) -> Result<String>
+where
+    Stream: Read,
+{
+    let mut bytes = vec![0u8; length as usize * 2];
+    stream.read_exact(&mut bytes)?;
+    let utf16be: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+        .collect();
+    String::from_utf16(&utf16be).map_err(|_| Error::UnsupportedProtocol)
+}
+
+fn parse_handshake(packet: &mut &[u8]) -> Result<Handshake> {
+    let packet_id = read_varint(packet)?;
+    if packet_id != 0x00 {
+        return Err(Error::UnsupportedProtocol);
+    }
+    let protocol_version = read_varint(packet)?;
+    let hostname_length = read_varint(packet)?;
+    if hostname_length < 0 || hostname_length as usize > packet.len() {
+        return Err(Error::UnsupportedProtocol);
+    }
+    let (hostname, rest) = packet.split_at(hostname_length as usize);
+    let hostname = String::from_utf8(hostname.to_vec()).map_err(|_| Error::UnsupportedProtocol)?;
+    *packet = rest;
+    let mut port = [0u8; 2];
+    packet.read_exact(&mut port)?;
+    let next_state = read_varint(packet)?;
+    Ok(Handshake {
+        strategy: Strategy::Modern,
+        protocol_version,
+        hostname,
+        port: u16::from_be_bytes(port),
+        next_state,
+    })
+}
+
+/// Send the response to the status request, in the format of the protocol the client used.
+///
+/// The legacy responses have only the version, the protocol number, the description without styles and the player counts.
+pub fn write_status<Stream>(
+    stream: &mut Stream,
+    strategy: Strategy,
+    response: &Response,
+) -> Result<()>
+where
+    Stream: Write,
+{
+    let packet = match strategy {
+        Strategy::Modern => build_status(response),
+        Strategy::Legacy16 | Strategy::Legacy14 => build_legacy_status(&format!(
+            "\u{00a7}1\0{}\0{}\0{}\0{}\0{}",
+            response.protocol,
+            response.version,
+            response.description.plain_text(),
+            response.online_players,
+            response.max_players,
+        )),
+        // the fields are separated by §, so it is removed from the MOTD
+        Strategy::Beta => build_legacy_status(&format!(
+            "{}\u{00a7}{}\u{00a7}{}",
+            response.description.plain_text().replace('\u{00a7}', ""),
+            response.online_players,
+            response.max_players,
+        )),
+    };
+    stream.write_all(&packet)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Read the favicon of the server from the PNG file, to set as the [`favicon`](Response::favicon) of the response.
+///
+/// The response encodes it as a `data:image/png;base64,` URI when written.
+/// The clients show only a 64×64 PNG, so any other image fails with [`InvalidData`](io::ErrorKind::InvalidData),
+/// as does a file larger than the default [`max_favicon_size`](Limits::max_favicon_size).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{server::read_favicon, Chat, Response};
+///
+/// let mut response = Response::new("1.20.4", 765, Chat::from_legacy("A Minecraft Server"));
+/// response.favicon = Some(read_favicon("server-icon.png").unwrap());
+/// ```
+pub fn read_favicon(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let max_size = Limits::default().max_favicon_size;
+    let mut favicon = vec![];
+    // read one byte more than the limit to tell a file too large
+    File::open(path)?
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut favicon)?;
+    if favicon.len() > max_size {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the favicon is too large").into());
+    }
+    check_favicon(&favicon)?;
+    Ok(favicon)
+}
+
+// check the header of the PNG for the size, without decoding the image
+fn check_favicon(favicon: &[u8]) -> io::Result<()> {
+    let header = favicon
+        .strip_prefix(PNG_SIGNATURE)
+        .and_then(|rest| rest.strip_prefix(PNG_HEADER))
+        .filter(|header| header.len() >= 8)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the favicon is not a PNG"))?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    if (width, height) != (FAVICON_SIZE, FAVICON_SIZE) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the favicon is {}x{}, not 64x64", width, height),
+        ));
+    }
+    Ok(())
+}
+
+/// Announce the server on the port to the clients in the local network, as the vanilla game does for a LAN world.
+///
+/// The clients list the announced servers in the LAN worlds section with the MOTD, and forget them soon,
+/// so send the announcement every [`LAN_INTERVAL`](LAN_INTERVAL) while serving.
+/// It is sent to [`LAN_ADDRESS`](LAN_ADDRESS) from the socket, which should be bound to an IPv4 address.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::server::{announce_lan, LAN_INTERVAL};
+/// use std::net::UdpSocket;
+///
+/// let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+/// loop {
+///     announce_lan(&socket, "§cUnder maintenance", 25565).unwrap();
+///     std::thread::sleep(LAN_INTERVAL);
+/// }
+/// ```
+pub fn announce_lan(socket: &UdpSocket, motd: &str, port: u16) -> Result<()> {
+    socket.send_to(
+        build_lan_announcement(motd, port).as_bytes(),
+        SocketAddr::V4(LAN_ADDRESS),
+    )?;
+    Ok(())
+}
+
+fn build_lan_announcement(motd: &str, port: u16) -> String {
+    // the clients take the MOTD up to the first closing tag
+    let motd = motd.replace("[/MOTD]", "");
+    format!("[MOTD]{}[/MOTD][AD]{}[/AD]", motd, port)
+}
+
+pub(crate) fn build_status(response: &Response) -> Vec<u8> {
+    build_status_packet(response.to_status_json().as_bytes())
+}
+
+// the status response packet with the bytes as the JSON, which need not be valid
+pub(crate) fn build_status_packet(json: &[u8]) -> Vec<u8> {
+    let mut data = vec![
+        0x00, // packet id: 0 for response as VarInt
+    ];
+    write_varint(&mut data, json.len() as i32);
+    data.extend_from_slice(json);
+    let mut packet = vec![];
+    write_varint(&mut packet, data.len() as i32);
+    packet.append(&mut data);
+    packet
+}
+
+fn build_legacy_status(status: &str) -> Vec<u8> {
+    let utf16: Vec<u16> = status.encode_utf16().collect();
+    let mut packet = vec![
+        0xff, // packet id: 0xff for kick
+    ];
+    packet.extend_from_slice(&(utf16.len() as u16).to_be_bytes());
+    packet.extend(utf16.iter().flat_map(|unit| unit.to_be_bytes()));
+    packet
+}
+
+fn answer<Stream>(stream: &mut Stream, handshake: &Handshake, response: &Response) -> Result<()>
+where
+    Stream: Read + Write,
+{
+    write_status(stream, handshake.strategy, response)?;
+    // the legacy clients do not measure the latency
+    if handshake.strategy != Strategy::Modern {
+        return Ok(());
+    }
+
+    // the client may close the connection without measuring the latency
+    let mut ping = [0u8; 10];
+    match stream.read_exact(&mut ping) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+        Err(error) => return Err(error.into()),
+    }
+    if ping[..2] != [9, 0x01] {
+        return Err(Error::UnsupportedProtocol);
+    }
+    // the pong is the same packet as the ping
+    stream.write_all(&ping)?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::net::{TcpListener, TcpStream};
+
+    use crate::Chat;
+
+    #[test]
+    fn answer_ping() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+            response.online_players = 3;
+            respond(&mut stream, &response).unwrap()
+        });
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let (response, _) = crate::sync::ping_with_latency(&mut stream, "localhost", port).unwrap();
+        assert_eq!(response.version, "1.20.4");
+        assert_eq!(response.online_players, 3);
+        assert_eq!(response.description.plain_text(), "Hello");
+
+        let handshake = server.join().unwrap();
+        assert_eq!(handshake.strategy, Strategy::Modern);
+        assert_eq!(handshake.hostname, "localhost");
+        assert_eq!(handshake.port, port);
+    }
+
+    #[test]
+    fn answer_legacy_ping() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut handshakes = vec![];
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                stream
+                    .set_read_timeout(Some(std::time::Duration::from_millis(100)))
+                    .unwrap();
+                let response = Response::new("1.6.4", 78, Chat::from_legacy("Hello"));
+                handshakes.push(respond(&mut stream, &response).unwrap());
+            }
+            handshakes
+        });
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let response = crate::sync::ping_legacy(&mut stream, "localhost", port).unwrap();
+        assert_eq!(response.version, "1.6.4");
+        assert_eq!(response.description.text, "Hello");
+        let mut transport =
+            |_: &str, port: u16| Ok::<_, Error>(TcpStream::connect(("127.0.0.1", port))?);
+        let response =
+            crate::sync::ping_with_fallback(&mut transport, "localhost", port, &[Strategy::Beta])
+                .unwrap();
+        assert_eq!(response.description.text, "Hello");
+
+        let handshakes = server.join().unwrap();
+        assert_eq!(handshakes[0].strategy, Strategy::Legacy16);
+        assert_eq!(handshakes[0].protocol_version, 0x4a);
+        assert_eq!(handshakes[0].hostname, "localhost");
+        assert_eq!(handshakes[0].port, port);
+        assert_eq!(handshakes[1].strategy, Strategy::Beta);
+    }
+
+    #[test]
+    fn reject_long_legacy_handshake() {
+        let mut request = vec![0xfe, 0x01, 0xfa];
+        request.extend_from_slice(&11u16.to_be_bytes());
+        request.extend("MC|PingHost".encode_utf16().flat_map(u16::to_be_bytes));
+        // a length byte followed by the protocol version reads as one length over the limit
+        request.extend_from_slice(&[0x12, 0x4a]);
+        request.extend_from_slice(&[0; 0x12]);
+        assert!(matches!(
+            read_handshake(&mut request.as_slice()),
+            Err(Error::UnsupportedProtocol)
+        ));
+    }
+
+    #[test]
+    fn strip_separator_from_beta_motd() {
+        let mut response = Response::new(
+            "b1.7.3",
+            0,
+            Chat {
+                text: "A \u{00a7} B".to_string(),
+                ..Default::default()
+            },
+        );
+        response.online_players = 3;
+        let mut packet = vec![];
+        write_status(&mut packet, Strategy::Beta, &response).unwrap();
+        let status: Vec<u16> = packet[3..]
+            .chunks_exact(2)
+            .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+            .collect();
+        assert_eq!(
+            String::from_utf16(&status).unwrap(),
+            "A  B\u{00a7}3\u{00a7}20"
+        );
+    }
+
+    #[test]
+    fn read_png_favicon() {
+        let png = |width: u32, height: u32| {
+            let mut png = PNG_SIGNATURE.to_vec();
+            png.extend_from_slice(PNG_HEADER);
+            png.extend_from_slice(&width.to_be_bytes());
+            png.extend_from_slice(&height.to_be_bytes());
+            png.extend_from_slice(&[8, 6, 0, 0, 0]);
+            png
+        };
+        let path =
+            std::env::temp_dir().join(format!("craftping-favicon-{}.png", std::process::id()));
+        std::fs::write(&path, png(64, 64)).unwrap();
+        let favicon = read_favicon(&path);
+        std::fs::remove_file(&path).unwrap();
+        let mut response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+        response.favicon = Some(favicon.unwrap());
+        assert!(response
+            .to_status_json()
+            .contains("\"data:image/png;base64,iVBORw0KGgo"));
+
+        assert!(check_favicon(&png(128, 128)).is_err());
+        assert!(check_favicon(b"GIF89a").is_err());
+        assert!(check_favicon(&PNG_SIGNATURE[..4]).is_err());
+    }
+
+    #[test]
+    fn build_lan_announcements() {
+        assert_eq!(
+            build_lan_announcement("§cUnder maintenance", 25565),
+            "[MOTD]§cUnder maintenance[/MOTD][AD]25565[/AD]"
+        );
+        assert_eq!(
+            build_lan_announcement("A[/MOTD]B", 25566),
+            "[MOTD]AB[/MOTD][AD]25566[/AD]"
+        );
+    }
+}
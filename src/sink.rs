@@ -0,0 +1,233 @@
+//! Provides the [`StatusSink`](StatusSink) abstraction to record the ping results as a history.
+//!
+//! The long-running pingers, like [`Watcher`](crate::watch::Watcher) and [`Scan`](crate::scan::Scan),
+//! push every result into a sink. [`NdjsonSink`](NdjsonSink) and [`CsvSink`](CsvSink) write them into any [`Write`](Write),
+//! and any other storage can be plugged in by implementing [`StatusSink`](StatusSink).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use craftping::{pinger::Pinger, sink::{NdjsonSink, StatusSink}};
+//! use std::fs::File;
+//!
+//! let mut sink = NdjsonSink::new(File::create("history.ndjson").unwrap());
+//! let result = Pinger::new("my.server.com", 25565).ping();
+//! sink.record("my.server.com", &result).unwrap();
+//! ```
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{Response, Result};
+
+/// Receives the ping results, to keep them as a history.
+pub trait StatusSink {
+    /// Record the result of pinging the server, named by `server`.
+    fn record(&mut self, server: &str, result: &Result<Response>) -> Result<()>;
+
+    /// Write out the buffered records, if any. Called when the recording is stopped.
+    ///
+    /// The default implementation does nothing, as the built-in sinks write every record as it arrives.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: StatusSink + ?Sized> StatusSink for &mut S {
+    fn record(&mut self, server: &str, result: &Result<Response>) -> Result<()> {
+        (**self).record(server, result)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+impl<S: StatusSink + ?Sized> StatusSink for Box<S> {
+    fn record(&mut self, server: &str, result: &Result<Response>) -> Result<()> {
+        (**self).record(server, result)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// The result of a ping and the time it finished, as written by [`NdjsonSink`](NdjsonSink)
+/// and passed to the exporters of [`Monitor`](crate::monitor::Monitor).
+pub struct Report {
+    /// The name of the server.
+    pub server: String,
+    /// The time the ping finished, in seconds since the unix epoch.
+    pub timestamp: u64,
+    /// The response if the server was online.
+    pub response: Option<Response>,
+    /// The error message if the server was offline.
+    pub error: Option<String>,
+    /// The round-trip time of the ping packet sent after the response in milliseconds, if it was measured.
+    ///
+    /// It does not include the time to resolve the hostname and to connect.
+    /// For the legacy servers, it is the time taken by the legacy request.
+    pub latency_ms: Option<f64>,
+}
+
+impl Report {
+    /// Create a report of the result of pinging the server named by `server`, finished now, without the latency.
+    pub fn new(server: impl Into<String>, result: &Result<Response>) -> Self {
+        Self {
+            server: server.into(),
+            timestamp: timestamp(),
+            response: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(ToString::to_string),
+            latency_ms: None,
+        }
+    }
+}
+
+// seconds since the unix epoch
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+/// Writes every result as a line of JSON object, which is a [`Report`](Report).
+///
+/// It is also the `ndjson` and `stdout` exporters of [`Monitor`](crate::monitor::Monitor).
+pub struct NdjsonSink<W> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonSink<W> {
+    /// Create a sink writing into the writer.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Return the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> NdjsonSink<W> {
+    /// Write the report as a line.
+    pub fn write_report(&mut self, report: &Report) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, report).map_err(io::Error::from)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> StatusSink for NdjsonSink<W> {
+    fn record(&mut self, server: &str, result: &Result<Response>) -> Result<()> {
+        self.write_report(&Report::new(server, result))
+    }
+}
+
+#[derive(Debug)]
+/// Writes every result as a row of CSV, preceded by the header row.
+///
+/// The columns are `timestamp`, `server`, `online`, `online_players`, `max_players`, `version`, `protocol` and `error`.
+/// The columns of the response are empty if the ping failed.
+pub struct CsvSink<W> {
+    writer: W,
+    header_written: bool,
+}
+
+impl<W: Write> CsvSink<W> {
+    /// Create a sink writing into the writer. The header is written with the first row.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            header_written: false,
+        }
+    }
+
+    /// Create a sink appending to the writer which already has the header, e.g. a reopened file.
+    pub fn without_header(writer: W) -> Self {
+        Self {
+            writer,
+            header_written: true,
+        }
+    }
+
+    /// Return the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> StatusSink for CsvSink<W> {
+    fn record(&mut self, server: &str, result: &Result<Response>) -> Result<()> {
+        if !self.header_written {
+            self.writer.write_all(
+                b"timestamp,server,online,online_players,max_players,version,protocol,error\n",
+            )?;
+            self.header_written = true;
+        }
+        let columns = match result {
+            Ok(response) => [
+                "true".to_string(),
+                response.online_players.to_string(),
+                response.max_players.to_string(),
+                escape_csv(&response.version),
+                response.protocol.to_string(),
+                String::new(),
+            ],
+            Err(error) => [
+                "false".to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                escape_csv(&error.to_string()),
+            ],
+        };
+        writeln!(
+            self.writer,
+            "{},{},{}",
+            timestamp(),
+            escape_csv(server),
+            columns.join(",")
+        )?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// quote the field if it has a delimiter, a quote or a line break
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::Error;
+
+    #[test]
+    fn write_csv() {
+        let mut sink = CsvSink::new(vec![]);
+        sink.record("a,b", &Err(Error::UnsupportedProtocol))
+            .unwrap();
+        sink.record("c", &Err(Error::UnsupportedProtocol)).unwrap();
+        let csv = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("timestamp,server,"));
+        assert!(lines[1].ends_with(",\"a,b\",false,,,,,unsupported protocol"));
+    }
+}
@@ -0,0 +1,143 @@
+//! Provides [`Stats`](Stats), which aggregates the ping results of a server over time.
+//!
+//! The [`Stats`](Stats) computes the availability, the latency distribution and the peak player count,
+//! for a monitoring dashboard or a status page.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use craftping::{pinger::Pinger, stats::Stats};
+//! use std::time::{Duration, Instant};
+//!
+//! let pinger = Pinger::new("my.server.com", 25565).connect_timeout(Duration::from_secs(3));
+//! let mut stats = Stats::new();
+//! for _ in 0..10 {
+//!     let start = Instant::now();
+//!     let result = pinger.ping();
+//!     stats.record(&result, Some(start.elapsed()));
+//!     std::thread::sleep(Duration::from_secs(60));
+//! }
+//! println!("{:.2}% available", stats.availability().unwrap() * 100.0);
+//! println!("p95 latency: {:?}", stats.latency_percentile(95.0));
+//! ```
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::{Response, Result};
+
+#[derive(Debug, Clone, Default)]
+/// The statistics of the ping results of a server.
+///
+/// Every latency is kept to compute the percentiles, so [`clear`](Stats::clear) it periodically
+/// when recording the results for a long time.
+pub struct Stats {
+    attempts: usize,
+    successes: usize,
+    latencies: Vec<Duration>,
+    peak_players: Option<usize>,
+}
+
+impl Stats {
+    /// Create an empty statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a ping result, with the time it took if measured.
+    ///
+    /// The latency of a failed ping is ignored.
+    pub fn record(&mut self, result: &Result<Response>, latency: Option<Duration>) {
+        self.attempts += 1;
+        if let Ok(response) = result {
+            self.successes += 1;
+            self.latencies.extend(latency);
+            self.peak_players = self.peak_players.max(Some(response.online_players));
+        }
+    }
+
+    /// Forget every recorded result.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The number of the recorded results.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    /// The number of the successful pings.
+    pub fn successes(&self) -> usize {
+        self.successes
+    }
+
+    /// The ratio of the successful pings, between `0.0` and `1.0`.
+    /// `None` if nothing is recorded.
+    pub fn availability(&self) -> Option<f64> {
+        (self.attempts > 0).then(|| self.successes as f64 / self.attempts as f64)
+    }
+
+    /// The mean latency of the successful pings. `None` if no latency is recorded.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let count = self.latencies.len() as u32;
+        (count > 0).then(|| self.latencies.iter().sum::<Duration>() / count)
+    }
+
+    /// The latency at the percentile between `0.0` and `100.0`, by the nearest-rank method.
+    /// `None` if no latency is recorded.
+    pub fn latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let mut latencies = self.latencies.clone();
+        latencies.sort_unstable();
+        // f64::ceil is not available without std
+        let exact = percentile.clamp(0.0, 100.0) / 100.0 * latencies.len() as f64;
+        let rank = exact as usize + ((exact as usize as f64) < exact) as usize;
+        Some(latencies[rank.saturating_sub(1)])
+    }
+
+    /// The largest number of the online players seen. `None` if no ping succeeded.
+    pub fn peak_players(&self) -> Option<usize> {
+        self.peak_players
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{entity::RawLatest, Error};
+
+    #[test]
+    fn aggregate_results() {
+        let raw = br#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":7},"description":"A"}"#;
+        let latest: RawLatest = serde_json::from_slice(raw).unwrap();
+        let online = Response::try_from(latest);
+
+        let mut stats = Stats::new();
+        assert_eq!(stats.availability(), None);
+        for millis in [40, 10, 30, 20] {
+            stats.record(&online, Some(Duration::from_millis(millis)));
+        }
+        stats.record(
+            &Err(Error::UnsupportedProtocol),
+            Some(Duration::from_secs(5)),
+        );
+
+        assert_eq!(stats.availability(), Some(0.8));
+        assert_eq!(stats.mean_latency(), Some(Duration::from_millis(25)));
+        assert_eq!(
+            stats.latency_percentile(50.0),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(
+            stats.latency_percentile(100.0),
+            Some(Duration::from_millis(40))
+        );
+        assert_eq!(
+            stats.latency_percentile(0.0),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(stats.peak_players(), Some(7));
+    }
+}
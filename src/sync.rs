@@ -2,9 +2,27 @@
 //!
 //! The [`ping`](ping) function here sends a ping request, and wait for the server to respond.
 //! If you want to send ping in an asynchronous context, see [`tokio`](tokio) or [`futures`](futures) module.
-use std::convert::TryInto;
+use std::{
+    collections::BTreeSet,
+    convert::TryInto,
+    net::{Ipv4Addr, Ipv6Addr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
 
-use crate::*;
+use crate::{
+    query::{full_stat_with_retransmission, FullStat, Retransmission},
+    transport::{TcpTransport, Transport},
+    *,
+};
+
+const LEGACY_14_REQUEST: [u8; 2] = [
+    0xfe, // 1st packet id: 0xfe for server list ping
+    0x01, // payload: always 1
+];
+
+const BETA_REQUEST: [u8; 1] = [
+    0xfe, // 1st packet id: 0xfe for server list ping
+];
 
 /// Send a ping request to the server and wait for the response.
 ///
@@ -30,46 +48,710 @@ pub fn ping<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Re
 where
     Stream: Read + Write,
 {
-    ping_latest(stream, hostname, port).or_else(|_| ping_legacy(stream))
+    ping_with_limits(stream, hostname, port, &Limits::default())
 }
 
-fn ping_latest<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+/// Send a ping request to the server and wait for the response,
+/// rejecting the response if it exceeds the limits.
+///
+/// [`ping`](ping) uses the default [`Limits`](Limits).
+pub fn ping_with_limits<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    limits: &Limits,
+) -> Result<Response>
 where
     Stream: Read + Write,
 {
-    let request = build_latest_request(hostname, port)?;
-    stream.write_all(&request)?;
-    stream.flush()?;
+    ping_with_config(stream, hostname, port, &PingConfig::from(*limits))
+}
 
-    let _length = read_varint(stream)?;
-    let packet_id = read_varint(stream)?;
-    let response_length = read_varint(stream)?;
-    if packet_id != 0x00 || response_length < 0 {
-        return Err(Error::UnsupportedProtocol);
+/// Send a ping request to the server with the options and wait for the response.
+///
+/// See also [`PingConfig`](PingConfig).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{sync::ping_with_config, PingConfig};
+/// use std::net::TcpStream;
+///
+/// let mut stream = TcpStream::connect(("127.0.0.1", 25565)).unwrap();
+/// let config = PingConfig {
+///     virtual_host: Some("my.server.com".to_string()),
+///     legacy_fallback: false,
+///     ..Default::default()
+/// };
+/// let response = ping_with_config(&mut stream, "127.0.0.1", 25565, &config).unwrap();
+/// ```
+pub fn ping_with_config<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    match request_latest(stream, hostname, port, config, &mut Vec::new()) {
+        Ok(response) => Ok(response),
+        Err(error) if !config.legacy_fallback => Err(error),
+        Err(error) => {
+            telemetry::fallback(&error);
+            request_legacy(
+                stream,
+                &build_legacy_request(hostname, port, config),
+                &config.limits,
+                &mut Vec::new(),
+            )
+        }
     }
-    let mut response_buffer = vec![0; response_length as usize];
-    stream.read_exact(&mut response_buffer)?;
+}
+
+/// Send a ping request of the current protocol (1.7 and later) to the server and wait for the response.
+///
+/// Unlike [`ping`](ping), it does not fall back to the legacy protocol,
+/// so the error of the current protocol is returned as is.
+pub fn ping_latest<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    request_latest(
+        stream,
+        hostname,
+        port,
+        &PingConfig::default(),
+        &mut Vec::new(),
+    )
+}
+
+/// Connect to the server at the `minecraft://host:port` URI and ping it, with the timeout of connecting and each read and write.
+///
+/// The address without the scheme is accepted as well.
+/// If the port is not given, the SRV record of the hostname is looked up like the vanilla client.
+/// An invalid URI is returned as an I/O error of [`InvalidInput`](std::io::ErrorKind::InvalidInput).
+/// See also [`ServerAddress::from_url`](ServerAddress::from_url).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::sync::ping_url;
+/// use std::time::Duration;
+///
+/// let response = ping_url("minecraft://my.server.com:25565", Duration::from_secs(5)).unwrap();
+/// println!("{} player(s) online", response.online_players);
+/// ```
+pub fn ping_url(url: &str, timeout: Duration) -> Result<Response> {
+    let address = ServerAddress::from_url(url)
+        .map_err(|_| Error::Io(std::io::ErrorKind::InvalidInput.into()))?;
+    ping_addr(address, timeout)
+}
 
-    let mut raw = decode_latest_response(&response_buffer)?;
-    raw.raw_json = response_buffer;
-    raw.try_into()
+/// Resolve and connect to the server, and ping it, with the timeout of connecting and each read and write.
+///
+/// The address parsed without the port is resolved through its SRV records like the vanilla client,
+/// trying the next target if connecting to one fails.
+/// See also [`ServerAddress::lookup_all`](ServerAddress::lookup_all).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::sync::ping_addr;
+/// use std::time::Duration;
+///
+/// let response = ping_addr(("my.server.com", 25565), Duration::from_secs(5)).unwrap();
+/// println!("{} player(s) online", response.online_players);
+/// ```
+pub fn ping_addr(address: impl Into<ServerAddress>, timeout: Duration) -> Result<Response> {
+    let address = address.into();
+    let mut transport = TcpTransport::new(timeout);
+    // fail over to the next target of the SRV records if the connection fails
+    let mut last_error = Error::Io(std::io::ErrorKind::NotFound.into());
+    for target in address.lookup_all() {
+        match transport.connect(target.hostname(), target.port()) {
+            Ok(mut stream) => return ping(&mut stream, address.hostname(), target.port()),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
 }
 
-fn ping_legacy<Stream>(stream: &mut Stream) -> Result<Response>
+/// Send a ping request of the legacy protocol (1.6) to the server and wait for the response.
+///
+/// Servers before 1.7 only understand this protocol.
+/// Most of the later servers also answer it, without the fields added since 1.7.
+///
+/// `hostname` and `port` are sent to the server as the 1.6 client does. Some proxies reject the request without them.
+pub fn ping_legacy<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
 where
     Stream: Read + Write,
 {
-    stream.write_all(&LEGACY_REQUEST)?;
-    stream.flush()?;
+    request_legacy(
+        stream,
+        &build_legacy_request(hostname, port, &PingConfig::default()),
+        &Limits::default(),
+        &mut Vec::new(),
+    )
+}
 
-    let mut buffer = Vec::new();
-    stream.read_to_end(&mut buffer)?;
+/// Send a ping request to the server and wait for the response,
+/// reusing the memory of the raw response received by the previous ping with the same `buf`.
+///
+/// It is useful when the same server is pinged repeatedly.
+/// The response is stored in `buf` until the next ping with it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{sync::ping_into, ResponseBuf};
+/// use std::net::TcpStream;
+///
+/// let mut buf = ResponseBuf::new();
+/// loop {
+///     let mut stream = TcpStream::connect(("my.server.com", 25565)).unwrap();
+///     let response = ping_into(&mut stream, "my.server.com", 25565, &mut buf).unwrap();
+///     println!("Players online: {}", response.online_players);
+///     std::thread::sleep(std::time::Duration::from_secs(5));
+/// }
+/// ```
+pub fn ping_into<'a, Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    buf: &'a mut ResponseBuf,
+) -> Result<&'a Response>
+where
+    Stream: Read + Write,
+{
+    let config = PingConfig::default();
+    let buffer = buf.reclaim();
+    let response = match request_latest(stream, hostname, port, &config, buffer) {
+        Ok(response) => response,
+        Err(_) => request_legacy(
+            stream,
+            &build_legacy_request(hostname, port, &config),
+            &config.limits,
+            buffer,
+        )?,
+    };
+    Ok(buf.store(response))
+}
 
-    let response = decode_legacy(&buffer)?;
-    parse_legacy(&response, buffer)
+/// Send a ping request to the server, and measure the round-trip time with the ping packet after the response.
+///
+/// The latency is measured the same way as the vanilla client does.
+/// For the legacy servers, which do not support the ping packet, the time taken by the legacy request is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::sync::ping_with_latency;
+/// use std::net::TcpStream;
+///
+/// let mut stream = TcpStream::connect(("my.server.com", 25565)).unwrap();
+/// let (response, latency) = ping_with_latency(&mut stream, "my.server.com", 25565).unwrap();
+/// println!("{} player(s) online, {:?}", response.online_players, latency);
+/// ```
+pub fn ping_with_latency<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+) -> Result<(Response, Duration)>
+where
+    Stream: Read + Write,
+{
+    let config = PingConfig::default();
+    match request_latest(stream, hostname, port, &config, &mut Vec::new()) {
+        Ok(response) => Ok((response, measure_latency(stream)?)),
+        Err(_) => {
+            let start = Instant::now();
+            let response = request_legacy(
+                stream,
+                &build_legacy_request(hostname, port, &config),
+                &config.limits,
+                &mut Vec::new(),
+            )?;
+            Ok((response, start.elapsed()))
+        }
+    }
 }
 
-fn read_varint(stream: &mut impl Read) -> Result<i32> {
+/// Try the strategies in order, each on a new connection opened by the transport,
+/// and return the first successful response.
+///
+/// Unlike [`ping`](ping), which falls back to the legacy protocol on the same connection,
+/// each strategy gets a fresh connection, so servers closing the connection on an unknown request
+/// are handled correctly. If every strategy fails, the error of the last one is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{sync::ping_with_fallback, transport::TcpTransport, Strategy};
+/// use std::time::Duration;
+///
+/// let mut transport = TcpTransport::new(Duration::from_secs(5));
+/// let strategies = [Strategy::Modern, Strategy::Legacy16, Strategy::Beta];
+/// let response = ping_with_fallback(&mut transport, "my.server.com", 25565, &strategies).unwrap();
+/// ```
+pub fn ping_with_fallback<T>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+    strategies: &[Strategy],
+) -> Result<Response>
+where
+    T: Transport,
+{
+    let config = PingConfig::default();
+    let mut last_error = Error::UnsupportedProtocol;
+    for &strategy in strategies {
+        let result = transport
+            .connect(hostname, port)
+            .and_then(|mut stream| ping_strategy(&mut stream, hostname, port, strategy, &config));
+        match result {
+            Ok(response) => return Ok(response),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+/// A way to get the status of a server, for [`ping_any`](ping_any).
+pub enum Probe {
+    /// The Server List Ping with the strategy, over a new connection opened by the transport.
+    Slp(Strategy),
+    /// The Query full stat request, waiting for the response to the first attempt up to the timeout.
+    ///
+    /// It is sent to the same port as the Server List Ping.
+    /// See also [`Retransmission`](crate::query::Retransmission) for the retransmissions.
+    Query(Duration),
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+/// The status of a server returned from [`ping_any`](ping_any).
+pub enum ServerStatus {
+    /// The response of the Server List Ping.
+    Slp(Response),
+    /// The response of the Query protocol.
+    Query(FullStat),
+}
+
+/// Try the probes in order, and return the status from the first successful one.
+///
+/// It is useful to get the status of unknown servers, which may speak only some of the protocols.
+/// If every probe fails, the error of the last one is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{sync::{ping_any, Probe, ServerStatus}, transport::TcpTransport, Strategy};
+/// use std::time::Duration;
+///
+/// let mut transport = TcpTransport::new(Duration::from_secs(5));
+/// let probes = [
+///     Probe::Slp(Strategy::Modern),
+///     Probe::Slp(Strategy::Legacy16),
+///     Probe::Query(Duration::from_secs(5)),
+/// ];
+/// match ping_any(&mut transport, "my.server.com", 25565, &probes).unwrap() {
+///     ServerStatus::Slp(response) => println!("{} player(s) online", response.online_players),
+///     ServerStatus::Query(stat) => println!("{} player(s) online", stat.online_players),
+///     _ => {}
+/// }
+/// ```
+pub fn ping_any<T>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+    probes: &[Probe],
+) -> Result<ServerStatus>
+where
+    T: Transport,
+{
+    let config = PingConfig::default();
+    let mut last_error = Error::UnsupportedProtocol;
+    for &probe in probes {
+        let result = match probe {
+            Probe::Slp(strategy) => transport
+                .connect(hostname, port)
+                .and_then(|mut stream| {
+                    ping_strategy(&mut stream, hostname, port, strategy, &config)
+                })
+                .map(ServerStatus::Slp),
+            Probe::Query(timeout) => {
+                query_full_stat(hostname, port, timeout).map(ServerStatus::Query)
+            }
+        };
+        match result {
+            Ok(status) => return Ok(status),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+fn query_full_stat(hostname: &str, port: u16, timeout: Duration) -> Result<FullStat> {
+    let address = (hostname, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+    let local: SocketAddr = if address.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(address)?;
+    let retransmission = Retransmission {
+        timeout,
+        ..Default::default()
+    };
+    full_stat_with_retransmission(&socket, &retransmission)
+}
+
+/// The protocol numbers of well-known releases, for [`ping_with_protocol_retry`](ping_with_protocol_retry).
+///
+/// They are 1.21.4, 1.20.4, 1.16.5, 1.12.2 and 1.8.9, from the newest.
+pub const KNOWN_PROTOCOLS: [i32; 5] = [769, 765, 754, 340, 47];
+
+/// Ping the server with the config, and retry with each of the protocol numbers if it fails.
+///
+/// Some servers, notably certain proxies and plugins, reject the handshake advertising the protocol -1
+/// which [`PingConfig`](PingConfig) uses by default.
+/// Each attempt gets a fresh connection opened by the transport, since such servers close the connection.
+/// The legacy protocol is tried after every protocol number fails, if [`legacy_fallback`](PingConfig::legacy_fallback) is set.
+///
+/// If the transport fails to connect, the error is returned without further attempts.
+/// If the server drops a connection after answering an earlier attempt, as the connection throttles do,
+/// it is reconnected once after [`throttle_delay`](PingConfig::throttle_delay),
+/// and fails with [`Throttled`](Error::Throttled) if dropped again.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{sync::{ping_with_protocol_retry, KNOWN_PROTOCOLS}, transport::TcpTransport};
+/// use std::time::Duration;
+///
+/// let mut transport = TcpTransport::new(Duration::from_secs(5));
+/// let response = ping_with_protocol_retry(
+///     &mut transport,
+///     "my.server.com",
+///     25565,
+///     &Default::default(),
+///     &KNOWN_PROTOCOLS,
+/// )
+/// .unwrap();
+/// ```
+pub fn ping_with_protocol_retry<T>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+    protocols: &[i32],
+) -> Result<Response>
+where
+    T: Transport,
+{
+    let mut last_error = Error::UnsupportedProtocol;
+    let mut answered = false;
+    let versions = std::iter::once(config.protocol_version).chain(protocols.iter().copied());
+    for protocol_version in versions {
+        let attempt = PingConfig {
+            protocol_version,
+            legacy_fallback: false,
+            ..config.clone()
+        };
+        let result =
+            reconnect_throttled(transport, hostname, port, config, &mut answered, |stream| {
+                request_latest(stream, hostname, port, &attempt, &mut Vec::new())
+            });
+        match result {
+            Ok(response) => return Ok(response),
+            Err(Error::Throttled) => return Err(Error::Throttled),
+            Err(error) => last_error = error,
+        }
+    }
+    if config.legacy_fallback {
+        let request = build_legacy_request(hostname, port, config);
+        return reconnect_throttled(transport, hostname, port, config, &mut answered, |stream| {
+            request_legacy(stream, &request, &config.limits, &mut Vec::new())
+        });
+    }
+    Err(last_error)
+}
+
+// ping over a new connection, reconnecting once after the throttle delay
+// if the server drops it after answering an earlier one, as the connection throttles do
+fn reconnect_throttled<T, F>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+    answered: &mut bool,
+    mut ping: F,
+) -> Result<Response>
+where
+    T: Transport,
+    F: FnMut(&mut T::Stream) -> Result<Response>,
+{
+    let mut result = ping(&mut transport.connect(hostname, port)?);
+    if *answered && is_dropped(&result) {
+        let delay = config.throttle_delay.ok_or(Error::Throttled)?;
+        std::thread::sleep(delay);
+        result = ping(&mut transport.connect(hostname, port)?);
+        if is_dropped(&result) {
+            return Err(Error::Throttled);
+        }
+    }
+    // a server rejecting the request with a response is not throttling
+    *answered |= matches!(result, Ok(_) | Err(Error::UnsupportedProtocol));
+    result
+}
+
+// whether the server closed the connection instead of answering
+fn is_dropped<T>(result: &Result<T>) -> bool {
+    matches!(
+        result,
+        Err(Error::Io(error)) if matches!(
+            error.kind(),
+            std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::BrokenPipe
+                | std::io::ErrorKind::UnexpectedEof
+        )
+    )
+}
+
+/// Determine which of the protocol numbers the server accepts.
+///
+/// The server is pinged advertising each protocol number, on a new connection opened by the transport.
+/// A protocol number is accepted if the server reports the same number back.
+/// Vanilla servers always report their own protocol number, while the multi-version proxies
+/// (e.g. ViaVersion) report the client's one if they support it, so more than one number means such a proxy.
+///
+/// If the transport fails to connect, the error is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{sync::{probe_protocols, KNOWN_PROTOCOLS}, transport::TcpTransport};
+/// use std::time::Duration;
+///
+/// let mut transport = TcpTransport::new(Duration::from_secs(5));
+/// let accepted = probe_protocols(&mut transport, "my.server.com", 25565, &KNOWN_PROTOCOLS).unwrap();
+/// if accepted.len() > 1 {
+///     println!("The server supports multiple versions: {:?}", accepted);
+/// }
+/// ```
+pub fn probe_protocols<T>(
+    transport: &mut T,
+    hostname: &str,
+    port: u16,
+    protocols: &[i32],
+) -> Result<BTreeSet<i32>>
+where
+    T: Transport,
+{
+    let mut accepted = BTreeSet::new();
+    for &protocol_version in protocols {
+        let config = PingConfig {
+            protocol_version,
+            legacy_fallback: false,
+            ..Default::default()
+        };
+        let mut stream = transport.connect(hostname, port)?;
+        if let Ok(response) = request_latest(&mut stream, hostname, port, &config, &mut Vec::new())
+        {
+            if response.protocol == protocol_version {
+                accepted.insert(protocol_version);
+            }
+        }
+    }
+    Ok(accepted)
+}
+
+/// The ports commonly used by Java Edition servers, for [`sweep_ports`](sweep_ports).
+pub const COMMON_PORTS: [u16; 13] = [
+    25565, 25566, 25567, 25568, 25569, 25570, 25571, 25572, 25573, 25574, 25575, 25576, 25577,
+];
+
+/// Ping every port of the host concurrently, and return the ports which answered with their responses.
+///
+/// It is useful when only the hostname of a server running on a nonstandard port is known.
+/// Each port is pinged on its own thread with a clone of the transport,
+/// and the results are sorted by the port number.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{sync::{sweep_ports, COMMON_PORTS}, transport::TcpTransport};
+/// use std::time::Duration;
+///
+/// let transport = TcpTransport::new(Duration::from_secs(3));
+/// for (port, response) in sweep_ports(&transport, "my.server.com", &COMMON_PORTS) {
+///     println!("{}: {} player(s) online", port, response.online_players);
+/// }
+/// ```
+pub fn sweep_ports<T>(transport: &T, hostname: &str, ports: &[u16]) -> Vec<(u16, Response)>
+where
+    T: Transport + Clone + Send,
+{
+    let mut found: Vec<(u16, Response)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = ports
+            .iter()
+            .map(|&port| {
+                let mut transport = transport.clone();
+                scope.spawn(move || {
+                    let mut stream = transport.connect(hostname, port)?;
+                    ping(&mut stream, hostname, port).map(|response| (port, response))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().ok()?.ok())
+            .collect()
+    });
+    found.sort_by_key(|(port, _)| *port);
+    found
+}
+
+fn ping_strategy<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    strategy: Strategy,
+    config: &PingConfig,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    let limits = &config.limits;
+    match strategy {
+        Strategy::Modern => request_latest(stream, hostname, port, config, &mut Vec::new()),
+        Strategy::Legacy16 => request_legacy(
+            stream,
+            &build_legacy_request(hostname, port, config),
+            limits,
+            &mut Vec::new(),
+        ),
+        Strategy::Legacy14 => {
+            let mut buffer = Vec::new();
+            let response = exchange_legacy(stream, &LEGACY_14_REQUEST, limits, &mut buffer)?;
+            parse_legacy(&response, buffer)
+        }
+        Strategy::Beta => {
+            let mut buffer = Vec::new();
+            let response = exchange_legacy(stream, &BETA_REQUEST, limits, &mut buffer)?;
+            parse_beta(&response, buffer)
+        }
+    }
+}
+
+fn request_latest<Stream>(
+    stream: &mut Stream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+    buffer: &mut Vec<u8>,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    telemetry::observe("latest", || {
+        let limits = &config.limits;
+        let request = build_latest_request(hostname, port, config)?;
+        telemetry::stage(
+            "handshake",
+            stream.write_all(&request).and_then(|_| stream.flush()),
+        )?;
+
+        let _length = telemetry::stage("length", read_varint(stream))?;
+        let packet_id = telemetry::stage("packet_id", read_varint(stream))?;
+        let response_length = telemetry::stage("status_length", read_varint(stream))?;
+        if packet_id != 0x00
+            || response_length < 0
+            || response_length as usize > limits.max_response_size
+        {
+            telemetry::unexpected_status(packet_id, response_length);
+            return Err(Error::UnsupportedProtocol);
+        }
+        buffer.clear();
+        buffer.resize(response_length as usize, 0);
+        telemetry::stage("status", stream.read_exact(buffer))?;
+
+        let mut raw = telemetry::stage("decode", decode_latest_response(buffer, limits))?;
+        raw.raw_json = std::mem::take(buffer);
+        raw.try_into()
+    })
+}
+
+fn request_legacy<Stream>(
+    stream: &mut Stream,
+    request: &[u8],
+    limits: &Limits,
+    buffer: &mut Vec<u8>,
+) -> Result<Response>
+where
+    Stream: Read + Write,
+{
+    telemetry::observe("legacy", || {
+        let response = exchange_legacy(stream, request, limits, buffer)?;
+        parse_legacy(&response, std::mem::take(buffer))
+    })
+}
+
+// send a legacy request, and return the decoded response while leaving the raw response in the buffer
+fn exchange_legacy<Stream>(
+    stream: &mut Stream,
+    request: &[u8],
+    limits: &Limits,
+    buffer: &mut Vec<u8>,
+) -> Result<String>
+where
+    Stream: Read + Write,
+{
+    telemetry::stage(
+        "handshake",
+        stream.write_all(request).and_then(|_| stream.flush()),
+    )?;
+
+    // read as long as the header tells, since some servers keep the connection open
+    let mut header = [0u8; 3];
+    telemetry::stage("status", stream.read_exact(&mut header))?;
+    let length = legacy_body_length(header, limits)?;
+    buffer.clear();
+    buffer.extend_from_slice(&header);
+    buffer.resize(header.len() + length, 0);
+    telemetry::stage("status", stream.read_exact(&mut buffer[header.len()..]))?;
+
+    telemetry::stage("decode", decode_legacy(buffer))
+}
+
+fn measure_latency<Stream>(stream: &mut Stream) -> Result<Duration>
+where
+    Stream: Read + Write,
+{
+    let payload = ping_payload();
+    let start = Instant::now();
+    stream.write_all(&build_ping_request(payload))?;
+    stream.flush()?;
+
+    let length = read_varint(stream)?;
+    let packet_id = read_varint(stream)?;
+    let mut pong = [0u8; 8];
+    stream.read_exact(&mut pong)?;
+    if length != 9 || packet_id != 0x01 || i64::from_be_bytes(pong) != payload {
+        return Err(Error::UnsupportedProtocol);
+    }
+    Ok(start.elapsed())
+}
+
+pub(crate) fn read_varint(stream: &mut impl Read) -> Result<i32> {
     let mut buffer = [0u8];
     let mut result = 0;
     let mut read_count = 0u32;
@@ -88,6 +770,57 @@ fn read_varint(stream: &mut impl Read) -> Result<i32> {
     }
 }
 
+/// An extension trait to ping the server through the stream, as in `stream.slp_ping(hostname, port)`.
+///
+/// It is implemented for every stream, and the methods are the same as the functions of this module.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::sync::PingExt;
+/// use std::net::TcpStream;
+///
+/// let mut stream = TcpStream::connect(("my.server.com", 25565)).unwrap();
+/// let response = stream.slp_ping("my.server.com", 25565).unwrap();
+/// ```
+pub trait PingExt {
+    /// Send a ping request to the server and wait for the response.
+    /// See also [`ping`](ping).
+    fn slp_ping(&mut self, hostname: &str, port: u16) -> Result<Response>;
+
+    /// Send a ping request to the server with the options and wait for the response.
+    /// See also [`ping_with_config`](ping_with_config).
+    fn slp_ping_with_config(
+        &mut self,
+        hostname: &str,
+        port: u16,
+        config: &PingConfig,
+    ) -> Result<Response>;
+
+    /// Send a ping request of the legacy protocol (1.6) to the server and wait for the response.
+    /// See also [`ping_legacy`](ping_legacy).
+    fn slp_ping_legacy(&mut self, hostname: &str, port: u16) -> Result<Response>;
+}
+
+impl<Stream: Read + Write> PingExt for Stream {
+    fn slp_ping(&mut self, hostname: &str, port: u16) -> Result<Response> {
+        ping(self, hostname, port)
+    }
+
+    fn slp_ping_with_config(
+        &mut self,
+        hostname: &str,
+        port: u16,
+        config: &PingConfig,
+    ) -> Result<Response> {
+        ping_with_config(self, hostname, port, config)
+    }
+
+    fn slp_ping_legacy(&mut self, hostname: &str, port: u16) -> Result<Response> {
+        ping_legacy(self, hostname, port)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -107,4 +840,134 @@ mod test {
             buffer = reader.into_inner();
         }
     }
+
+    #[test]
+    fn handshake_with_config() {
+        let config = PingConfig {
+            protocol_version: 47,
+            virtual_host: Some("a.b".to_string()),
+            ..Default::default()
+        };
+        let request = build_latest_request("127.0.0.1", 25565, &config).unwrap();
+        assert_eq!(
+            request,
+            [9, 0x00, 47, 3, b'a', b'.', b'b', 0x63, 0xdd, 0x01, 1, 0x00]
+        );
+
+        let config = PingConfig {
+            forge_marker: Some(crate::ForgeMarker::Fml2),
+            ..config
+        };
+        let request = build_latest_request("127.0.0.1", 25565, &config).unwrap();
+        assert_eq!(&request[3..13], b"\x09a.b\0FML2\0");
+    }
+
+    #[test]
+    fn parse_beta_response() {
+        let response = parse_beta("A \u{00a7}cServer\u{00a7}3\u{00a7}20", vec![]).unwrap();
+        assert_eq!(response.description.text, "A \u{00a7}cServer");
+        assert_eq!(response.online_players, 3);
+        assert_eq!(response.max_players, 20);
+    }
+
+    // a server which answers the legacy ping, and never closes the connection
+    struct KeepAlive(Cursor<Vec<u8>>);
+
+    impl Read for KeepAlive {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            // a server keeping the connection open times out instead of ending the stream
+            if self.0.position() >= self.0.get_ref().len() as u64 {
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for KeepAlive {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn read_legacy_by_length() {
+        let status = ["\u{a7}1", "78", "1.6.4", "A Minecraft Server", "0", "20"].join("\0");
+        let mut packet = vec![0xff];
+        packet.extend_from_slice(&(status.encode_utf16().count() as u16).to_be_bytes());
+        packet.extend(status.encode_utf16().flat_map(u16::to_be_bytes));
+
+        let response = ping_legacy(
+            &mut KeepAlive(Cursor::new(packet.clone())),
+            "localhost",
+            25565,
+        )
+        .unwrap();
+        assert_eq!(response.version, "1.6.4");
+        packet.truncate(packet.len() - 1);
+        assert!(ping_legacy(&mut KeepAlive(Cursor::new(packet)), "localhost", 25565).is_err());
+    }
+    // a server answering each connection with the reply, or dropping it without one
+    struct Scripted(Option<Cursor<Vec<u8>>>);
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match &mut self.0 {
+                Some(reply) => reply.read(buf),
+                None => Err(std::io::ErrorKind::ConnectionReset.into()),
+            }
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reconnect_when_throttled() {
+        let json = br#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":0},"description":"Hi"}"#;
+        let mut data = vec![0x00];
+        write_varint(&mut data, json.len() as i32);
+        data.extend_from_slice(json);
+        let mut status = vec![];
+        write_varint(&mut status, data.len() as i32);
+        status.extend_from_slice(&data);
+        // rejects the protocol -1 with another packet, then throttles the next connection
+        let replies = |throttled: usize| {
+            let mut replies = vec![Some(vec![2, 0x01, 0])];
+            replies.extend(std::iter::repeat_n(None, throttled));
+            replies.push(Some(status.clone()));
+            replies.into_iter()
+        };
+        let ping = |replies: &mut dyn Iterator<Item = Option<Vec<u8>>>, throttle_delay| {
+            let config = PingConfig {
+                throttle_delay,
+                legacy_fallback: false,
+                ..Default::default()
+            };
+            let mut transport = |_: &str, _: u16| {
+                let reply = replies.next().expect("no more connections");
+                Ok::<_, Error>(Scripted(reply.map(Cursor::new)))
+            };
+            ping_with_protocol_retry(&mut transport, "localhost", 25565, &config, &[765])
+        };
+
+        let delay = Some(Duration::from_millis(10));
+        let response = ping(&mut replies(1), delay).unwrap();
+        assert_eq!(response.version, "1.20.4");
+        assert!(matches!(
+            ping(&mut replies(2), delay),
+            Err(Error::Throttled)
+        ));
+        assert!(matches!(ping(&mut replies(1), None), Err(Error::Throttled)));
+    }
 }
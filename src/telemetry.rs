@@ -0,0 +1,163 @@
+// reports every request to the telemetry facades enabled by the features
+use core::fmt::Display;
+#[cfg(any(feature = "async-tokio", feature = "async-futures"))]
+use core::future::Future;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+use crate::{Error, Response, Result};
+
+// run a blocking request of the protocol, `latest` or `legacy`, and report its result
+#[cfg(feature = "sync")]
+pub(crate) fn observe(
+    protocol: &'static str,
+    request: impl FnOnce() -> Result<Response>,
+) -> Result<Response> {
+    #[cfg(feature = "tracing")]
+    let _span = ::tracing::debug_span!("request", protocol).entered();
+    #[cfg(feature = "metrics")]
+    let started = Instant::now();
+    let result = request();
+    #[cfg(feature = "metrics")]
+    metrics::record(protocol, started, &result);
+    finish(protocol, &result);
+    result
+}
+
+// run an asynchronous request of the protocol, `latest` or `legacy`, and report its result
+#[cfg(any(feature = "async-tokio", feature = "async-futures"))]
+pub(crate) async fn observe_async(
+    protocol: &'static str,
+    request: impl Future<Output = Result<Response>>,
+) -> Result<Response> {
+    #[cfg(feature = "tracing")]
+    let request =
+        ::tracing::Instrument::instrument(request, ::tracing::debug_span!("request", protocol));
+    #[cfg(feature = "metrics")]
+    let started = Instant::now();
+    let result = request.await;
+    #[cfg(feature = "metrics")]
+    metrics::record(protocol, started, &result);
+    finish(protocol, &result);
+    result
+}
+
+#[cfg_attr(
+    not(any(feature = "tracing", feature = "log")),
+    allow(unused_variables)
+)]
+fn finish(protocol: &'static str, result: &Result<Response>) {
+    #[cfg(feature = "tracing")]
+    match result {
+        Ok(response) => {
+            ::tracing::debug!(protocol, bytes = response.raw().len(), "request succeeded")
+        }
+        Err(error) => ::tracing::debug!(protocol, %error, "request failed"),
+    }
+    #[cfg(feature = "log")]
+    match result {
+        Ok(response) => ::log::debug!(
+            "{} request succeeded with {} bytes",
+            protocol,
+            response.raw().len()
+        ),
+        Err(error) => ::log::debug!("{} request failed: {}", protocol, error),
+    }
+}
+
+// report the result of a stage of a request, e.g. `handshake`, `status` or `decode`
+#[cfg_attr(
+    not(any(feature = "tracing", feature = "log")),
+    allow(unused_variables)
+)]
+pub(crate) fn stage<T, E: Display>(
+    name: &'static str,
+    result: core::result::Result<T, E>,
+) -> core::result::Result<T, E> {
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok(_) => ::tracing::trace!(stage = name, "stage finished"),
+        Err(error) => ::tracing::debug!(stage = name, %error, "stage failed"),
+    }
+    #[cfg(feature = "log")]
+    match &result {
+        Ok(_) => ::log::trace!("{} stage finished", name),
+        Err(error) => ::log::debug!("{} stage failed: {}", name, error),
+    }
+    result
+}
+
+// report the header of a status response which is not recognized
+#[cfg_attr(
+    not(any(feature = "tracing", feature = "log")),
+    allow(unused_variables)
+)]
+pub(crate) fn unexpected_status(packet_id: i32, length: i32) {
+    #[cfg(feature = "tracing")]
+    ::tracing::debug!(packet_id, length, "unexpected status response header");
+    #[cfg(feature = "log")]
+    ::log::debug!(
+        "unexpected status response header: packet id {}, length {}",
+        packet_id,
+        length
+    );
+}
+
+// report that the legacy protocol is tried after the current protocol failed
+#[cfg_attr(
+    not(any(feature = "tracing", feature = "log")),
+    allow(unused_variables)
+)]
+pub(crate) fn fallback(error: &Error) {
+    #[cfg(feature = "tracing")]
+    ::tracing::debug!(%error, "falling back to the legacy protocol");
+    #[cfg(feature = "log")]
+    ::log::debug!("falling back to the legacy protocol: {}", error);
+}
+
+#[cfg(feature = "metrics")]
+mod metrics {
+    use std::{io, time::Instant};
+
+    use ::metrics::{counter, histogram};
+
+    use crate::{Error, Response, Result};
+
+    pub(super) fn record(protocol: &'static str, started: Instant, result: &Result<Response>) {
+        counter!("craftping_requests_total", "protocol" => protocol).increment(1);
+        histogram!("craftping_request_duration_seconds", "protocol" => protocol)
+            .record(started.elapsed().as_secs_f64());
+        match result {
+            Ok(response) => {
+                counter!("craftping_response_bytes_total", "protocol" => protocol)
+                    .increment(response.raw().len() as u64);
+            }
+            Err(error) => {
+                counter!(
+                    "craftping_request_failures_total",
+                    "protocol" => protocol,
+                    "kind" => failure_kind(error),
+                )
+                .increment(1);
+            }
+        }
+    }
+
+    fn failure_kind(error: &Error) -> &'static str {
+        match error {
+            Error::Io(error) => match error.kind() {
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => "timed_out",
+                io::ErrorKind::ConnectionRefused => "connection_refused",
+                io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted => {
+                    "connection_reset"
+                }
+                io::ErrorKind::UnexpectedEof => "unexpected_eof",
+                _ => "io",
+            },
+            #[cfg(feature = "embedded-io")]
+            Error::EmbeddedIo(_) => "io",
+            Error::UnsupportedProtocol => "unsupported_protocol",
+            Error::Throttled => "throttled",
+        }
+    }
+}
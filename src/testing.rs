@@ -0,0 +1,207 @@
+//! Provides [`MockServer`](MockServer) to test the code pinging servers without real ones.
+//!
+//! The [`MockServer`](MockServer) listens on a local port, and answers every connection with the [`Reply`](Reply),
+//! including the malformed, the slow and the truncated ones.
+//!
+//! # Examples
+//!
+//! ```
+//! use craftping::{sync::ping, testing::{MockServer, Reply}, Chat, Response};
+//! use std::net::TcpStream;
+//!
+//! let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+//! let server = MockServer::start(Reply::status(response)).unwrap();
+//! let mut stream = TcpStream::connect(server.address()).unwrap();
+//! let response = ping(&mut stream, "localhost", server.address().port()).unwrap();
+//! assert_eq!(response.version, "1.20.4");
+//! ```
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{
+    server::{build_status, build_status_packet, read_handshake, respond},
+    Response,
+};
+
+// long enough for the clients to send the rest of the request
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+/// The way [`MockServer`](MockServer) answers a connection.
+pub enum Reply {
+    /// Answer the ping request with the response, in the format of the protocol the client used.
+    /// See also [`respond`](crate::server::respond).
+    Status(Box<Response>),
+    /// Answer the ping request of the current protocol with the bytes as the status JSON,
+    /// which need not be a valid JSON.
+    Json(Vec<u8>),
+    /// Read the request, and send the bytes as is before closing the connection.
+    Raw(Vec<u8>),
+    /// Wait for the duration, then answer with the reply.
+    Delayed(Duration, Box<Reply>),
+    /// Read the request, and never answer until the client closes the connection.
+    Silent,
+}
+
+impl Reply {
+    /// Answer the ping request with the response. See also [`Reply::Status`](Reply::Status).
+    pub fn status(response: Response) -> Reply {
+        Reply::Status(Box::new(response))
+    }
+
+    /// Answer the ping request of the current protocol with the response, cut after the first `length` bytes.
+    pub fn truncated(response: &Response, length: usize) -> Reply {
+        let mut packet = build_status(response);
+        packet.truncate(length);
+        Reply::Raw(packet)
+    }
+
+    fn answer(&self, stream: &mut TcpStream) -> crate::Result<()> {
+        match self {
+            Reply::Status(response) => {
+                respond(stream, response)?;
+            }
+            Reply::Json(json) => {
+                read_handshake(stream)?;
+                stream.write_all(&build_status_packet(json))?;
+            }
+            Reply::Raw(bytes) => {
+                drain(stream)?;
+                stream.write_all(bytes)?;
+            }
+            Reply::Delayed(delay, reply) => {
+                std::thread::sleep(*delay);
+                reply.answer(stream)?;
+            }
+            Reply::Silent => {
+                stream.set_read_timeout(None)?;
+                io::copy(stream, &mut io::sink())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// read whatever the client sent until it waits for the answer
+fn drain(stream: &mut TcpStream) -> io::Result<()> {
+    let mut buffer = [0u8; 1024];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(())
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A server listening on a local port, which answers every connection with the [`Reply`](Reply).
+///
+/// Each connection is answered in its own thread. Dropping it stops the server.
+pub struct MockServer {
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Start a server on a free port of the loopback address.
+    pub fn start(reply: Reply) -> io::Result<MockServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let address = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handle = std::thread::spawn({
+            let stopped = stopped.clone();
+            move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::Acquire) {
+                        break;
+                    }
+                    let Ok(mut stream) = stream else {
+                        continue;
+                    };
+                    let reply = reply.clone();
+                    std::thread::spawn(move || {
+                        // the legacy clients of 1.5 and older are told apart by the timeout
+                        if stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_ok() {
+                            let _ = reply.answer(&mut stream);
+                        }
+                    });
+                }
+            }
+        });
+        Ok(MockServer {
+            address,
+            stopped,
+            handle: Some(handle),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        // wake up the listener blocked on accepting
+        let _ = TcpStream::connect(self.address);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{sync::ping_with_config, Chat, Error, PingConfig};
+
+    fn ping(server: &MockServer) -> crate::Result<Response> {
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let config = PingConfig {
+            legacy_fallback: false,
+            ..Default::default()
+        };
+        ping_with_config(&mut stream, "localhost", server.address().port(), &config)
+    }
+
+    #[test]
+    fn reply_canned_responses() {
+        let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+
+        let server = MockServer::start(Reply::status(response.clone())).unwrap();
+        assert_eq!(ping(&server).unwrap().version, "1.20.4");
+
+        let server = MockServer::start(Reply::Json(b"{".to_vec())).unwrap();
+        assert!(matches!(ping(&server), Err(Error::UnsupportedProtocol)));
+
+        let server = MockServer::start(Reply::truncated(&response, 20)).unwrap();
+        assert!(matches!(ping(&server), Err(Error::Io(_))));
+
+        let server = MockServer::start(Reply::Silent).unwrap();
+        assert!(matches!(ping(&server), Err(Error::Io(_))));
+    }
+}
@@ -2,11 +2,32 @@
 //!
 //! The [`ping`](ping) function here sends a ping request, and returns a [`Future`](std::future::Future) resolves to a result of [`Response`](Response).
 //! If you want to send ping synchronously, see [`sync`](sync) module.
-use std::convert::TryInto;
+//!
+//! The functions take the stream by value and pin it while pinging, so a stream which is not [`Unpin`](Unpin)
+//! can be passed as is, and a mutable reference like `&mut stream` works for the other streams.
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    pin::{pin, Pin},
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
-use ::tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use ::tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::Semaphore,
+    task::spawn,
+    time::{sleep, Sleep},
+};
 
-use crate::*;
+use crate::{
+    async_transport::{self, request_latest, request_legacy, TokioIo},
+    pinger::Pinger,
+    *,
+};
 
 /// Send a ping request to the server and return a future response.
 ///
@@ -30,78 +51,612 @@ use crate::*;
 /// );
 /// # }
 /// ```
-pub async fn ping<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+pub async fn ping<Stream>(stream: Stream, hostname: &str, port: u16) -> Result<Response>
 where
-    Stream: AsyncRead + AsyncWrite + Unpin,
+    Stream: AsyncRead + AsyncWrite,
 {
-    match ping_latest(stream, hostname, port).await {
-        ok @ Ok(_) => ok,
-        Err(_) => ping_legacy(stream).await,
-    }
+    ping_with_limits(stream, hostname, port, &Limits::default()).await
+}
+
+/// Send a ping request to the server and return a future response,
+/// rejecting the response if it exceeds the limits.
+///
+/// [`ping`](ping) uses the default [`Limits`](Limits).
+pub async fn ping_with_limits<Stream>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+    limits: &Limits,
+) -> Result<Response>
+where
+    Stream: AsyncRead + AsyncWrite,
+{
+    ping_with_config(stream, hostname, port, &PingConfig::from(*limits)).await
 }
 
-async fn ping_latest<Stream>(stream: &mut Stream, hostname: &str, port: u16) -> Result<Response>
+/// Send a ping request to the server with the options and return a future response.
+///
+/// See also [`PingConfig`](PingConfig).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{tokio::ping_with_config, PingConfig};
+/// use tokio::net::TcpStream;
+///
+/// # async fn run() {
+/// let mut stream = TcpStream::connect(("127.0.0.1", 25565)).await.unwrap();
+/// let config = PingConfig {
+///     virtual_host: Some("my.server.com".to_string()),
+///     legacy_fallback: false,
+///     ..Default::default()
+/// };
+/// let response = ping_with_config(&mut stream, "127.0.0.1", 25565, &config).await.unwrap();
+/// # }
+/// ```
+pub async fn ping_with_config<Stream>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response>
 where
-    Stream: AsyncRead + AsyncWrite + Unpin,
+    Stream: AsyncRead + AsyncWrite,
 {
-    let request = build_latest_request(hostname, port)?;
-    stream.write_all(&request).await?;
-    stream.flush().await?;
+    async_transport::ping_with_config(&mut TokioIo(pin!(stream)), hostname, port, config).await
+}
 
-    let _length = read_varint(stream).await?;
-    let packet_id = read_varint(stream).await?;
-    let response_length = read_varint(stream).await?;
-    if packet_id != 0x00 || response_length < 0 {
-        return Err(Error::UnsupportedProtocol);
+/// Send a ping request to the server and return a future response,
+/// failing with [`TimedOut`](io::ErrorKind::TimedOut) if the whole exchange does not finish in time.
+///
+/// Unlike the read and write timeouts, the timeout bounds the exchange as a whole,
+/// including the legacy ping after the modern one fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::tokio::ping_with_timeout;
+/// use std::time::Duration;
+/// use tokio::net::TcpStream;
+///
+/// # async fn run() {
+/// let mut stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+/// let response = ping_with_timeout(&mut stream, "my.server.com", 25565, Duration::from_secs(5))
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub async fn ping_with_timeout<Stream>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<Response>
+where
+    Stream: AsyncRead + AsyncWrite,
+{
+    let config = PingConfig::default();
+    match ::tokio::time::timeout(timeout, ping_with_config(stream, hostname, port, &config)).await {
+        Ok(result) => result,
+        Err(_) => Err(Error::Io(io::ErrorKind::TimedOut.into())),
     }
-    let mut response_buffer = vec![0; response_length as usize];
-    stream.read_exact(&mut response_buffer).await?;
+}
 
-    let mut raw = decode_latest_response(&response_buffer)?;
-    raw.raw_json = response_buffer;
-    raw.try_into()
+/// Send a ping request of the current protocol (1.7 and later) to the server and return a future response.
+///
+/// Unlike [`ping`](ping), it does not fall back to the legacy protocol,
+/// so the error of the current protocol is returned as is.
+pub async fn ping_latest<Stream>(stream: Stream, hostname: &str, port: u16) -> Result<Response>
+where
+    Stream: AsyncRead + AsyncWrite,
+{
+    let mut stream = TokioIo(pin!(stream));
+    request_latest(
+        &mut stream,
+        hostname,
+        port,
+        &PingConfig::default(),
+        &mut Vec::new(),
+    )
+    .await
 }
 
-async fn ping_legacy<Stream>(stream: &mut Stream) -> Result<Response>
+/// Send a ping request of the legacy protocol (1.6) to the server and return a future response.
+///
+/// Servers before 1.7 only understand this protocol.
+/// Most of the later servers also answer it, without the fields added since 1.7.
+///
+/// `hostname` and `port` are sent to the server as the 1.6 client does. Some proxies reject the request without them.
+pub async fn ping_legacy<Stream>(stream: Stream, hostname: &str, port: u16) -> Result<Response>
 where
-    Stream: AsyncRead + AsyncWrite + Unpin,
+    Stream: AsyncRead + AsyncWrite,
 {
-    stream.write_all(&LEGACY_REQUEST).await?;
-    stream.flush().await?;
+    let mut stream = TokioIo(pin!(stream));
+    request_legacy(
+        &mut stream,
+        &build_legacy_request(hostname, port, &PingConfig::default()),
+        &Limits::default(),
+        &mut Vec::new(),
+    )
+    .await
+}
 
-    let mut buffer = Vec::new();
-    stream.read_to_end(&mut buffer).await?;
+/// Send a ping request to the server and return a future response,
+/// reusing the memory of the raw response received by the previous ping with the same `buf`.
+///
+/// It is useful when the same server is pinged repeatedly.
+/// The response is stored in `buf` until the next ping with it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{tokio::ping_into, ResponseBuf};
+/// use tokio::net::TcpStream;
+///
+/// # async fn run() {
+/// let mut buf = ResponseBuf::new();
+/// for _ in 0..10 {
+///     let mut stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+///     let response = ping_into(&mut stream, "my.server.com", 25565, &mut buf).await.unwrap();
+///     println!("Players online: {}", response.online_players);
+/// }
+/// # }
+/// ```
+pub async fn ping_into<'a, Stream>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+    buf: &'a mut ResponseBuf,
+) -> Result<&'a Response>
+where
+    Stream: AsyncRead + AsyncWrite,
+{
+    async_transport::ping_into(&mut TokioIo(pin!(stream)), hostname, port, buf).await
+}
 
-    let response = decode_legacy(&buffer)?;
-    parse_legacy(&response, buffer)
+/// Send a ping request to the server, and measure the round-trip time with the ping packet after the response.
+///
+/// The latency is measured the same way as the vanilla client does.
+/// For the legacy servers, which do not support the ping packet, the time taken by the legacy request is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::tokio::ping_with_latency;
+/// use tokio::net::TcpStream;
+///
+/// # async fn run() {
+/// let mut stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+/// let (response, latency) = ping_with_latency(&mut stream, "my.server.com", 25565).await.unwrap();
+/// println!("{} player(s) online, {:?}", response.online_players, latency);
+/// # }
+/// ```
+pub async fn ping_with_latency<Stream>(
+    stream: Stream,
+    hostname: &str,
+    port: u16,
+) -> Result<(Response, Duration)>
+where
+    Stream: AsyncRead + AsyncWrite,
+{
+    async_transport::ping_with_latency(&mut TokioIo(pin!(stream)), hostname, port).await
 }
 
-async fn read_varint<Stream>(stream: &mut Stream) -> Result<i32>
+/// Connect to the servers and ping them concurrently, running at most `concurrency_limit` pings at once.
+///
+/// Each target is pinged with [`Pinger::ping_tokio`](Pinger::ping_tokio) on its own task,
+/// so the timeouts of the pinger apply, and a failed target does not affect the others.
+/// The results are returned in the order of the targets.
+/// The pings of the same server are started 4 seconds apart, as the connection throttles of Spigot and BungeeCord
+/// drop the connections made sooner, and each waits for its turn in its slot of the concurrency limit.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{pinger::Pinger, tokio::ping_many};
+/// use std::time::Duration;
+///
+/// # async fn run() {
+/// let servers = [("my.server.com", 25565), ("other.server.com", 25565)];
+/// let targets = servers.iter().map(|&(hostname, port)| {
+///     Pinger::new(hostname, port)
+///         .connect_timeout(Duration::from_secs(3))
+///         .read_timeout(Duration::from_secs(5))
+/// });
+/// for ((hostname, _), result) in servers.iter().zip(ping_many(targets, 16).await) {
+///     match result {
+///         Ok(response) => println!("{}: {} player(s) online", hostname, response.online_players),
+///         Err(error) => println!("{}: {}", hostname, error),
+///     }
+/// }
+/// # }
+/// ```
+pub async fn ping_many<I>(targets: I, concurrency_limit: usize) -> Vec<Result<Response>>
+where
+    I: IntoIterator<Item = Pinger>,
+{
+    schedule_many(targets, concurrency_limit, None).await
+}
+
+/// Connect to the servers and ping them concurrently like [`ping_many`](ping_many),
+/// starting the pings no faster than the rate limit.
+///
+/// The targets are resolved on their own tasks, so [`RateLimit::per_subnet`](RateLimit::per_subnet) does not apply here.
+/// To limit the rate per network, see [`Scanner::rate_limit`](crate::scan::Scanner::rate_limit).
+pub async fn ping_many_with_rate_limit<I>(
+    targets: I,
+    concurrency_limit: usize,
+    rate_limit: RateLimit,
+) -> Vec<Result<Response>>
 where
-    Stream: AsyncRead + Unpin,
+    I: IntoIterator<Item = Pinger>,
 {
-    let mut buffer = [0u8];
-    let mut result = 0;
-    let mut read_count = 0u32;
-    loop {
-        stream.read_exact(&mut buffer).await?;
-        result |= (buffer[0] as i32 & LAST_SEVEN_BITS)
-            .checked_shl(7 * read_count)
-            .ok_or(Error::UnsupportedProtocol)?;
-
-        read_count += 1;
-        if read_count > 5 {
-            break Err(Error::UnsupportedProtocol);
-        } else if (buffer[0] & NEXT_BYTE_EXISTS) == 0 {
-            break Ok(result);
+    schedule_many(
+        targets,
+        concurrency_limit,
+        Some(RateLimiter::new(rate_limit)),
+    )
+    .await
+}
+
+async fn schedule_many<I>(
+    targets: I,
+    concurrency_limit: usize,
+    mut limiter: Option<RateLimiter>,
+) -> Vec<Result<Response>>
+where
+    I: IntoIterator<Item = Pinger>,
+{
+    let semaphore = Arc::new(Semaphore::new(concurrency_limit.max(1)));
+    let mut handles = Vec::new();
+    let mut last_starts = HashMap::new();
+    for pinger in targets {
+        // the semaphore is never closed
+        let permit = semaphore.clone().acquire_owned().await.ok();
+        if let Some(limiter) = &mut limiter {
+            limiter.acquire(None).await;
+        }
+        // the connection throttles drop the pings of the same server started too soon after another
+        let now = Instant::now();
+        let start = match last_starts.get(&(pinger.hostname().to_string(), pinger.port())) {
+            Some(&last) => now.max(last + THROTTLE_DELAY),
+            None => now,
+        };
+        last_starts.insert((pinger.hostname().to_string(), pinger.port()), start);
+        handles.push(spawn(async move {
+            let _permit = permit;
+            ::tokio::time::sleep_until(start.into()).await;
+            pinger.ping_tokio().await
+        }));
+    }
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(match handle.await {
+            Ok(result) => result,
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(_) => Err(io::Error::from(io::ErrorKind::Interrupted).into()),
+        });
+    }
+    results
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The rate of starting pings, to avoid tripping the abuse detection or saturating the uplink.
+///
+/// A rate of 0 is treated as 1.
+pub struct RateLimit {
+    /// The maximum number of pings started per second.
+    pub per_second: u32,
+    /// The maximum number of pings started per second to the same /24 network (/64 for IPv6).
+    /// `None` to limit the total rate only.
+    pub per_subnet: Option<u32>,
+}
+
+impl RateLimit {
+    /// Create a rate limit of the pings per second, without a limit per network.
+    pub fn new(per_second: u32) -> Self {
+        Self {
+            per_second,
+            per_subnet: None,
+        }
+    }
+}
+
+// a token bucket, which holds up to a second of the tokens
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32) -> Self {
+        let rate = rate.max(1) as f64;
+        Self {
+            rate,
+            tokens: rate,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+    }
+
+    // take a token, and return how long to wait until it is refilled
+    fn take(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= 1.0;
+        match self.tokens < 0.0 {
+            true => Duration::from_secs_f64(-self.tokens / self.rate),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+// the buckets of the networks are dropped when they are full, after this many networks
+const MAX_IDLE_SUBNETS: usize = 4096;
+
+// paces the start of the pings, shared by the schedulers of the concurrent pings
+pub(crate) struct RateLimiter {
+    total: TokenBucket,
+    per_subnet: Option<u32>,
+    subnets: HashMap<IpAddr, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            total: TokenBucket::new(limit.per_second),
+            per_subnet: limit.per_subnet,
+            subnets: HashMap::new(),
+        }
+    }
+
+    // wait until a ping to the address can be started
+    pub async fn acquire(&mut self, address: Option<IpAddr>) {
+        let now = Instant::now();
+        let mut wait = self.total.take(now);
+        if let (Some(rate), Some(address)) = (self.per_subnet, address) {
+            if self.subnets.len() > MAX_IDLE_SUBNETS {
+                self.subnets.retain(|_, bucket| {
+                    bucket.refill(now);
+                    bucket.tokens < bucket.rate
+                });
+            }
+            let subnet = match address {
+                IpAddr::V4(address) => IpAddr::V4(Ipv4Addr::from(u32::from(address) & !0xff)),
+                IpAddr::V6(address) => {
+                    IpAddr::V6(Ipv6Addr::from(u128::from(address) & !(u64::MAX as u128)))
+                }
+            };
+            let bucket = self
+                .subnets
+                .entry(subnet)
+                .or_insert_with(|| TokenBucket::new(rate));
+            wait = wait.max(bucket.take(now));
+        }
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A stream failing the reads and writes which make no progress for the timeouts, with [`TimedOut`](io::ErrorKind::TimedOut).
+///
+/// Tokio streams have no read and write timeouts unlike [`std::net::TcpStream`](std::net::TcpStream), so they are enforced around the stream.
+/// The timer restarts whenever some bytes arrive, so it fails a server stalling in the middle of the response
+/// while letting a slow but steady one finish. Bound the whole ping with [`ping_with_timeout`](ping_with_timeout)
+/// as well to stop the servers trickling the bytes.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::tokio::{ping_with_timeout, TimeoutStream};
+/// use std::time::Duration;
+/// use tokio::net::TcpStream;
+///
+/// # async fn run() {
+/// let stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+/// let inactivity = Some(Duration::from_secs(3));
+/// let mut stream = TimeoutStream::new(stream, inactivity, inactivity);
+/// let response = ping_with_timeout(&mut stream, "my.server.com", 25565, Duration::from_secs(10))
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub struct TimeoutStream<S> {
+    stream: S,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    // the timer of the pending read or write, started when it first returned pending
+    read_timer: Option<Pin<Box<Sleep>>>,
+    write_timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> TimeoutStream<S> {
+    /// Wrap the stream with the timeouts of each read and write. `None` waits forever.
+    pub fn new(stream: S, read_timeout: Option<Duration>, write_timeout: Option<Duration>) -> Self {
+        Self {
+            stream,
+            read_timeout,
+            write_timeout,
+            read_timer: None,
+            write_timer: None,
+        }
+    }
+
+    /// Return the wrapped stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+// poll the operation, failing with TimedOut if it stays pending longer than the timeout
+fn poll_timeout<T>(
+    cx: &mut Context<'_>,
+    timeout: Option<Duration>,
+    timer: &mut Option<Pin<Box<Sleep>>>,
+    poll: Poll<io::Result<T>>,
+) -> Poll<io::Result<T>> {
+    match (poll, timeout) {
+        (Poll::Ready(result), _) => {
+            *timer = None;
+            Poll::Ready(result)
+        }
+        (Poll::Pending, None) => Poll::Pending,
+        (Poll::Pending, Some(timeout)) => {
+            let sleep = timer.get_or_insert_with(|| Box::pin(sleep(timeout)));
+            match sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    *timer = None;
+                    Poll::Ready(Err(io::ErrorKind::TimedOut.into()))
+                }
+                Poll::Pending => Poll::Pending,
+            }
         }
     }
 }
 
+impl<S: AsyncRead + Unpin> AsyncRead for TimeoutStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_read(cx, buf);
+        poll_timeout(cx, this.read_timeout, &mut this.read_timer, poll)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TimeoutStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_write(cx, buf);
+        poll_timeout(cx, this.write_timeout, &mut this.write_timer, poll)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_flush(cx);
+        poll_timeout(cx, this.write_timeout, &mut this.write_timer, poll)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+/// An extension trait to ping the server through the stream, as in `stream.slp_ping(hostname, port).await`.
+///
+/// It is implemented for every [`Unpin`](Unpin) stream, and the methods are the same as the functions of this module.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::tokio::PingExt;
+/// use std::time::Duration;
+/// use tokio::net::TcpStream;
+///
+/// # async fn run() {
+/// let mut stream = TcpStream::connect(("my.server.com", 25565)).await.unwrap();
+/// let response = stream
+///     .slp_ping_with_timeout("my.server.com", 25565, Duration::from_secs(5))
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub trait PingExt {
+    /// Send a ping request to the server and return a future response.
+    /// See also [`ping`](ping).
+    fn slp_ping<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+    ) -> impl Future<Output = Result<Response>> + 'a;
+
+    /// Send a ping request to the server with the options and return a future response.
+    /// See also [`ping_with_config`](ping_with_config).
+    fn slp_ping_with_config<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+        config: &'a PingConfig,
+    ) -> impl Future<Output = Result<Response>> + 'a;
+
+    /// Send a ping request to the server and return a future response, bounding the whole exchange by the timeout.
+    /// See also [`ping_with_timeout`](ping_with_timeout).
+    fn slp_ping_with_timeout<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Response>> + 'a;
+
+    /// Send a ping request of the legacy protocol (1.6) to the server and return a future response.
+    /// See also [`ping_legacy`](ping_legacy).
+    fn slp_ping_legacy<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+    ) -> impl Future<Output = Result<Response>> + 'a;
+}
+
+impl<Stream: AsyncRead + AsyncWrite + Unpin> PingExt for Stream {
+    fn slp_ping<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+    ) -> impl Future<Output = Result<Response>> + 'a {
+        ping(self, hostname, port)
+    }
+
+    fn slp_ping_with_config<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+        config: &'a PingConfig,
+    ) -> impl Future<Output = Result<Response>> + 'a {
+        ping_with_config(self, hostname, port, config)
+    }
+
+    fn slp_ping_with_timeout<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Response>> + 'a {
+        ping_with_timeout(self, hostname, port, timeout)
+    }
+
+    fn slp_ping_legacy<'a>(
+        &'a mut self,
+        hostname: &'a str,
+        port: u16,
+    ) -> impl Future<Output = Result<Response>> + 'a {
+        ping_legacy(self, hostname, port)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use crate::async_transport::read_varint;
+
     use std::io::Cursor;
 
     #[test]
@@ -114,13 +669,65 @@ mod test {
         for &i in samples.iter() {
             buffer.clear();
             write_varint(&mut buffer, i);
-            let mut reader = Cursor::new(buffer);
-            let task = read_varint(&mut reader);
+            let mut transport = TokioIo(Cursor::new(buffer));
+            let task = read_varint(&mut transport);
 
             let deserialized = runtime.block_on(task).unwrap();
 
             assert_eq!(i, deserialized);
-            buffer = reader.into_inner();
+            buffer = transport.0.into_inner();
+        }
+    }
+
+    #[test]
+    fn time_out_whole_ping() {
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        // the server never answers, so neither the modern nor the legacy ping finishes
+        let (mut client, _server) = ::tokio::io::duplex(4096);
+        let task = ping_with_timeout(&mut client, "localhost", 25565, Duration::from_millis(100));
+        match runtime.block_on(task) {
+            Err(Error::Io(error)) => assert_eq!(error.kind(), io::ErrorKind::TimedOut),
+            result => panic!("unexpected result: {:?}", result),
         }
     }
+
+    #[test]
+    fn time_out_stalled_read() {
+        use ::tokio::io::AsyncWriteExt;
+
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let (client, mut server) = ::tokio::io::duplex(4096);
+        let task = async move {
+            // the server stalls after the length of the response
+            server.write_all(&[0x80, 0x01]).await.unwrap();
+            let inactivity = Some(Duration::from_millis(100));
+            let mut stream = TimeoutStream::new(client, inactivity, inactivity);
+            ping_latest(&mut stream, "localhost", 25565).await
+        };
+        match runtime.block_on(task) {
+            Err(Error::Io(error)) => assert_eq!(error.kind(), io::ErrorKind::TimedOut),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn pace_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2);
+        assert_eq!(Duration::ZERO, bucket.take(start));
+        assert_eq!(Duration::ZERO, bucket.take(start));
+        assert_eq!(Duration::from_millis(500), bucket.take(start));
+        assert_eq!(Duration::from_millis(1000), bucket.take(start));
+
+        let later = start + Duration::from_secs(10);
+        assert_eq!(Duration::ZERO, bucket.take(later));
+        assert_eq!(Duration::ZERO, bucket.take(later));
+        assert_eq!(Duration::from_millis(500), bucket.take(later));
+    }
 }
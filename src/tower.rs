@@ -0,0 +1,127 @@
+//! Provides [`PingService`](PingService), a `tower::Service` pinging the [`ServerAddress`](ServerAddress) requested.
+//!
+//! Being a service, the pings can be composed with the standard tower middleware,
+//! e.g. the timeouts, the retries, the rate limits and the load shedding.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use craftping::{tower::PingService, ServerAddress};
+//! use std::time::Duration;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//!
+//! # async fn run() -> Result<(), tower::BoxError> {
+//! let mut service = ServiceBuilder::new()
+//!     .timeout(Duration::from_secs(10))
+//!     .service(PingService::new().connect_timeout(Duration::from_secs(3)));
+//! let response = service
+//!     .ready()
+//!     .await?
+//!     .call(ServerAddress::new("my.server.com", 25565))
+//!     .await?;
+//! println!("{} player(s) online", response.online_players);
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tower_service::Service;
+
+use crate::{pinger::Pinger, Error, Response, Result, ServerAddress};
+
+#[derive(Debug, Clone, Default)]
+/// A service which resolves, connects to and pings the requested server, with the tokio runtime.
+///
+/// Every call connects to the server anew, as [`Pinger::ping_tokio`](Pinger::ping_tokio) does.
+/// The SRV records are looked up if the address has no port, as [`Pinger::from_address`](Pinger::from_address) does.
+/// The service is always ready, leaving the backpressure to the middleware.
+pub struct PingService {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+impl PingService {
+    /// Create a service without any timeout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the timeout of connecting to each resolved address.
+    /// See also [`Pinger::connect_timeout`](Pinger::connect_timeout).
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout of each read from the server.
+    /// See also [`Pinger::read_timeout`](Pinger::read_timeout).
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout of each write to the server.
+    /// See also [`Pinger::write_timeout`](Pinger::write_timeout).
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    fn pinger(&self, address: ServerAddress) -> Pinger {
+        let mut pinger = Pinger::from_address(address);
+        if let Some(timeout) = self.connect_timeout {
+            pinger = pinger.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            pinger = pinger.read_timeout(timeout);
+        }
+        if let Some(timeout) = self.write_timeout {
+            pinger = pinger.write_timeout(timeout);
+        }
+        pinger
+    }
+}
+
+impl Service<ServerAddress> for PingService {
+    type Response = Response;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, address: ServerAddress) -> Self::Future {
+        let pinger = self.pinger(address);
+        Box::pin(async move { pinger.ping_tokio().await })
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod test {
+    use super::*;
+
+    use crate::{
+        testing::{MockServer, Reply},
+        Chat,
+    };
+
+    #[tokio::test]
+    async fn call_service() {
+        let response = Response::new("1.20.4", 765, Chat::from_legacy("Hello"));
+        let server = MockServer::start(Reply::status(response)).unwrap();
+        let mut service = PingService::new().read_timeout(Duration::from_millis(500));
+        std::future::poll_fn(|cx| service.poll_ready(cx))
+            .await
+            .unwrap();
+        let address = ServerAddress::new("127.0.0.1", server.address().port());
+        let response = service.call(address).await.unwrap();
+        assert_eq!(response.version, "1.20.4");
+    }
+}
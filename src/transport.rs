@@ -0,0 +1,642 @@
+//! Provides the [`Transport`](Transport) abstraction used by the connection-owning APIs.
+//!
+//! The [`ping`](crate::sync::ping) function accepts any stream, but the APIs which open connections themselves
+//! (like [`Monitor`](crate::monitor::Monitor)) need to know *how* to open one.
+//! [`TcpTransport`](TcpTransport) is the default, and any other transport (tunnels, port forwards, test doubles)
+//! can be plugged in by implementing [`Transport`](Transport).
+//! [`ProxyTransport`](ProxyTransport) connects through a chain of SOCKS5 and HTTP CONNECT [`Proxy`](Proxy) servers,
+//! which [`Pinger`](crate::pinger::Pinger::proxy) also accepts.
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use crate::{
+    resolve::{DnsCache, Resolver, SystemResolver},
+    Error, IpPreference, Result,
+};
+
+/// Opens a stream to a server, to send a ping request over it.
+///
+/// It is implemented for closures of `FnMut(&str, u16) -> Result<Stream>`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{sync::ping, transport::Transport};
+/// use std::net::TcpStream;
+///
+/// let mut transport = |hostname: &str, port: u16| -> craftping::Result<TcpStream> {
+///     Ok(TcpStream::connect(("127.0.0.1", port))?)
+/// };
+/// let mut stream = transport.connect("my.server.com", 25565).unwrap();
+/// let response = ping(&mut stream, "my.server.com", 25565).unwrap();
+/// ```
+pub trait Transport {
+    /// The stream type this transport opens.
+    type Stream: Read + Write;
+
+    /// Open a stream to the server.
+    fn connect(&mut self, hostname: &str, port: u16) -> Result<Self::Stream>;
+}
+
+impl<F, Stream> Transport for F
+where
+    F: FnMut(&str, u16) -> Result<Stream>,
+    Stream: Read + Write,
+{
+    type Stream = Stream;
+
+    fn connect(&mut self, hostname: &str, port: u16) -> Result<Self::Stream> {
+        self(hostname, port)
+    }
+}
+
+/// The default [`Transport`](Transport), which opens a [`TcpStream`](TcpStream).
+///
+/// It tries every resolved address in the order of the [`IpPreference`](IpPreference),
+/// and can reuse the resolved addresses with a [`DnsCache`](DnsCache).
+/// The hostnames are resolved with the [`Resolver`](Resolver), which is [`SystemResolver`](SystemResolver) by default.
+#[derive(Debug, Clone)]
+pub struct TcpTransport<R = SystemResolver> {
+    timeout: Duration,
+    ip_preference: IpPreference,
+    dns_cache: DnsCache,
+    resolver: R,
+}
+
+impl TcpTransport {
+    /// Create a transport with the connect, read and write timeout.
+    /// The timeout should not be zero.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            ip_preference: IpPreference::Any,
+            dns_cache: DnsCache::new(Duration::ZERO),
+            resolver: SystemResolver::default(),
+        }
+    }
+}
+
+impl<R: Resolver> TcpTransport<R> {
+    /// Set the resolver to resolve the hostnames with.
+    pub fn resolver<S: Resolver>(self, resolver: S) -> TcpTransport<S> {
+        TcpTransport {
+            timeout: self.timeout,
+            ip_preference: self.ip_preference,
+            dns_cache: self.dns_cache,
+            resolver,
+        }
+    }
+
+    /// Set the IP family to connect with.
+    pub fn ip_preference(mut self, ip_preference: IpPreference) -> Self {
+        self.ip_preference = ip_preference;
+        self
+    }
+
+    /// Reuse the resolved addresses for their TTL, up to `ttl`, with a new [`DnsCache`](DnsCache).
+    /// Defaults to zero, which disables the cache.
+    pub fn dns_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.dns_cache = DnsCache::new(ttl);
+        self
+    }
+
+    /// Reuse the resolved addresses of the cache, and cache the new ones.
+    ///
+    /// The cache can be shared with the other transports and [`Pinger`](crate::pinger::Pinger::dns_cache)s.
+    pub fn dns_cache(mut self, cache: DnsCache) -> Self {
+        self.dns_cache = cache;
+        self
+    }
+
+    /// Forget every cached address, so that the hostnames are resolved again on the next connection.
+    ///
+    /// The cached addresses of a server are also forgotten whenever none of them can be connected.
+    pub fn invalidate_dns_cache(&mut self) {
+        self.dns_cache.clear();
+    }
+
+    fn resolve(&mut self, hostname: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let resolved = match hostname.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => self
+                .dns_cache
+                .resolver(&mut self.resolver)
+                .lookup_ip(hostname)?,
+        };
+        let mut addresses: Vec<_> = resolved
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect();
+        self.ip_preference.apply(&mut addresses);
+        if addresses.is_empty() {
+            return Err(Error::Io(io::ErrorKind::NotFound.into()));
+        }
+        Ok(addresses)
+    }
+}
+
+impl<R: Resolver> Transport for TcpTransport<R> {
+    type Stream = TcpStream;
+
+    fn connect(&mut self, hostname: &str, port: u16) -> Result<Self::Stream> {
+        let addresses = self.resolve(hostname, port)?;
+        let mut last_error = io::ErrorKind::NotFound.into();
+        for address in &addresses {
+            match TcpStream::connect_timeout(address, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(stream);
+                }
+                Err(error) => last_error = error,
+            }
+        }
+        // the server may have moved, so resolve again on the next connection
+        self.dns_cache.forget(hostname);
+        Err(last_error.into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A kind of the [`Proxy`](Proxy).
+pub enum ProxyKind {
+    /// A SOCKS5 proxy. The hostname of the next hop is resolved by the proxy.
+    Socks5,
+    /// An HTTP proxy supporting the `CONNECT` method.
+    HttpConnect,
+}
+
+#[derive(Debug, Clone)]
+/// A proxy server, which [`ProxyTransport`](ProxyTransport) and [`Pinger`](crate::pinger::Pinger::proxy) connect through.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{pinger::Pinger, transport::Proxy};
+///
+/// let proxy = Proxy::socks5("127.0.0.1", 1080).credentials("user", "password");
+/// let response = Pinger::new("my.server.com", 25565).proxy(proxy).ping().unwrap();
+/// ```
+pub struct Proxy {
+    /// The kind of the proxy.
+    pub kind: ProxyKind,
+    /// The hostname of the proxy.
+    pub hostname: String,
+    /// The port of the proxy.
+    pub port: u16,
+    /// The username and the password, if the proxy requires authentication.
+    ///
+    /// SOCKS5 proxies are authenticated with the username/password method,
+    /// and HTTP proxies with the basic authentication.
+    pub credentials: Option<(String, String)>,
+}
+
+impl Proxy {
+    /// Create a proxy of the kind at the address, without authentication.
+    pub fn new(kind: ProxyKind, hostname: impl Into<String>, port: u16) -> Self {
+        Self {
+            kind,
+            hostname: hostname.into(),
+            port,
+            credentials: None,
+        }
+    }
+
+    /// Create a SOCKS5 proxy at the address.
+    pub fn socks5(hostname: impl Into<String>, port: u16) -> Self {
+        Self::new(ProxyKind::Socks5, hostname, port)
+    }
+
+    /// Create an HTTP CONNECT proxy at the address.
+    pub fn http_connect(hostname: impl Into<String>, port: u16) -> Self {
+        Self::new(ProxyKind::HttpConnect, hostname, port)
+    }
+
+    /// Authenticate to the proxy with the username and the password.
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+}
+
+/// A [`Transport`](Transport) which connects to the server through a chain of proxies.
+///
+/// The connection is made to the first proxy, then each proxy is asked to connect to the next one,
+/// and the last proxy is asked to connect to the server.
+/// The hostnames of the next hops and the server are resolved by the proxies.
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::{sync::ping, transport::{Proxy, ProxyTransport, Transport}};
+/// use std::time::Duration;
+///
+/// let mut transport = ProxyTransport::new(Duration::from_secs(5))
+///     .proxy(Proxy::socks5("proxy-a.example.com", 1080))
+///     .proxy(Proxy::http_connect("proxy-b.example.com", 3128));
+/// let mut stream = transport.connect("my.server.com", 25565).unwrap();
+/// let response = ping(&mut stream, "my.server.com", 25565).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProxyTransport {
+    tcp: TcpTransport,
+    chain: Vec<Proxy>,
+}
+
+impl ProxyTransport {
+    /// Create a transport without any proxy, with the connect, read and write timeout.
+    ///
+    /// The timeout applies to the connection to the first proxy and to each proxy handshake.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            tcp: TcpTransport::new(timeout),
+            chain: vec![],
+        }
+    }
+
+    /// Append a proxy to the end of the chain.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.chain.push(proxy);
+        self
+    }
+}
+
+impl Transport for ProxyTransport {
+    type Stream = TcpStream;
+
+    fn connect(&mut self, hostname: &str, port: u16) -> Result<Self::Stream> {
+        let first = match self.chain.first() {
+            Some(first) => first,
+            None => return self.tcp.connect(hostname, port),
+        };
+        let mut stream = self.tcp.connect(&first.hostname, first.port)?;
+        for (index, proxy) in self.chain.iter().enumerate() {
+            let (next_hostname, next_port) = match self.chain.get(index + 1) {
+                Some(next) => (next.hostname.as_str(), next.port),
+                None => (hostname, port),
+            };
+            tunnel(&mut stream, proxy, next_hostname, next_port)?;
+        }
+        Ok(stream)
+    }
+}
+
+// the largest response header accepted from an HTTP proxy
+const MAX_HTTP_HEADER_SIZE: usize = 8192;
+
+const SOCKS5_NO_AUTHENTICATION: u8 = 0x00;
+const SOCKS5_USERNAME_PASSWORD: u8 = 0x02;
+
+fn socks5_error(message: &str) -> io::Error {
+    io::Error::other(format!("SOCKS5: {}", message))
+}
+
+fn http_connect_error(message: &str) -> io::Error {
+    io::Error::other(format!("HTTP CONNECT: {}", message))
+}
+
+// Ask the proxy at the other end of the stream to connect to the host
+pub(crate) fn tunnel<Stream>(
+    stream: &mut Stream,
+    proxy: &Proxy,
+    hostname: &str,
+    port: u16,
+) -> io::Result<()>
+where
+    Stream: Read + Write,
+{
+    match proxy.kind {
+        ProxyKind::Socks5 => socks5_handshake(stream, proxy.credentials.as_ref(), hostname, port),
+        ProxyKind::HttpConnect => {
+            stream.write_all(&http_connect_request(proxy, hostname, port))?;
+            stream.flush()?;
+            // read byte by byte not to consume the response of the server
+            let mut header = vec![];
+            let mut byte = [0];
+            while !header.ends_with(b"\r\n\r\n") {
+                if header.len() >= MAX_HTTP_HEADER_SIZE {
+                    return Err(http_connect_error("response header too long"));
+                }
+                stream.read_exact(&mut byte)?;
+                header.push(byte[0]);
+            }
+            http_connect_status(&header)
+        }
+    }
+}
+
+// Ask the proxy at the other end of the stream to connect to the host asynchronously, as `tunnel` does
+#[cfg(feature = "async-tokio")]
+pub(crate) async fn tunnel_tokio<Stream>(
+    stream: &mut Stream,
+    proxy: &Proxy,
+    hostname: &str,
+    port: u16,
+) -> io::Result<()>
+where
+    Stream: ::tokio::io::AsyncRead + ::tokio::io::AsyncWrite + Unpin,
+{
+    use ::tokio::io::AsyncReadExt;
+
+    match proxy.kind {
+        ProxyKind::Socks5 => {
+            socks5_handshake_tokio(stream, proxy.credentials.as_ref(), hostname, port).await
+        }
+        ProxyKind::HttpConnect => {
+            write_all_tokio(stream, &http_connect_request(proxy, hostname, port)).await?;
+            // read byte by byte not to consume the response of the server
+            let mut header = vec![];
+            while !header.ends_with(b"\r\n\r\n") {
+                if header.len() >= MAX_HTTP_HEADER_SIZE {
+                    return Err(http_connect_error("response header too long"));
+                }
+                header.push(stream.read_u8().await?);
+            }
+            http_connect_status(&header)
+        }
+    }
+}
+
+// Ask the SOCKS5 proxy at the other end of the stream to connect to the host (RFC 1928, RFC 1929)
+fn socks5_handshake<Stream>(
+    stream: &mut Stream,
+    credentials: Option<&(String, String)>,
+    hostname: &str,
+    port: u16,
+) -> io::Result<()>
+where
+    Stream: Read + Write,
+{
+    let method = socks5_method(credentials);
+    stream.write_all(&[0x05, 0x01, method])?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [0x05, method] {
+        return Err(socks5_error("authentication method rejected"));
+    }
+
+    if let Some((username, password)) = credentials {
+        stream.write_all(&socks5_auth_request(username, password)?)?;
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0x00 {
+            return Err(socks5_error("authentication failed"));
+        }
+    }
+
+    stream.write_all(&socks5_connect_request(hostname, port)?)?;
+    stream.flush()?;
+
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header)?;
+    let address_length = match socks5_bound_length(header)? {
+        Some(length) => length,
+        None => {
+            let mut length = [0u8];
+            stream.read_exact(&mut length)?;
+            length[0] as usize
+        }
+    };
+    // skip the bound address and port
+    let mut bound = vec![0u8; address_length + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(())
+}
+
+// Ask the SOCKS5 proxy at the other end of the stream to connect to the host asynchronously, as `socks5_handshake` does
+#[cfg(feature = "async-tokio")]
+async fn socks5_handshake_tokio<Stream>(
+    stream: &mut Stream,
+    credentials: Option<&(String, String)>,
+    hostname: &str,
+    port: u16,
+) -> io::Result<()>
+where
+    Stream: ::tokio::io::AsyncRead + ::tokio::io::AsyncWrite + Unpin,
+{
+    use ::tokio::io::AsyncReadExt;
+
+    let method = socks5_method(credentials);
+    write_all_tokio(stream, &[0x05, 0x01, method]).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [0x05, method] {
+        return Err(socks5_error("authentication method rejected"));
+    }
+
+    if let Some((username, password)) = credentials {
+        write_all_tokio(stream, &socks5_auth_request(username, password)?).await?;
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0x00 {
+            return Err(socks5_error("authentication failed"));
+        }
+    }
+
+    write_all_tokio(stream, &socks5_connect_request(hostname, port)?).await?;
+
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let address_length = match socks5_bound_length(header)? {
+        Some(length) => length,
+        None => stream.read_u8().await? as usize,
+    };
+    // skip the bound address and port
+    let mut bound = vec![0u8; address_length + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+#[cfg(feature = "async-tokio")]
+async fn write_all_tokio<Stream>(stream: &mut Stream, buf: &[u8]) -> io::Result<()>
+where
+    Stream: ::tokio::io::AsyncWrite + Unpin,
+{
+    use ::tokio::io::AsyncWriteExt;
+
+    stream.write_all(buf).await?;
+    stream.flush().await
+}
+
+// the authentication method to offer to the SOCKS5 proxy
+fn socks5_method(credentials: Option<&(String, String)>) -> u8 {
+    if credentials.is_some() {
+        SOCKS5_USERNAME_PASSWORD
+    } else {
+        SOCKS5_NO_AUTHENTICATION
+    }
+}
+
+// the username/password authentication request of SOCKS5
+fn socks5_auth_request(username: &str, password: &str) -> io::Result<Vec<u8>> {
+    if username.len() > 255 || password.len() > 255 {
+        return Err(socks5_error("username or password too long"));
+    }
+    let mut request = vec![0x01, username.len() as u8];
+    request.extend_from_slice(username.as_bytes());
+    request.push(password.len() as u8);
+    request.extend_from_slice(password.as_bytes());
+    Ok(request)
+}
+
+// the CONNECT request of SOCKS5, leaving the hostname to be resolved by the proxy
+fn socks5_connect_request(hostname: &str, port: u16) -> io::Result<Vec<u8>> {
+    let mut request = vec![0x05, 0x01, 0x00];
+    match hostname.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(0x01);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(0x04);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if hostname.len() > 255 {
+                return Err(socks5_error("hostname too long"));
+            }
+            request.push(0x03);
+            request.push(hostname.len() as u8);
+            request.extend_from_slice(hostname.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+}
+
+// check the header of the CONNECT reply of SOCKS5, and return the length of the bound address,
+// or `None` if it is a domain name whose length comes next
+fn socks5_bound_length(header: [u8; 4]) -> io::Result<Option<usize>> {
+    if header[0] != 0x05 {
+        return Err(socks5_error("invalid reply"));
+    }
+    if header[1] != 0x00 {
+        return Err(socks5_error(&format!(
+            "connection failed with code {}",
+            header[1]
+        )));
+    }
+    match header[3] {
+        0x01 => Ok(Some(4)),
+        0x04 => Ok(Some(16)),
+        0x03 => Ok(None),
+        _ => Err(socks5_error("invalid address type")),
+    }
+}
+
+// the CONNECT request of HTTP, leaving the hostname to be resolved by the proxy
+fn http_connect_request(proxy: &Proxy, hostname: &str, port: u16) -> Vec<u8> {
+    let authority = match hostname.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+        _ => format!("{}:{}", hostname, port),
+    };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some((username, password)) = &proxy.credentials {
+        let token = STANDARD.encode(format!("{}:{}", username, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    request.into_bytes()
+}
+
+// check the status line of the response of the HTTP proxy
+fn http_connect_status(header: &[u8]) -> io::Result<()> {
+    let status = header
+        .split(|&b| b == b' ')
+        .nth(1)
+        .ok_or_else(|| http_connect_error("invalid response"))?;
+    match status {
+        b"200" => Ok(()),
+        b"407" => Err(http_connect_error("authentication failed")),
+        _ => Err(http_connect_error(&format!(
+            "connection failed with status {}",
+            String::from_utf8_lossy(status)
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Cursor;
+
+    // reads from the prepared input, and records the output
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn socks5_connect() {
+        let mut stream = Duplex {
+            input: Cursor::new(vec![
+                0x05, 0x02, // username/password method selected
+                0x01, 0x00, // authenticated
+                0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x04, 0x38, // connected
+            ]),
+            output: vec![],
+        };
+        let credentials = ("user".to_string(), "pw".to_string());
+        socks5_handshake(&mut stream, Some(&credentials), "mc.example", 25565).unwrap();
+
+        let mut expected = vec![0x05, 0x01, 0x02, 0x01, 4];
+        expected.extend_from_slice(b"user");
+        expected.push(2);
+        expected.extend_from_slice(b"pw");
+        expected.extend_from_slice(&[0x05, 0x01, 0x00, 0x03, 10]);
+        expected.extend_from_slice(b"mc.example");
+        expected.extend_from_slice(&25565u16.to_be_bytes());
+        assert_eq!(stream.output, expected);
+    }
+
+    #[test]
+    fn http_connect() {
+        let proxy = Proxy::http_connect("127.0.0.1", 8080).credentials("user", "pass");
+        let mut stream = Duplex {
+            input: Cursor::new(b"HTTP/1.1 200 Connection established\r\n\r\n\x00".to_vec()),
+            output: vec![],
+        };
+        tunnel(&mut stream, &proxy, "my.server.com", 25565).unwrap();
+        assert_eq!(
+            stream.output,
+            b"CONNECT my.server.com:25565 HTTP/1.1\r\nHost: my.server.com:25565\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"
+        );
+        // the response of the server after the header is left unread
+        assert_eq!(stream.input.position(), 39);
+        assert!(
+            http_connect_status(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").is_err()
+        );
+    }
+
+    #[test]
+    fn apply_ip_preference() {
+        let v4: SocketAddr = "127.0.0.1:25565".parse().unwrap();
+        let v6: SocketAddr = "[::1]:25565".parse().unwrap();
+
+        let mut addresses = vec![v4, v6];
+        IpPreference::PreferV6.apply(&mut addresses);
+        assert_eq!(addresses, [v6, v4]);
+
+        IpPreference::V4Only.apply(&mut addresses);
+        assert_eq!(addresses, [v4]);
+    }
+}
@@ -0,0 +1,82 @@
+//! Provides asynchronous [`ping`](ping) function on io_uring, with the tokio-uring runtime.
+//!
+//! The completion-based I/O of io_uring takes the ownership of the buffers while reading or writing,
+//! so it does not fit the `AsyncRead` and `AsyncWrite` traits of the [`tokio`](crate::tokio) module.
+//! The functions here pass the owned buffers to [`TcpStream`](TcpStream) instead, reading the response in chunks.
+//!
+//! Only available on Linux.
+use std::io;
+
+use tokio_uring::net::TcpStream;
+
+use crate::{completion::OwnedIo, *};
+
+/// Send a ping request to the server and return a future response.
+///
+/// See also [`Response`](Response).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::uring::ping;
+/// use tokio_uring::net::TcpStream;
+///
+/// tokio_uring::start(async {
+///     let address = "127.0.0.1:25565".parse().unwrap();
+///     let stream = TcpStream::connect(address).await.unwrap();
+///     let response = ping(&stream, "127.0.0.1", 25565).await.unwrap();
+///     println!("Players online: {}", response.online_players);
+/// });
+/// ```
+pub async fn ping(stream: &TcpStream, hostname: &str, port: u16) -> Result<Response> {
+    ping_with_config(stream, hostname, port, &PingConfig::default()).await
+}
+
+/// Send a ping request to the server with the options and return a future response.
+///
+/// See also [`PingConfig`](PingConfig).
+pub async fn ping_with_config(
+    stream: &TcpStream,
+    hostname: &str,
+    port: u16,
+    config: &PingConfig,
+) -> Result<Response> {
+    completion::ping_with_config(&mut UringStream(stream), hostname, port, config).await
+}
+
+/// Send a ping request of the current protocol (1.7 and later) to the server and return a future response.
+///
+/// Unlike [`ping`](ping), it does not fall back to the legacy protocol.
+pub async fn ping_latest(stream: &TcpStream, hostname: &str, port: u16) -> Result<Response> {
+    completion::request_latest(
+        &mut UringStream(stream),
+        hostname,
+        port,
+        &PingConfig::default(),
+    )
+    .await
+}
+
+/// Send a ping request of the legacy protocol (1.6) to the server and return a future response.
+///
+/// `hostname` and `port` are sent to the server as the 1.6 client does. Some proxies reject the request without them.
+pub async fn ping_legacy(stream: &TcpStream, hostname: &str, port: u16) -> Result<Response> {
+    completion::request_legacy(
+        &mut UringStream(stream),
+        build_legacy_request(hostname, port, &PingConfig::default()),
+        &Limits::default(),
+    )
+    .await
+}
+
+struct UringStream<'a>(&'a TcpStream);
+
+impl OwnedIo for UringStream<'_> {
+    async fn read(&mut self, buffer: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+        self.0.read(buffer).await
+    }
+
+    async fn write_all(&mut self, buffer: Vec<u8>) -> (io::Result<()>, Vec<u8>) {
+        self.0.write_all(buffer).await
+    }
+}
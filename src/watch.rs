@@ -0,0 +1,495 @@
+//! Provides a [`Watcher`](Watcher) which pings a server periodically.
+//!
+//! The [`Watcher`](Watcher) connects to the server again for every ping, so a restarted server is picked up by itself.
+//! A failed ping is yielded as an error, and the watch goes on with the next one.
+//! The updates are available as a blocking [`Iterator`](Iterator) with [`Watcher::iter`](Watcher::iter),
+//! or as a [`Stream`](futures::Stream) with [`Watcher::stream`](Watcher::stream) when `async-tokio` feature is enabled.
+//! To react to the changes rather than the statuses, see [`Watcher::events`](Watcher::events).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use craftping::{pinger::Pinger, watch::Watcher};
+//! use std::time::Duration;
+//!
+//! let pinger = Pinger::new("my.server.com", 25565).connect_timeout(Duration::from_secs(3));
+//! for update in Watcher::new(pinger, Duration::from_secs(30)).iter() {
+//!     match update {
+//!         Ok(response) => println!("{} player(s) online", response.online_players),
+//!         Err(error) => println!("offline: {}", error),
+//!     }
+//! }
+//! ```
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{pinger::Pinger, sink::StatusSink, CancelToken, Chat, Player, Response, Result};
+
+#[derive(Debug, Clone)]
+/// Pings a server at an interval, yielding every result.
+///
+/// The first ping is sent immediately. The interval is measured from the start of each ping,
+/// and the next ping is sent immediately if a ping takes longer than the interval.
+pub struct Watcher {
+    pinger: Pinger,
+    interval: Duration,
+}
+
+impl Watcher {
+    /// Create a watcher which pings with the pinger at the interval.
+    pub fn new(pinger: Pinger, interval: Duration) -> Self {
+        Self { pinger, interval }
+    }
+
+    /// The pinger used for every ping.
+    pub fn pinger(&self) -> &Pinger {
+        &self.pinger
+    }
+
+    /// The interval between the pings.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Iterate over the results of the pings, blocking the thread until the next one.
+    ///
+    /// The iterator never ends.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            watcher: self,
+            schedule: Schedule::new(self.interval),
+            token: None,
+        }
+    }
+
+    /// Iterate over the results of the pings until the token is cancelled.
+    ///
+    /// The ping in flight is finished and yielded, and the iterator ends without starting another.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::{pinger::Pinger, watch::Watcher, CancelToken};
+    /// use std::time::Duration;
+    ///
+    /// let watcher = Watcher::new(Pinger::new("my.server.com", 25565), Duration::from_secs(30));
+    /// let token = CancelToken::new();
+    /// let handle = token.clone();
+    /// std::thread::spawn(move || {
+    ///     std::thread::sleep(Duration::from_secs(600));
+    ///     handle.cancel();
+    /// });
+    /// for update in watcher.iter_until(&token) {
+    ///     println!("{:?}", update.map(|response| response.online_players));
+    /// }
+    /// ```
+    pub fn iter_until(&self, token: &CancelToken) -> Iter<'_> {
+        Iter {
+            watcher: self,
+            schedule: Schedule::new(self.interval),
+            token: Some(token.clone()),
+        }
+    }
+
+    /// Return a stream of the results of the pings, with the tokio runtime.
+    ///
+    /// The stream never ends.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::{pinger::Pinger, watch::Watcher};
+    /// use futures::StreamExt;
+    /// use std::time::Duration;
+    ///
+    /// # async fn run() {
+    /// let watcher = Watcher::new(Pinger::new("my.server.com", 25565), Duration::from_secs(30));
+    /// let mut updates = Box::pin(watcher.stream());
+    /// while let Some(update) = updates.next().await {
+    ///     println!("{:?}", update.map(|response| response.online_players));
+    /// }
+    /// # }
+    /// ```
+    #[cfg(feature = "async-tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
+    pub fn stream(&self) -> impl ::futures::Stream<Item = Result<Response>> + Send + 'static {
+        self.stream_until(CancelToken::new())
+    }
+
+    /// Return a stream of the results of the pings until the token is cancelled, with the tokio runtime.
+    ///
+    /// The ping in flight is finished and yielded, and the stream ends without starting another.
+    #[cfg(feature = "async-tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
+    pub fn stream_until(
+        &self,
+        token: CancelToken,
+    ) -> impl ::futures::Stream<Item = Result<Response>> + Send + 'static {
+        let pinger = self.pinger.clone();
+        ::futures::stream::unfold(Schedule::new(self.interval), move |mut schedule| {
+            let pinger = pinger.clone();
+            let token = token.clone();
+            async move {
+                let next_ping = ::tokio::time::Instant::from_std(schedule.next_ping());
+                let sleep = std::pin::pin!(::tokio::time::sleep_until(next_ping));
+                ::futures::future::select(sleep, token.cancelled()).await;
+                if token.is_cancelled() {
+                    return None;
+                }
+                schedule.start();
+                let result = pinger.ping_tokio().await;
+                Some((result, schedule))
+            }
+        })
+    }
+
+    /// Ping forever, recording every result into the sink, named by `hostname:port`.
+    ///
+    /// It returns only when the sink fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::{pinger::Pinger, sink::CsvSink, watch::Watcher};
+    /// use std::{fs::File, time::Duration};
+    ///
+    /// let watcher = Watcher::new(Pinger::new("my.server.com", 25565), Duration::from_secs(60));
+    /// let error = watcher.record_into(CsvSink::new(File::create("history.csv").unwrap()));
+    /// ```
+    pub fn record_into(&self, sink: impl StatusSink) -> Result<()> {
+        self.record_updates(self.iter(), sink)
+    }
+
+    /// Ping until the token is cancelled, recording every result into the sink, named by `hostname:port`.
+    ///
+    /// The ping in flight is finished and recorded, and then the sink is flushed before this returns.
+    /// This also returns when the sink fails.
+    pub fn record_into_until(&self, sink: impl StatusSink, token: &CancelToken) -> Result<()> {
+        self.record_updates(self.iter_until(token), sink)
+    }
+
+    fn record_updates(&self, updates: Iter<'_>, mut sink: impl StatusSink) -> Result<()> {
+        let server = format!("{}:{}", self.pinger.hostname(), self.pinger.port());
+        for update in updates {
+            sink.record(&server, &update)?;
+        }
+        sink.flush()
+    }
+
+    /// Iterate over the changes of the server, blocking the thread until the next one.
+    ///
+    /// See also [`EventTracker`](EventTracker).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use craftping::{pinger::Pinger, watch::{Event, Watcher}};
+    /// use std::time::Duration;
+    ///
+    /// let watcher = Watcher::new(Pinger::new("my.server.com", 25565), Duration::from_secs(30));
+    /// for event in watcher.events() {
+    ///     if let Event::PlayerJoined(player) = event {
+    ///         println!("{} joined", player.name);
+    ///     }
+    /// }
+    /// ```
+    pub fn events(&self) -> Events<'_> {
+        Events {
+            updates: self.iter(),
+            tracker: EventTracker::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Return a stream of the changes of the server, with the tokio runtime.
+    ///
+    /// See also [`EventTracker`](EventTracker).
+    #[cfg(feature = "async-tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
+    pub fn event_stream(&self) -> impl ::futures::Stream<Item = Event> + Send + 'static {
+        use ::futures::StreamExt;
+
+        let mut tracker = EventTracker::new();
+        self.stream()
+            .flat_map(move |update| ::futures::stream::iter(tracker.update(&update)))
+    }
+}
+
+// when the next ping is due, measured from the start of the previous one
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Schedule {
+    interval: Duration,
+    next_ping: Instant,
+}
+
+impl Schedule {
+    // a schedule whose first ping is due now
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_ping: Instant::now(),
+        }
+    }
+
+    pub(crate) fn next_ping(&self) -> Instant {
+        self.next_ping
+    }
+
+    // start a ping now, and schedule the next one after the interval
+    pub(crate) fn start(&mut self) {
+        let now = Instant::now();
+        // an interval too long to represent postpones the next ping as far as possible
+        self.next_ping = now
+            .checked_add(self.interval)
+            .unwrap_or_else(|| far_future(now));
+    }
+}
+
+// the latest instant after `now` which can be represented, found by halving the step
+fn far_future(now: Instant) -> Instant {
+    let mut far = now;
+    let mut step = Duration::MAX;
+    while !step.is_zero() {
+        match far.checked_add(step) {
+            Some(later) => far = later,
+            None => step /= 2,
+        }
+    }
+    far
+}
+
+impl<'a> IntoIterator for &'a Watcher {
+    type Item = Result<Response>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[derive(Debug)]
+/// The blocking iterator of [`Watcher`](Watcher), created by [`Watcher::iter`](Watcher::iter).
+pub struct Iter<'a> {
+    watcher: &'a Watcher,
+    schedule: Schedule,
+    token: Option<CancelToken>,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Result<Response>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let wait = self
+            .schedule
+            .next_ping()
+            .saturating_duration_since(Instant::now());
+        match &self.token {
+            Some(token) if token.wait_timeout(wait) => return None,
+            Some(_) => {}
+            None => std::thread::sleep(wait),
+        }
+        self.schedule.start();
+        Some(self.watcher.pinger.ping())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+/// A change of the server between two consecutive pings.
+///
+/// See also [`Notifier`](crate::monitor::Notifier), which receives them from [`Monitor`](crate::monitor::Monitor).
+pub enum Event {
+    /// The server answered after failing, or for the first time.
+    CameOnline,
+    /// The server failed to answer after answering, or for the first time.
+    WentOffline,
+    /// The player appeared in the sample.
+    PlayerJoined(Player),
+    /// The player disappeared from the sample.
+    PlayerLeft(Player),
+    /// The description (aka MOTD) changed.
+    MotdChanged {
+        /// The previous description.
+        old: Chat,
+        /// The current description.
+        new: Chat,
+    },
+    /// The version name or the protocol number changed.
+    VersionChanged {
+        /// The previous version name.
+        old: String,
+        /// The current version name.
+        new: String,
+        /// The previous protocol number.
+        old_protocol: i32,
+        /// The current protocol number.
+        new_protocol: i32,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+/// Computes the [`Event`](Event)s by comparing each ping result with the previous one.
+///
+/// The changes are computed with [`Response::diff`](Response::diff), so only the players in the samples are reported.
+/// Large servers send a part of the players as the sample, and the players moving in and out of it are reported as well.
+/// The players, the description and the version are compared only between two successful pings.
+pub struct EventTracker {
+    last: Option<Response>,
+    online: Option<bool>,
+}
+
+impl EventTracker {
+    /// Create a tracker which has seen no pings yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare the result with the previous one, and return the changes in between.
+    pub fn update(&mut self, result: &Result<Response>) -> Vec<Event> {
+        let mut events = Vec::new();
+        match result {
+            Ok(response) => {
+                if self.online != Some(true) {
+                    events.push(Event::CameOnline);
+                }
+                if let Some(last) = &self.last {
+                    diff(last, response, &mut events);
+                }
+                self.online = Some(true);
+                self.last = Some(response.clone());
+            }
+            Err(_) => {
+                if self.online != Some(false) {
+                    events.push(Event::WentOffline);
+                }
+                self.online = Some(false);
+            }
+        }
+        events
+    }
+}
+
+fn diff(old: &Response, new: &Response, events: &mut Vec<Event>) {
+    let diff = old.diff(new);
+    events.extend(diff.joined.into_iter().map(Event::PlayerJoined));
+    events.extend(diff.left.into_iter().map(Event::PlayerLeft));
+    if let Some(description) = diff.description {
+        events.push(Event::MotdChanged {
+            old: old.description.clone(),
+            new: description,
+        });
+    }
+    if diff.version_changed {
+        events.push(Event::VersionChanged {
+            old: old.version.clone(),
+            new: new.version.clone(),
+            old_protocol: old.protocol,
+            new_protocol: new.protocol,
+        });
+    }
+}
+
+#[derive(Debug)]
+/// The blocking iterator of the changes, created by [`Watcher::events`](Watcher::events).
+pub struct Events<'a> {
+    updates: Iter<'a>,
+    tracker: EventTracker,
+    pending: VecDeque<Event>,
+}
+
+impl Iterator for Events<'_> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let update = self.updates.next()?;
+            self.pending.extend(self.tracker.update(&update));
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{entity::RawLatest, Error};
+
+    fn response(players: &str, description: &str) -> Result<Response> {
+        let raw = format!(
+            r#"{{"version":{{"name":"1.20.4","protocol":765}},"players":{{"max":20,"online":1,"sample":[{}]}},"description":"{}"}}"#,
+            players, description,
+        );
+        let latest: RawLatest = serde_json::from_str(&raw).unwrap();
+        Response::try_from(latest)
+    }
+
+    #[test]
+    fn track_events() {
+        let alice = r#"{"name":"Alice","id":"a"}"#;
+        let bob = r#"{"name":"Bob","id":"b"}"#;
+        let mut tracker = EventTracker::new();
+
+        assert_eq!(
+            tracker.update(&response(alice, "A")),
+            vec![Event::CameOnline]
+        );
+        let events = tracker.update(&response(bob, "B"));
+        assert!(matches!(&events[0], Event::PlayerJoined(player) if player.name == "Bob"));
+        assert!(matches!(&events[1], Event::PlayerLeft(player) if player.name == "Alice"));
+        assert!(matches!(&events[2], Event::MotdChanged { new, .. } if new.text == "B"));
+        assert_eq!(events.len(), 3);
+
+        // a proxy can switch the protocol behind the same version name
+        let mut upgraded = response(bob, "B").unwrap();
+        upgraded.protocol = 766;
+        assert_eq!(
+            tracker.update(&Ok(upgraded.clone())),
+            vec![Event::VersionChanged {
+                old: "1.20.4".to_string(),
+                new: "1.20.4".to_string(),
+                old_protocol: 765,
+                new_protocol: 766,
+            }]
+        );
+
+        assert_eq!(
+            tracker.update(&Err(Error::UnsupportedProtocol)),
+            vec![Event::WentOffline]
+        );
+        assert_eq!(tracker.update(&Err(Error::UnsupportedProtocol)), vec![]);
+        assert_eq!(tracker.update(&Ok(upgraded)), vec![Event::CameOnline]);
+    }
+
+    #[test]
+    fn stop_when_cancelled() {
+        // nothing listens on the port just released, so every ping fails at once
+        let closed = std::net::TcpListener::bind(("127.0.0.1", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let watcher = Watcher::new(
+            Pinger::new("127.0.0.1", closed.port()),
+            Duration::from_secs(60),
+        );
+        let token = CancelToken::new();
+        let handle = token.clone();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle.cancel();
+        });
+        let started = Instant::now();
+        assert_eq!(watcher.iter_until(&token).count(), 1);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        canceller.join().unwrap();
+        assert_eq!(watcher.iter_until(&token).count(), 0);
+    }
+
+    #[test]
+    fn schedule_far_future() {
+        let mut schedule = Schedule::new(Duration::MAX);
+        schedule.start();
+        assert!(schedule.next_ping() > Instant::now() + Duration::from_secs(86400 * 365));
+    }
+}
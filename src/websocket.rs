@@ -0,0 +1,145 @@
+//! Provides [`WsStream`](WsStream) to ping a server over a WebSocket, with the tokio runtime.
+//!
+//! Some hosting panels and the bridges for browser clients (e.g. Eaglercraft) expose the Server List Ping
+//! over a WebSocket, carrying the packets in the binary messages.
+//! [`WsStream`](WsStream) converts the messages into a byte stream, so it can be passed to the
+//! [`tokio`](crate::tokio) functions like a TCP stream.
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use ::futures::{Sink, Stream};
+use ::tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+use tokio_tungstenite::{
+    tungstenite::{Bytes, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{Response, Result};
+
+/// A byte stream over a WebSocket.
+///
+/// Every flush of the written bytes is sent as a binary message,
+/// and the payloads of the received binary and text messages are read in order.
+/// A close message is read as the end of the stream.
+pub struct WsStream<S> {
+    socket: WebSocketStream<S>,
+    // the rest of the last received message
+    read_buffer: Bytes,
+    write_buffer: Vec<u8>,
+}
+
+impl<S> WsStream<S> {
+    /// Wrap a connected WebSocket.
+    pub fn new(socket: WebSocketStream<S>) -> Self {
+        Self {
+            socket,
+            read_buffer: Bytes::new(),
+            write_buffer: Vec::new(),
+        }
+    }
+
+    /// Return the WebSocket, discarding the bytes read but not consumed yet.
+    pub fn into_inner(self) -> WebSocketStream<S> {
+        self.socket
+    }
+}
+
+impl<S> AsyncRead for WsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.read_buffer.is_empty() {
+            match ready!(Pin::new(&mut this.socket).poll_next(cx)) {
+                Some(Ok(message @ (Message::Binary(_) | Message::Text(_)))) => {
+                    this.read_buffer = message.into_data();
+                }
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                // the pings are answered by the socket itself
+                Some(Ok(_)) => {}
+                Some(Err(error)) => return Poll::Ready(Err(io::Error::other(error))),
+            }
+        }
+        let length = this.read_buffer.len().min(buf.remaining());
+        buf.put_slice(&this.read_buffer[..length]);
+        this.read_buffer = this.read_buffer.slice(length..);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for WsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().write_buffer.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let mut socket = Pin::new(&mut this.socket);
+        if !this.write_buffer.is_empty() {
+            ready!(socket.as_mut().poll_ready(cx)).map_err(io::Error::other)?;
+            let message = Message::Binary(std::mem::take(&mut this.write_buffer).into());
+            socket
+                .as_mut()
+                .start_send(message)
+                .map_err(io::Error::other)?;
+        }
+        socket.poll_flush(cx).map_err(io::Error::other)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.get_mut().socket)
+            .poll_close(cx)
+            .map_err(io::Error::other)
+    }
+}
+
+/// Connect to the WebSocket at the URL, e.g. `ws://my.server.com:8080`.
+///
+/// Only `ws://` URLs are supported, as no TLS backend is enabled for the WebSocket.
+pub async fn connect(url: &str) -> Result<WsStream<MaybeTlsStream<TcpStream>>> {
+    let (socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(io::Error::other)?;
+    Ok(WsStream::new(socket))
+}
+
+/// Connect to the WebSocket at the URL, and send a ping request to the server over it.
+///
+/// The hostname and the port are sent in the handshake, as in [`tokio::ping`](crate::tokio::ping).
+///
+/// # Examples
+///
+/// ```no_run
+/// use craftping::websocket::ping;
+///
+/// # async fn run() {
+/// let response = ping("ws://my.server.com:8080", "my.server.com", 25565)
+///     .await
+///     .unwrap();
+/// println!("Players online: {}", response.online_players);
+/// # }
+/// ```
+pub async fn ping(url: &str, hostname: &str, port: u16) -> Result<Response> {
+    let mut stream = connect(url).await?;
+    crate::tokio::ping(&mut stream, hostname, port).await
+}